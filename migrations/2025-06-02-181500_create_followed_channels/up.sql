@@ -0,0 +1,13 @@
+CREATE TABLE followed_channels (
+    channel_id VARCHAR(255) PRIMARY KEY,
+    url VARCHAR(255) NOT NULL,
+    created_at TIMESTAMP DEFAULT CURRENT_TIMESTAMP
+);
+
+INSERT INTO followed_channels (channel_id, url) VALUES
+    ('onthebrink', 'https://warpcast.com/~/channel/onthebrink'),
+    ('piratewires', 'https://warpcast.com/~/channel/piratewires'),
+    ('moz', 'https://warpcast.com/~/channel/moz'),
+    ('networktimes', 'https://warpcast.com/~/channel/networktimes'),
+    ('gray', 'https://warpcast.com/~/channel/gray'),
+    ('all-in', 'https://warpcast.com/~/channel/all-in');
@@ -0,0 +1,3 @@
+DROP TABLE user_data;
+DROP TABLE reactions;
+DROP TABLE casts;
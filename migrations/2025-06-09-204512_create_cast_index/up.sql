@@ -0,0 +1,34 @@
+CREATE TABLE casts (
+    hash VARCHAR(255) PRIMARY KEY,
+    fid BIGINT NOT NULL,
+    channel_id VARCHAR(255),
+    parent_url TEXT,
+    parent_cast_hash VARCHAR(255),
+    text TEXT,
+    timestamp BIGINT NOT NULL,
+    raw JSONB NOT NULL,
+    indexed_at TIMESTAMP NOT NULL DEFAULT CURRENT_TIMESTAMP
+);
+
+CREATE INDEX casts_channel_timestamp_idx ON casts (channel_id, timestamp DESC);
+CREATE INDEX casts_fid_timestamp_idx ON casts (fid, timestamp DESC);
+
+CREATE TABLE reactions (
+    fid BIGINT NOT NULL,
+    reaction_type VARCHAR(64) NOT NULL,
+    target_fid BIGINT NOT NULL,
+    target_hash VARCHAR(255) NOT NULL,
+    timestamp BIGINT NOT NULL,
+    PRIMARY KEY (fid, target_hash, reaction_type)
+);
+
+CREATE INDEX reactions_target_hash_idx ON reactions (target_hash);
+
+CREATE TABLE user_data (
+    fid BIGINT NOT NULL,
+    data_type VARCHAR(64) NOT NULL,
+    value TEXT NOT NULL,
+    timestamp BIGINT NOT NULL,
+    updated_at TIMESTAMP NOT NULL DEFAULT CURRENT_TIMESTAMP,
+    PRIMARY KEY (fid, data_type)
+);
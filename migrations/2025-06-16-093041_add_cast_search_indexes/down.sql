@@ -0,0 +1 @@
+DROP INDEX casts_text_search_idx;
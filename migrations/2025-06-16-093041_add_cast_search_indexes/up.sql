@@ -0,0 +1 @@
+CREATE INDEX casts_text_search_idx ON casts USING GIN (to_tsvector('english', coalesce(text, '')));
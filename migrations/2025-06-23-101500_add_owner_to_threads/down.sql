@@ -0,0 +1,4 @@
+DROP INDEX IF EXISTS threads_owner_created_at_idx;
+
+ALTER TABLE threads
+DROP COLUMN owner;
@@ -0,0 +1,10 @@
+-- threads belong to the fid that created them. existing threads go to fid 0,
+-- which nobody can sign in as; hand them to a real account with
+--   UPDATE threads SET owner = <fid> WHERE owner = 0;
+ALTER TABLE threads
+ADD COLUMN owner BIGINT NOT NULL DEFAULT 0;
+
+ALTER TABLE threads
+ALTER COLUMN owner DROP DEFAULT;
+
+CREATE INDEX threads_owner_created_at_idx ON threads (owner, created_at DESC);
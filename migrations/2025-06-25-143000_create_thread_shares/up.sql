@@ -0,0 +1,9 @@
+CREATE TABLE thread_shares (
+    token VARCHAR(64) PRIMARY KEY,
+    thread_id VARCHAR(255) NOT NULL REFERENCES threads(id) ON DELETE CASCADE,
+    created_at TIMESTAMP NOT NULL DEFAULT CURRENT_TIMESTAMP,
+    expires_at TIMESTAMP,
+    revoked_at TIMESTAMP
+);
+
+CREATE INDEX thread_shares_thread_id_idx ON thread_shares (thread_id);
@@ -0,0 +1,11 @@
+CREATE TABLE user_settings (
+    fid BIGINT PRIMARY KEY,
+    theme VARCHAR(16) NOT NULL DEFAULT 'system',
+    default_model VARCHAR(64) NOT NULL DEFAULT 'gpt-4o-mini',
+    default_lab VARCHAR(32) NOT NULL DEFAULT 'openai',
+    default_channel VARCHAR(255),
+    notify_mentions BOOLEAN NOT NULL DEFAULT FALSE,
+    notify_replies BOOLEAN NOT NULL DEFAULT FALSE,
+    title_provider VARCHAR(32) NOT NULL DEFAULT 'openai',
+    updated_at TIMESTAMP NOT NULL DEFAULT CURRENT_TIMESTAMP
+);
@@ -7,8 +7,13 @@ use crate::pages::home::Home;
 use crate::pages::hometest::HomeTest;
 use crate::components::navbar::Navbar;
 use crate::components::profile::Profile;
+use crate::components::search::Search;
+use crate::components::auth::provide_session;
 use crate::components::cache_provider::provide_client_cache;
+use crate::components::preferences::provide_preferences;
+use crate::components::clock::provide_clock;
 use crate::pages::settings::Settings;
+use crate::pages::share::SharedThread;
 use crate::pages::writersroom::WritersRoom;
 use crate::pages::codedemo::CodeDemo;
 use crate::pages::mermaiddemo::MermaidDemo;
@@ -17,6 +22,15 @@ use crate::pages::mermaiddemo::MermaidDemo;
 pub fn App() -> impl IntoView {
     // provide the client cache through context
     provide_client_cache();
+
+    // who's signed in, if anyone
+    provide_session();
+
+    // their settings, and the theme to paint with before those load
+    let preferences = provide_preferences();
+
+    // shared "now" for relative cast times
+    provide_clock();
     
     // Provides context that manages stylesheets, titles, meta tags, etc.
     provide_meta_context();
@@ -85,13 +99,16 @@ pub fn App() -> impl IntoView {
         // content for this welcome page
         <Router fallback=|| {
             let mut outside_errors = Errors::default();
-            outside_errors.insert_with_default_key(AppError::NotFound);
+            outside_errors.insert_with_default_key(AppError::NotFound("page".to_string()));
             view! {
                 <ErrorTemplate outside_errors/>
             }
             .into_view()
         }>
-            <body class="bg-white dark:bg-catppuccin-base text-gray-900 dark:text-catppuccin-text">
+            <body class=move || format!(
+                "bg-white dark:bg-catppuccin-base text-gray-900 dark:text-catppuccin-text{}",
+                if preferences.is_dark() { " dark" } else { "" },
+            )>
             <nav>
                 <Navbar/>
             </nav>
@@ -102,6 +119,9 @@ pub fn App() -> impl IntoView {
                     <Route path="writersroom" view=WritersRoom/>
                     <Route path="settings" view=Settings/>
                     <Route path="profile/:id" view=Profile/>
+                    <Route path="search" view=Search/>
+                    // async so the og tags are in the head before anything is sent
+                    <Route path="share/:token" view=SharedThread ssr=SsrMode::Async/>
                     <Route path="codedemo" view=CodeDemo/>
                     <Route path="mermaiddemo" view=MermaidDemo/>
                 </Routes>
@@ -0,0 +1,257 @@
+use cfg_if::cfg_if;
+use leptos::*;
+use crate::models::auth::{SessionUser, SiwfChallenge};
+
+cfg_if! {
+    if #[cfg(feature = "ssr")] {
+        use crate::config::Config;
+        use crate::error_template::AppError;
+
+        fn hub_url(config: &Config) -> Result<String, AppError> {
+            config
+                .hubble_url
+                .as_ref()
+                .map(|url| format!("{}:2281", url))
+                .ok_or_else(|| AppError::Upstream("HUBBLE_URL isn't set, sign in is off".to_string()))
+        }
+
+        fn not_custody(address: &str) -> AppError {
+            AppError::Validation(format!("{} is not the custody address of a farcaster account", address))
+        }
+
+        fn set_cookie(cookie: String) {
+            use http::header::{HeaderValue, SET_COOKIE};
+            if let Some(response) = use_context::<leptos_axum::ResponseOptions>() {
+                if let Ok(value) = HeaderValue::from_str(&cookie) {
+                    response.append_header(SET_COOKIE, value);
+                }
+            }
+        }
+    }
+}
+
+cfg_if! {
+    if #[cfg(target_arch = "wasm32")] {
+        use js_sys::{Array, Function, Object, Promise, Reflect};
+        use wasm_bindgen::{JsCast, JsValue};
+        use wasm_bindgen_futures::JsFuture;
+
+        // EIP-1193: whatever wallet the browser injected as `window.ethereum`
+        async fn ethereum_request(method: &str, params: Array) -> Result<JsValue, String> {
+            let window = web_sys::window().ok_or("no window")?;
+            let ethereum = Reflect::get(&window, &"ethereum".into()).map_err(|_| "no ethereum wallet found")?;
+            if ethereum.is_undefined() {
+                return Err("no ethereum wallet found, install one that holds your farcaster custody address".to_string());
+            }
+            let request = Reflect::get(&ethereum, &"request".into())
+                .ok()
+                .and_then(|request| request.dyn_into::<Function>().ok())
+                .ok_or("wallet doesn't support eip-1193 requests")?;
+
+            let args = Object::new();
+            Reflect::set(&args, &"method".into(), &method.into()).map_err(|e| format!("{:?}", e))?;
+            Reflect::set(&args, &"params".into(), &params).map_err(|e| format!("{:?}", e))?;
+
+            let promise = request
+                .call1(&ethereum, &args)
+                .map_err(|e| format!("{:?}", e))?
+                .dyn_into::<Promise>()
+                .map_err(|_| "wallet didn't return a promise")?;
+            JsFuture::from(promise).await.map_err(|e| {
+                Reflect::get(&e, &"message".into())
+                    .ok()
+                    .and_then(|message| message.as_string())
+                    .unwrap_or_else(|| format!("{:?}", e))
+            })
+        }
+
+        async fn wallet_sign_in() -> Result<SessionUser, String> {
+            let accounts = ethereum_request("eth_requestAccounts", Array::new()).await?;
+            let address = Array::from(&accounts)
+                .get(0)
+                .as_string()
+                .ok_or("wallet didn't share an account")?;
+
+            let challenge = get_siwf_challenge(address.clone()).await.map_err(|e| e.to_string())?;
+
+            // personal_sign wants the message hex encoded
+            let message_hex = format!("0x{}", challenge.message.bytes().map(|b| format!("{:02x}", b)).collect::<String>());
+            let signature = ethereum_request("personal_sign", Array::of2(&message_hex.into(), &address.into()))
+                .await?
+                .as_string()
+                .ok_or("wallet returned an unexpected signature")?;
+
+            sign_in_with_farcaster(challenge.message, signature).await.map_err(|e| e.to_string())
+        }
+    } else {
+        async fn wallet_sign_in() -> Result<SessionUser, String> {
+            Err("signing in is only supported in the browser".into())
+        }
+    }
+}
+
+/// Mints a nonce and builds the sign in message for the fid `address` is custody of.
+#[server(GetSiwfChallenge, "/api")]
+pub async fn get_siwf_challenge(address: String) -> Result<SiwfChallenge, ServerFnError> {
+    use chrono::Utc;
+    use crate::services::auth::{custody_fid, issue_nonce, SiwfMessage, SiwfSettings};
+    use crate::state::AppState;
+
+    let app_state = use_context::<AppState>()
+        .expect("failed to get AppState from context");
+
+    let client = reqwest::Client::new();
+    let fid = custody_fid(&client, &hub_url(&app_state.config)?, &address)
+        .await
+        .map_err(|e| AppError::Upstream(format!("hub error: {}", e)))?
+        .ok_or_else(|| not_custody(&address))?;
+
+    let nonce = issue_nonce(&app_state.cache)
+        .await
+        .map_err(|e| AppError::Internal(format!("session error: {}", e)))?;
+
+    let settings = SiwfSettings::from_config(&app_state.config, &app_state.leptos_options.site_addr.to_string());
+    let message = SiwfMessage::new(&settings, &address, fid, &nonce, Utc::now());
+
+    Ok(SiwfChallenge { message: message.to_string(), nonce })
+}
+
+/// Verifies a signed SIWF message and starts a session for its fid.
+#[server(SignInWithFarcaster, "/api")]
+pub async fn sign_in_with_farcaster(message: String, signature: String) -> Result<SessionUser, ServerFnError> {
+    use chrono::Utc;
+    use crate::services::auth::{consume_nonce, create_session, custody_fid, session_cookie, SiwfMessage, SiwfSettings};
+    use crate::state::AppState;
+
+    let app_state = use_context::<AppState>()
+        .expect("failed to get AppState from context");
+    let settings = SiwfSettings::from_config(&app_state.config, &app_state.leptos_options.site_addr.to_string());
+
+    let message = SiwfMessage::parse(&message)
+        .and_then(|message| message.verify(&signature, &settings.domain, Utc::now()).map(|_| message))
+        .map_err(|e| AppError::Validation(format!("sign in failed: {}", e)))?;
+
+    if !consume_nonce(&app_state.cache, &message.nonce).await {
+        return Err(AppError::Validation("sign in failed: this sign in request expired or was already used".to_string()).into());
+    }
+
+    // the signature proves the address, the hub proves the address owns the fid
+    let fid = message.fid().unwrap_or_default();
+    let client = reqwest::Client::new();
+    let custody = custody_fid(&client, &hub_url(&app_state.config)?, &message.address)
+        .await
+        .map_err(|e| AppError::Upstream(format!("hub error: {}", e)))?;
+    if custody != Some(fid) {
+        return Err(not_custody(&message.address).into());
+    }
+
+    let user = SessionUser { fid, address: message.address.to_lowercase() };
+    let token = create_session(&app_state.cache, &user)
+        .await
+        .map_err(|e| AppError::Internal(format!("session error: {}", e)))?;
+    set_cookie(session_cookie(&token));
+
+    crate::log_info!("fid {} signed in", fid);
+    Ok(user)
+}
+
+#[server(SignOut, "/api")]
+pub async fn sign_out() -> Result<(), ServerFnError> {
+    use crate::services::auth::{cleared_session_cookie, delete_session, session_token};
+    use crate::state::AppState;
+
+    let app_state = use_context::<AppState>()
+        .expect("failed to get AppState from context");
+
+    if let Some(token) = use_context::<http::request::Parts>().and_then(|parts| session_token(&parts.headers)) {
+        delete_session(&app_state.cache, &token).await;
+    }
+    set_cookie(cleared_session_cookie());
+    Ok(())
+}
+
+#[server(GetCurrentUser, "/api")]
+pub async fn get_current_user() -> Result<Option<SessionUser>, ServerFnError> {
+    Ok(crate::services::auth::current_user().await)
+}
+
+/// The signed in user, shared through context so everything agrees on who's here.
+#[derive(Clone, Copy)]
+pub struct Session {
+    pub user: Resource<(), Option<SessionUser>>,
+}
+
+pub fn provide_session() {
+    let user = create_resource(|| (), |_| async move { get_current_user().await.ok().flatten() });
+    provide_context(Session { user });
+}
+
+pub fn use_session() -> Session {
+    expect_context::<Session>()
+}
+
+#[component]
+pub fn SignIn() -> impl IntoView {
+    let session = use_session();
+    let (error, set_error) = create_signal(None::<String>);
+
+    let sign_in = create_action(move |_: &()| async move {
+        match wallet_sign_in().await {
+            Ok(user) => {
+                set_error.set(None);
+                session.user.set(Some(user));
+            }
+            Err(e) => set_error.set(Some(e)),
+        }
+    });
+
+    let sign_out = create_action(move |_: &()| async move {
+        match sign_out().await {
+            Ok(()) => session.user.set(None),
+            Err(e) => set_error.set(Some(e.to_string())),
+        }
+    });
+
+    let link_class = "text-2xl text-teal-600 dark:text-mint-400 hover:text-teal-800 dark:hover:text-mint-300";
+
+    view! {
+        <div class="flex flex-col items-end">
+            <Transition fallback=|| ()>
+                {move || match session.user.get().flatten() {
+                    Some(user) => view! {
+                        <button class=link_class title=user.address.clone() on:click=move |_| sign_out.dispatch(())>
+                            {format!("fid {} · sign out", user.fid)}
+                        </button>
+                    }.into_view(),
+                    None => view! {
+                        <button class=link_class disabled=move || sign_in.pending().get() on:click=move |_| sign_in.dispatch(())>
+                            {move || if sign_in.pending().get() { "signing in..." } else { "sign in" }}
+                        </button>
+                    }.into_view(),
+                }}
+            </Transition>
+            {move || error.get().map(|err| view! { <p class="text-xs text-salmon-600 dark:text-salmon-400 max-w-xs">{err}</p> })}
+        </div>
+    }
+}
+
+/// Renders `children` only for signed in users, and a nudge to sign in otherwise.
+#[component]
+pub fn RequireSignIn(children: ChildrenFn) -> impl IntoView {
+    let session = use_session();
+    let children = store_value(children);
+
+    view! {
+        <Transition fallback=|| view! { <p class="p-4 text-teal-500 dark:text-teal-400">"loading..."</p> }>
+            {move || match session.user.get() {
+                Some(Some(_)) => children.with_value(|children| children()).into_view(),
+                Some(None) => view! {
+                    <div class="w-full pt-8 text-center bg-gray-300 dark:bg-teal-900 text-teal-700 dark:text-mint-300">
+                        "sign in with your farcaster account to use this page."
+                    </div>
+                }.into_view(),
+                None => ().into_view(),
+            }}
+        </Transition>
+    }
+}
@@ -1,16 +1,114 @@
+use cfg_if::cfg_if;
 use leptos::*;
+use serde::{Deserialize, Serialize};
+use std::cell::{Cell, RefCell};
 use std::collections::HashMap;
+use std::rc::Rc;
+use crate::models::farcaster::UserSummary;
 
-#[derive(Clone, Debug)]
+const MAX_ENTRIES: usize = 500;
+const TTL_MS: f64 = 60.0 * 60.0 * 1000.0;
+
+cfg_if! {
+    if #[cfg(target_arch = "wasm32")] {
+        /// bump when `CachedUserData` changes shape so old browser copies are dropped
+        const STORAGE_KEY: &str = "thenetworktimes:users:v1";
+
+        fn now() -> f64 {
+            js_sys::Date::now()
+        }
+
+        fn local_storage() -> Option<web_sys::Storage> {
+            web_sys::window()?.local_storage().ok().flatten()
+        }
+
+        fn load_entries() -> Entries {
+            local_storage()
+                .and_then(|storage| storage.get_item(STORAGE_KEY).ok().flatten())
+                .and_then(|json| serde_json::from_str(&json).ok())
+                .unwrap_or_default()
+        }
+
+        fn store_entries(entries: &Entries) {
+            let Some(storage) = local_storage() else { return };
+            if let Ok(json) = serde_json::to_string(entries) {
+                // a full or disabled storage just means the cache doesn't outlive the page
+                if storage.set_item(STORAGE_KEY, &json).is_err() {
+                    log::warn!("couldn't persist the user cache");
+                }
+            }
+        }
+
+        fn defer(write: impl FnOnce() + 'static) {
+            set_timeout(write, std::time::Duration::ZERO);
+        }
+    } else {
+        // rendering on the server: nothing to persist, and the cache dies with the request
+        fn now() -> f64 {
+            std::time::SystemTime::now()
+                .duration_since(std::time::UNIX_EPOCH)
+                .map(|elapsed| elapsed.as_millis() as f64)
+                .unwrap_or_default()
+        }
+
+        fn load_entries() -> Entries {
+            Entries::default()
+        }
+
+        fn store_entries(_entries: &Entries) {}
+
+        fn defer(write: impl FnOnce() + 'static) {
+            write();
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
 struct CachedUserData {
     username: String,
     pfp: String,
-    timestamp: f64,
+    stored_at: f64,
+    used_at: f64,
+}
+
+/// The cache itself, with the clock passed in so expiry and eviction can be tested.
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
+struct Entries(HashMap<u64, CachedUserData>);
+
+impl Entries {
+    fn get(&mut self, fid: u64, now: f64) -> Option<(String, String)> {
+        let data = self.0.get_mut(&fid)?;
+        if now - data.stored_at >= TTL_MS {
+            self.0.remove(&fid);
+            return None;
+        }
+        data.used_at = now;
+        Some((data.username.clone(), data.pfp.clone()))
+    }
+
+    fn insert(&mut self, fid: u64, username: String, pfp: String, now: f64) {
+        self.0.insert(fid, CachedUserData { username, pfp, stored_at: now, used_at: now });
+        while self.0.len() > MAX_ENTRIES {
+            let Some(oldest) = self.0.iter().min_by(|a, b| a.1.used_at.total_cmp(&b.1.used_at)).map(|(fid, _)| *fid) else {
+                break;
+            };
+            self.0.remove(&oldest);
+        }
+    }
+
+    fn prune(&mut self, now: f64) {
+        self.0.retain(|_, data| now - data.stored_at < TTL_MS);
+    }
 }
 
+/// Usernames and pfps already seen, kept in `localStorage` across reloads.
+/// Entries expire after an hour, and past `MAX_ENTRIES` the least recently
+/// used go first. Reads count as use, so they're persisted too, batched into
+/// one write per turn of the event loop.
 #[derive(Clone, Debug)]
 pub struct ClientCache {
-    cache: std::rc::Rc<std::cell::RefCell<HashMap<u64, CachedUserData>>>,
+    entries: Rc<RefCell<Entries>>,
+    write_pending: Rc<Cell<bool>>,
 }
 
 impl Default for ClientCache {
@@ -21,35 +119,118 @@ impl Default for ClientCache {
 
 impl ClientCache {
     pub fn new() -> Self {
+        let mut entries = load_entries();
+        entries.prune(now());
         ClientCache {
-            cache: std::rc::Rc::new(std::cell::RefCell::new(HashMap::new())),
+            entries: Rc::new(RefCell::new(entries)),
+            write_pending: Rc::new(Cell::new(false)),
         }
     }
 
-    // i think this is bugged because timestamp but not sure yet
     pub fn get(&self, fid: u64) -> Option<(String, String)> {
-        let cache = self.cache.borrow();
-        cache.get(&fid).and_then(|data| {
-            let now = js_sys::Date::now();
-            if now - data.timestamp < 3600000.0 {
-                Some((data.username.clone(), data.pfp.clone()))
-            } else {
-                None
-            }
-        })
+        let found = self.entries.borrow_mut().get(fid, now());
+        if found.is_some() {
+            self.persist();
+        }
+        found
     }
 
     pub fn set(&self, fid: u64, username: String, pfp: String) {
-        let mut cache = self.cache.borrow_mut();
-        cache.insert(fid, CachedUserData {
-            username,
-            pfp,
-            timestamp: js_sys::Date::now(),
+        self.entries.borrow_mut().insert(fid, username, pfp, now());
+        self.persist();
+    }
+
+    fn persist(&self) {
+        if self.write_pending.replace(true) {
+            return;
+        }
+        let (entries, write_pending) = (self.entries.clone(), self.write_pending.clone());
+        defer(move || {
+            write_pending.set(false);
+            store_entries(&entries.borrow());
         });
     }
+
+    /// Takes in user data that came down with the page, so casts by that user
+    /// don't ask for it again once hydrated.
+    pub fn seed(&self, user: &UserSummary) {
+        if let (Some(username), Some(pfp)) = (&user.username, &user.pfp) {
+            self.set(user.fid, username.clone(), pfp.clone());
+        }
+    }
 }
 
 pub fn provide_client_cache() {
     let client_cache = create_rw_signal(ClientCache::new());
     provide_context(client_cache);
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn entries_with(fids: &[u64], now: f64) -> Entries {
+        let mut entries = Entries::default();
+        for fid in fids {
+            entries.insert(*fid, format!("user{}", fid), format!("https://pfp/{}", fid), now);
+        }
+        entries
+    }
+
+    #[test]
+    fn entries_expire_after_the_ttl() {
+        let mut entries = entries_with(&[1], 1_000.0);
+
+        assert_eq!(entries.get(1, 1_000.0 + TTL_MS - 1.0), Some(("user1".to_string(), "https://pfp/1".to_string())));
+        assert_eq!(entries.get(1, 1_000.0 + TTL_MS), None);
+        assert!(entries.0.is_empty());
+    }
+
+    #[test]
+    fn reading_doesnt_extend_the_ttl() {
+        let mut entries = entries_with(&[1], 0.0);
+        entries.get(1, TTL_MS / 2.0);
+
+        assert_eq!(entries.get(1, TTL_MS), None);
+    }
+
+    #[test]
+    fn least_recently_used_is_evicted_past_the_cap() {
+        let mut entries = Entries::default();
+        for fid in 0..MAX_ENTRIES as u64 {
+            entries.insert(fid, "u".to_string(), "p".to_string(), fid as f64);
+        }
+        // touching the oldest makes fid 1 the least recently used
+        entries.get(0, 10_000.0);
+        entries.insert(9_999, "u".to_string(), "p".to_string(), 10_001.0);
+
+        assert_eq!(entries.0.len(), MAX_ENTRIES);
+        assert!(entries.0.contains_key(&0));
+        assert!(!entries.0.contains_key(&1));
+    }
+
+    #[test]
+    fn recency_survives_a_reload() {
+        let mut entries = Entries::default();
+        for fid in 0..MAX_ENTRIES as u64 {
+            entries.insert(fid, "u".to_string(), "p".to_string(), fid as f64);
+        }
+        entries.get(0, 10_000.0);
+        let json = serde_json::to_string(&entries).unwrap();
+        let mut reloaded: Entries = serde_json::from_str(&json).unwrap();
+        reloaded.insert(9_999, "u".to_string(), "p".to_string(), 10_001.0);
+
+        assert!(reloaded.0.contains_key(&0));
+        assert!(!reloaded.0.contains_key(&1));
+    }
+
+    #[test]
+    fn prune_drops_expired_entries_loaded_from_storage() {
+        let json = serde_json::to_string(&entries_with(&[1], 0.0)).unwrap();
+        let mut entries: Entries = serde_json::from_str(&json).unwrap();
+        entries.insert(2, "u".to_string(), "p".to_string(), TTL_MS);
+        entries.prune(TTL_MS + 1.0);
+
+        assert_eq!(entries.0.keys().collect::<Vec<_>>(), vec![&2]);
+    }
+}
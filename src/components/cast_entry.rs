@@ -1,11 +1,37 @@
 use leptos::*;
 use leptos_router::A;
-use crate::models::farcaster::{Cast, UserDataResponse};
+use std::collections::HashMap;
+use crate::models::farcaster::{Cast, CastAddBody, CastId};
+use crate::services::cast_text::{tokenize_cast, CastTextSegment};
+use crate::time::FarcasterTimestamp;
 use crate::components::cache_provider::ClientCache;
-use crate::{log_debug, log_error, log_info};
+use crate::components::clock::RelativeTime;
+use crate::components::embed::EmbedView;
+use crate::components::users::prefetch_users;
+use crate::log_error;
 use wasm_bindgen::prelude::*;
 use web_sys::{IntersectionObserver, IntersectionObserverEntry, IntersectionObserverInit};
 
+/// Everyone a page of casts shows, authors and mentions alike, to hand to
+/// [`prefetch_users`] before the page renders.
+pub fn page_fids<'a>(casts: impl IntoIterator<Item = &'a Cast>) -> Vec<u64> {
+    casts
+        .into_iter()
+        .flat_map(|cast| {
+            let mentions = cast.data.castAddBody.iter().flat_map(|body| body.mentions.iter().copied());
+            std::iter::once(cast.data.fid).chain(mentions)
+        })
+        .collect()
+}
+
+/// Usernames the client cache already has for `mentions`.
+pub fn mention_usernames(client_cache: &ClientCache, mentions: &[u64]) -> HashMap<u64, String> {
+    mentions
+        .iter()
+        .filter_map(|fid| client_cache.get(*fid).map(|(username, _)| (*fid, username)))
+        .collect()
+}
+
 #[component]
 pub fn CastEntry(
     cast: Cast,
@@ -15,32 +41,25 @@ pub fn CastEntry(
     let (user_data, set_user_data) = create_signal(None::<(String, String)>);
     let (is_visible, set_is_visible) = create_signal(false);
     let (cast_add_body, _set_cast_add_body) = create_signal(cast.data.castAddBody.clone());
+    let parent = CastId { fid: cast.data.fid, hash: cast.hash.clone() };
+    let mentions = cast.data.castAddBody.as_ref().map(|body| body.mentions.clone()).unwrap_or_default();
+    let (usernames, set_usernames) = create_signal(mention_usernames(&client_cache.get_untracked(), &mentions));
     let (show_modal, set_show_modal) = create_signal(false);
     let (modal_image_url, set_modal_image_url) = create_signal(None::<String>);
 
     let load_user_data = create_action(move |_: &()| {
         let fid = cast.data.fid;
+        let mentions = mentions.clone();
         let client_cache = client_cache.get();
         async move {
-            if let Some(cached_data) = client_cache.get(fid) {
-                log_debug!("using client cached data for fid: {}", fid);
-                set_user_data(Some(cached_data));
-            } else {
-                log_debug!("fetching user data for fid: {}", fid);
-                let username_result = get_user_data(fid, 6).await;
-                let pfp_result = get_user_data(fid, 1).await;
-
-                match (username_result, pfp_result) {
-                    (Ok(username_response), Ok(pfp_response)) => {
-                        let username = username_response.data.user_data_body.value;
-                        let pfp = pfp_response.data.user_data_body.value;
-                        log_info!("updating client cache and user data for fid: {}", fid);
-                        client_cache.set(fid, username.clone(), pfp.clone());
-                        set_user_data(Some((username, pfp)));
-                    },
-                    (Err(e), _) | (_, Err(e)) => {
-                        log_error!("failed to fetch user data for fid {}: {}", fid, e);
-                    }
+            // lists prefetch their pages, so this only asks the server for the odd one
+            // out like a streamed cast, and then for its author and mentions in one go
+            prefetch_users(&client_cache, std::iter::once(fid).chain(mentions.iter().copied())).await;
+            set_usernames(mention_usernames(&client_cache, &mentions));
+            match client_cache.get(fid) {
+                Some(user_data) => set_user_data(Some(user_data)),
+                None => {
+                    log_error!("no username or pfp for fid {}", fid);
                 }
             }
         }
@@ -81,15 +100,13 @@ pub fn CastEntry(
         });
     });
 
-    let processed_content = create_resource(
-        move || cast_add_body.get().and_then(|body| body.text.clone()),
-        |text| async move {
-            match text {
-                Some(content) => process_cast_content(content).await.unwrap_or_default(),
-                None => vec!["no text".to_string()],
-            }
+    // retokenized once the author loads, in case that brought in usernames the cache was missing
+    let segments = move || match cast_add_body.get() {
+        Some(CastAddBody { text: Some(text), mentions, mentionsPositions, .. }) => {
+            usernames.with(|usernames| tokenize_cast(&text, &mentions, &mentionsPositions, usernames))
         }
-    );
+        _ => vec![CastTextSegment::Text("no text".to_string())],
+    };
 
     let open_modal = move |url: String| {
         set_modal_image_url(Some(url));
@@ -117,6 +134,7 @@ pub fn CastEntry(
                                 <A href=format!("/profile/{}", cast.data.fid)>
                                     <span class="username text-seafoam-600 dark:text-aqua-400 hover:text-seafoam-700 dark:hover:text-aqua-300">{username}</span>
                                 </A>
+                                <RelativeTime timestamp=FarcasterTimestamp(cast.data.timestamp)/>
                             </div>
                         }
                     }
@@ -132,67 +150,19 @@ pub fn CastEntry(
             }}
     
             <div class="cast-content flex flex-col items-start pl-12">
-                <Suspense fallback=move || {
-                    view! { <p class="pt-2 text-gray-600 dark:text-gray-400">"Loading..."</p> }
-                }>
-                    {move || {
-                        processed_content
-                            .get()
-                            .map(|parts| {
-                                view! {
-                                    <p class="text-md text-gray-800 dark:text-gray-200">
-                                        {parts
-                                            .into_iter()
-                                            .map(|part| {
-                                                if part.starts_with("http") {
-                                                    view! {
-                                                        <a
-                                                            href=part.clone()
-                                                            target="_blank"
-                                                            rel="noopener noreferrer"
-                                                            class="text-teal-600 dark:text-teal-400 hover:underline"
-                                                        >
-                                                            {part}
-                                                        </a>
-                                                    }
-                                                        .into_view()
-                                                } else {
-                                                    view! { <span>{part}</span> }.into_view()
-                                                }
-                                            })
-                                            .collect::<Vec<_>>()}
-                                    </p>
-                                }
-                            })
-                    }}
-                </Suspense>
+                {move || view! { <CastText segments=segments()/> }}
     
                 {move || {
                     cast_add_body
                         .get()
                         .map(|body| {
-                            Some(
-                                body
-                                    .embeds
-                                    .iter()
-                                    .filter_map(|embed| {
-                                        embed
-                                            .url
-                                            .as_ref()
-                                            .map(|url| {
-                                                let url_clone = url.clone();
-                                                view! {
-                                                    <img
-                                                        src=url.clone()
-                                                        alt="cast image"
-                                                        class="mt-2 max-w-sm h-auto rounded-lg cursor-pointer"
-                                                        on:click=move |_| open_modal(url_clone.clone())
-                                                    />
-                                                }
-                                            })
-                                    })
-                                    .collect::<Vec<_>>(),
-                            )
+                            body
+                                .embeds
+                                .into_iter()
+                                .map(|embed| {
+                                    view! { <EmbedView embed=embed parent=parent.clone() on_image_click=open_modal/> }
+                                })
+                                .collect::<Vec<_>>()
                         })
                 }}
             </div>
@@ -227,113 +197,61 @@ fn ImageView(#[prop(into)] url: String) -> impl IntoView {
     }
 }
 
-#[server(ProcessCastContent, "/api")]
-pub async fn process_cast_content(content:String) -> Result<Vec<String>, ServerFnError> {
-    use regex::Regex;
-    /*
-     * apparently intersperse_with is feature gated and there's an open issue (i do not care), so i
-     * needed to add the feature flag thing to my lib file to parse the content on the server. i
-     * refuse to use the regex crate on the client! my wasm binary will still be large af i reckon,
-     * oh well.
-    */
-    let url_regex = Regex::new(r"https?://\S+").unwrap();
-    let parts: Vec<String> = url_regex.split(&content)
-        .map(|s| s.to_string())
-        .collect::<Vec<String>>()
-        .into_iter()
-        .intersperse_with(|| {
-            url_regex.find(&content)
-                .map(|m| m.as_str().to_string())
-                .unwrap_or_default()
-        })
-        .filter(|s| !s.is_empty())
-        .collect();
-
-    Ok(parts)
-}
-
-#[server(GetUserData, "/api")]
-pub async fn get_user_data(fid: u64, user_data_type: u8) -> Result<UserDataResponse, ServerFnError> {
-    use crate::services::hubble::{UserDataParams, get_user_data_by_fid};
-    use crate::services::redis::{get_user_data_from_cache, set_user_data_to_cache};
-    use crate::state::AppState;
-    use axum::extract::Query;
-    use std::fmt;
-
-    #[derive(Debug)]
-    enum UserDataError {
-        CacheRead(String),
-        CacheWrite(String),
-        Fetch(String),
-        Parse(String),
-    }
-    
-    impl fmt::Display for UserDataError {
-        fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
-            match self {
-                UserDataError::CacheRead(e) => write!(f, "cache read error: {}", e),
-                UserDataError::CacheWrite(e) => write!(f, "cache write error: {}", e),
-                UserDataError::Fetch(e) => write!(f, "fetch error: {}", e),
-                UserDataError::Parse(e) => write!(f, "parse error: {}", e),
-            }
-        }
-    }
-    
-    fn to_server_error(e: UserDataError) -> ServerFnError {
-        ServerFnError::ServerError(e.to_string())
-    }
-
-    crate::log_debug!("get_user_data called with fid: {}, user_data_type: {}", fid, user_data_type);
-
-    let app_state = use_context::<AppState>().expect("Failed to get AppState from context");
-    let mut redis_conn = app_state.redis_pool.clone();
-
-    let cache_key = format!("user_data:{}:{}", fid, user_data_type);
-
-    // Check cache
-    match get_user_data_from_cache(&mut redis_conn, &cache_key).await {
-        Ok(Some(cached_data)) => {
-//            info!("cache hit for fid: {}, type: {}", fid, user_data_type);
-            log_info!("cache hit for fid: {}, type: {}", fid, user_data_type);
-            return Ok(cached_data);
-        }
-        Ok(None) => {
-            crate::log_debug!("cache miss for fid: {}, type: {}", fid, user_data_type);
-        }
-        Err(e) => {
-            crate::log_warn!("error reading from cache for fid {}, type {}: {}", fid, user_data_type, e);
-            return Err(to_server_error(UserDataError::CacheRead(e.to_string())))
-        }
-    }
-
-    let params = UserDataParams {
-        fid,
-        user_data_type: Some(user_data_type.to_string()),
-    };
-
-    match get_user_data_by_fid(Query(params)).await {
-        Ok(json) => {
-            crate::log_debug!("successfully fetched user data for fid: {}, type: {}", fid, user_data_type);
-            match serde_json::from_value::<UserDataResponse>(json.0) {
-                Ok(user_data) => {
-                    // Update cache
-                    if let Err(e) = set_user_data_to_cache(&mut redis_conn, &cache_key, &user_data).await {
-                        crate::log_warn!("failed to update cache for fid {}, type {}: {}", fid, user_data_type, e);
-                        return Err(to_server_error(UserDataError::CacheWrite(e.to_string())))
+#[component]
+pub fn CastText(segments: Vec<CastTextSegment>) -> impl IntoView {
+    view! {
+        <p class="text-md text-gray-800 dark:text-gray-200 whitespace-pre-wrap break-words">
+            {segments
+                .into_iter()
+                .map(|segment| match segment {
+                    CastTextSegment::Text(text) => view! { <span>{text}</span> }.into_view(),
+                    CastTextSegment::Url { href, text } => {
+                        view! {
+                            <a
+                                href=href
+                                target="_blank"
+                                rel="noopener noreferrer"
+                                class="text-teal-600 dark:text-teal-400 hover:underline"
+                            >
+                                {text}
+                            </a>
+                        }
+                            .into_view()
                     }
-                    crate::log_debug!("successfully updated cache for fid: {}, type: {}", fid, user_data_type);
-                    Ok(user_data)
-                }
-                Err(e) => {
-                    crate::log_warn!("failed to parse user data for fid {}, type {}: {}", fid, user_data_type, e);
-                    Err(to_server_error(UserDataError::Parse(e.to_string())))
-                }
-            }
-        }
-        Err(e) => {
-            crate::log_warn!("failed to fetch user data for fid {}, type {}: {}", fid, user_data_type, e);
-            Err(to_server_error(UserDataError::Fetch(e.to_string())))
-        }
+                    CastTextSegment::Mention { fid, username } => {
+                        view! { <MentionLink fid=fid username=username/> }.into_view()
+                    }
+                    CastTextSegment::Channel(id) => {
+                        view! {
+                            <a
+                                href=format!("https://warpcast.com/~/channel/{}", id)
+                                target="_blank"
+                                rel="noopener noreferrer"
+                                class="text-seafoam-600 dark:text-aqua-400 hover:underline"
+                            >
+                                {format!("/{}", id)}
+                            </a>
+                        }
+                            .into_view()
+                    }
+                    CastTextSegment::Cashtag(ticker) => {
+                        view! { <span class="text-teal-600 dark:text-mint-400 font-medium">{format!("${}", ticker)}</span> }
+                            .into_view()
+                    }
+                })
+                .collect::<Vec<_>>()}
+        </p>
     }
+}
 
+#[component]
+fn MentionLink(fid: u64, username: Option<String>) -> impl IntoView {
+    view! {
+        <A
+            href=format!("/profile/{}", fid)
+            class="text-seafoam-600 dark:text-aqua-400 hover:underline"
+        >
+            {format!("@{}", username.unwrap_or_else(|| fid.to_string()))}
+        </A>
+    }
 }
@@ -1,30 +1,47 @@
+use cfg_if::cfg_if;
 use leptos::*;
+use log::error;
+use wasm_bindgen::closure::Closure;
+use wasm_bindgen::JsCast;
+use web_sys::{EventSource, MessageEvent};
 use crate::models::farcaster::Cast;
-use crate::components::cast_entry::CastEntry;
+use crate::components::cache_provider::ClientCache;
+use crate::components::cast_entry::{page_fids, CastEntry};
+use crate::components::users::prefetch_users;
 
 #[component]
 pub fn CastList(
-    active_channel: ReadSignal<String>
+    #[prop(into)] active_channel: Signal<String>
 ) -> impl IntoView {
-    let (cast_list, set_cast_list) = create_signal(Vec::new());
+    let (cast_list, set_cast_list) = create_signal(Vec::<Cast>::new());
     let (page, set_page) = create_signal(1u64);
     let (error, set_error) = create_signal(None::<String>);
     let (is_loading, set_is_loading) = create_signal(false);
     let (has_more, set_has_more) = create_signal(true);
+    let (new_casts, set_new_casts) = create_signal(Vec::<Cast>::new());
     let limit = 4u64;
+    let client_cache = use_context::<RwSignal<ClientCache>>().expect("ClientCache should be provided");
 
     let fetch_casts = create_action(move |_: &()| {
         let current_page = page.get();
         let current_channel = active_channel.get();
+        let client_cache = client_cache.get_untracked();
         async move {
             set_is_loading.set(true);
             match get_casts_by_channel(current_channel, current_page, limit).await {
-                Ok(mut fetched_casts) => {
+                Ok(fetched_casts) => {
+                    prefetch_users(&client_cache, page_fids(&fetched_casts)).await;
                     if fetched_casts.is_empty() {
                         set_has_more.set(false);
                     } else {
-                        fetched_casts.reverse();
-                        set_cast_list.update(|list| list.extend(fetched_casts));
+                        // casts prepended from the live stream shift the pages, so skip repeats
+                        set_cast_list.update(|list| {
+                            let fresh: Vec<Cast> = fetched_casts
+                                .into_iter()
+                                .filter(|cast| !list.iter().any(|c| c.hash == cast.hash))
+                                .collect();
+                            list.extend(fresh);
+                        });
                     }
                     set_error.set(None);
                 }
@@ -45,6 +62,53 @@ pub fn CastList(
         fetch_casts.dispatch(());
     });
 
+    // new casts in the channel are held back until the reader asks for them
+    create_effect(move |_| {
+        let channel = active_channel.get();
+        set_new_casts.set(Vec::new());
+
+        let event_source = match EventSource::new(&format!("/api/cast_stream?channel={}", urlencoding::encode(&channel))) {
+            Ok(event_source) => event_source,
+            Err(e) => {
+                error!("failed to connect to cast stream: {:?}", e);
+                return;
+            }
+        };
+
+        let on_message = Closure::wrap(Box::new(move |event: MessageEvent| {
+            let Some(data) = event.data().as_string() else { return };
+            match serde_json::from_str::<Cast>(&data) {
+                Ok(cast) => set_new_casts.update(|pending| {
+                    if !pending.iter().any(|c| c.hash == cast.hash) {
+                        pending.insert(0, cast);
+                    }
+                }),
+                Err(e) => error!("failed to parse streamed cast: {:?}", e),
+            }
+        }) as Box<dyn FnMut(_)>);
+        event_source.set_onmessage(Some(on_message.as_ref().unchecked_ref()));
+
+        // the handler lives exactly as long as the source it's attached to
+        on_cleanup(move || {
+            event_source.set_onmessage(None);
+            event_source.close();
+            drop(on_message);
+        });
+    });
+
+    let show_new_casts = move |_| {
+        let pending = new_casts.get();
+        set_new_casts.set(Vec::new());
+        set_cast_list.update(|list| {
+            let mut merged: Vec<Cast> = pending
+                .into_iter()
+                .filter(|cast| !list.iter().any(|c| c.hash == cast.hash))
+                .collect();
+            merged.append(list);
+            *list = merged;
+        });
+    };
+
     let load_more = move |_| {
         if !is_loading.get() && has_more.get() {
             set_page.update(|p| *p += 1);
@@ -60,20 +124,35 @@ pub fn CastList(
                 </a>
             </h2>
             {move || error.get().map(|err| view! { <p class="text-salmon-600 dark:text-salmon-400">{err}</p> })}
+            {move || {
+                let count = new_casts.with(Vec::len);
+                (count > 0).then(|| view! {
+                    <button
+                        on:click=show_new_casts
+                        class="new-casts-banner w-full mb-4 px-4 py-2 bg-seafoam-600 dark:bg-teal-600 text-white hover:bg-seafoam-700 dark:hover:bg-teal-700 transition-colors duration-300 rounded-md"
+                    >
+                        {format!("{} new cast{}", count, if count == 1 { "" } else { "s" })}
+                    </button>
+                })
+            }}
             <div class="cast-list">
                 <For
                     each=move || cast_list.get()
                     key=|cast| cast.hash.clone()
                     children=move |cast| {
-                        let index = cast_list.with(|list| list.iter().position(|c| c.hash == cast.hash).unwrap_or(0));
+                        // casts can be prepended, so the position has to follow the list
+                        let index = {
+                            let hash = cast.hash.clone();
+                            Signal::derive(move || cast_list.with(|list| list.iter().position(|c| c.hash == hash).unwrap_or(0)))
+                        };
                         view! {
                             <div class=move || format!(
                                 "border-l border-r border-b last:border-b-0 border-teal-700 dark:border-teal-300 {} p-4 transition-colors duration-300 ease-in-out hover:bg-gray-100 dark:hover:bg-teal-800 group",
-                                if index == 0 { "border-t" } else { "" }
+                                if index.get() == 0 { "border-t" } else { "" }
                             )>
                                 <CastEntry 
                                     cast=cast 
-                                    lazy_load_index=Signal::derive(move || index < 22)
+                                    lazy_load_index=Signal::derive(move || index.get() < 22)
                                 />
                             </div>
                         }
@@ -106,53 +185,98 @@ pub fn CastList(
 
 #[server(GetCastsByChannel, "/api")]
 pub async fn get_casts_by_channel(channel: String, page: u64, limit: u64) -> Result<Vec<Cast>, ServerFnError> {
-    use axum::extract::{Query, Path};
-    use serde_json::Value;
-    use std::collections::HashMap;
-    use crate::services::hubble::get_casts_by_parent;
-    use std::fmt;
-
-    #[derive(Debug)]
-    enum CastError {
-        FetchError(String),
-        ParseError(String),
-    }
+    use crate::state::AppState;
 
-    impl fmt::Display for CastError {
-        fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
-            match self {
-                CastError::FetchError(e) => write!(f, "fetch error: {}", e),
-                CastError::ParseError(e) => write!(f, "parse error: {}", e),
-            }
-        }
-    }
-    
-    fn to_server_error(e: CastError) -> ServerFnError {
-        ServerFnError::ServerError(e.to_string())
-    }
+    let app_state = use_context::<AppState>().expect("Failed to get AppState from context");
+    let offset = (page.max(1) - 1) * limit;
+
+    Ok(load_channel_casts(&app_state, &channel, offset, limit).await?)
+}
+
+cfg_if! {
+    if #[cfg(feature = "ssr")] {
+        use axum::extract::{Query, Path, State};
+        use crate::database::casts;
+        use crate::database::db::interact;
+        use crate::error_template::AppError;
+        use crate::models::farcaster::CastResponse;
+        use crate::services::hubble::{found, get_casts_by_parent, PageParams};
+        use crate::state::AppState;
+
+        /// the hub is always read in pages this size, so every offset shares the same cached pages
+        const HUB_PAGE_SIZE: u32 = 100;
+
+        /// `limit` casts from a channel starting `offset` casts back from the newest.
+        /// A channel is read from one place for good: the local index once the
+        /// indexer has stored anything for it, the hub otherwise. Switching part way
+        /// would splice two different orderings together and repeat casts.
+        pub async fn load_channel_casts(app_state: &AppState, channel: &str, offset: u64, limit: u64) -> Result<Vec<Cast>, AppError> {
+            let local_casts = {
+                let channel = channel.to_string();
+                interact(&app_state.pool, move |conn| {
+                    let page = casts::get_casts_by_channel(conn, &channel, offset as i64, limit as i64)?;
+                    // an empty page past the end of an indexed channel is the end of it
+                    if page.is_empty() && casts::newest_cast_timestamp(conn, &channel)?.is_none() {
+                        return Ok(None);
+                    }
+                    Ok(Some(page))
+                })
+                .await
+                .map_err(|e| AppError::Internal(format!("failed to load indexed casts: {}", e)))?
+            };
 
-    let mut query_params = HashMap::new();
-    query_params.insert("page".to_string(), page);
-    query_params.insert("limit".to_string(), limit);
+            if let Some(local_casts) = local_casts {
+                return local_casts
+                    .into_iter()
+                    .map(Cast::try_from)
+                    .collect::<Result<Vec<_>, _>>()
+                    .map_err(|e| AppError::Internal(format!("unreadable indexed cast: {:?}", e)));
+            }
 
-    let channel_url = format!("https://warpcast.com/~/channel/{}", channel);
-    let encoded_channel_url = urlencoding::encode(&channel_url);
+            crate::log_debug!("/{} isn't indexed, reading it from the hub", channel);
+            hub_channel_casts(app_state, channel, offset, limit).await
+        }
 
-    let casts_response = get_casts_by_parent(
-        Path(encoded_channel_url.to_string()),
-        Query(query_params)
-    )
-    .await
-    .map_err(|e| CastError::FetchError(format!("failed to fetch casts: {:?}", e)))
-    .map_err(to_server_error)?;
+        /// walks the hub's newest first pages of a channel far enough to cover `offset + limit`
+        async fn hub_channel_casts(app_state: &AppState, channel: &str, offset: u64, limit: u64) -> Result<Vec<Cast>, AppError> {
+            let channel_url = format!("https://warpcast.com/~/channel/{}", channel);
+            let encoded_channel_url = urlencoding::encode(&channel_url).to_string();
 
-    let cast_response: Value = serde_json::from_value(casts_response.0)
-        .map_err(|e| CastError::ParseError(format!("failed to parse cast response: {:?}", e)))
-        .map_err(to_server_error)?;
+            let mut casts = Vec::new();
+            let mut page_token: Option<String> = None;
+            while (casts.len() as u64) < offset + limit {
+                let cache_key = format!("channel:{}:{}", channel, page_token.as_deref().unwrap_or_default());
+                let page = app_state
+                    .caches
+                    .cast_pages
+                    .get_or_load(&cache_key, || async {
+                        let params = PageParams {
+                            page_size: Some(HUB_PAGE_SIZE),
+                            page_token: page_token.clone(),
+                            reverse: Some(true),
+                        };
+                        let json = get_casts_by_parent(
+                            State(app_state.config.clone()),
+                            Path(encoded_channel_url.clone()),
+                            Query(params)
+                        )
+                        .await;
+                        found(json)?
+                            .map(|json| serde_json::from_value::<CastResponse>(json.0))
+                            .transpose()
+                            .map_err(|e| AppError::Upstream(format!("unreadable casts: {}", e)))
+                    })
+                    .await?;
+                let Some(page) = page else { break };
 
-    let casts: Vec<Cast> = serde_json::from_value(cast_response["messages"].clone())
-        .map_err(|e| CastError::ParseError(format!("failed to parse casts: {:?}", e)))
-        .map_err(to_server_error)?;
+                casts.extend(page.messages);
+                match page.next_page_token.filter(|token| !token.is_empty()) {
+                    Some(token) => page_token = Some(token),
+                    None => break,
+                }
+            }
 
-    Ok(casts)
+            Ok(casts.into_iter().skip(offset as usize).take(limit as usize).collect())
+        }
+    }
 }
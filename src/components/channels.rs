@@ -1,117 +1,36 @@
 use cfg_if::cfg_if;
 use leptos::*;
-use chrono::{DateTime, NaiveDateTime, Utc};
 use std::collections::HashMap;
 use std::collections::HashSet;
 use log::info;
-use crate::models::farcaster::{Channel, ChannelsResponse};
-
-cfg_if! {
-    if #[cfg(target_arch = "wasm32")] {
-        use gloo_net::http::Request;
-        use crate::models::farcaster::UserDataResponse;
-
-        async fn fetch_channels() -> Result<ChannelsResponse, String> {
-            match Request::get("/api/channels").send().await {
-                Ok(response) => {
-                    match response.json::<ChannelsResponse>().await {
-                        Ok(data) => {
-                            info!("Channels fetched successfully: {:?}", data);
-                            Ok(data)
-                        },
-                        Err(err) => {
-                            info!("Failed to parse channels: {:?}", err);
-                            Err(format!("Failed to parse channels: {:?}", err))
-                        }
-                    }
-                },
-                Err(err) => {
-                    info!("Failed to fetch channels: {:?}", err);
-                    Err(format!("Failed to fetch channels: {:?}", err))
-                }
-            }
-        }
-
-        async fn fetch_username(fid: u64, lead_usernames: Signal<HashMap<u64, String>>) -> Result<String, String> {
-            if lead_usernames.with_untracked(|usernames| !usernames.contains_key(&fid)) {
-                match Request::get(&format!("https://api.thenetworktimes.xyz/userDataByFid?fid={}&user_data_type=USER_DATA_TYPE_USERNAME", fid)).send().await {
-                    Ok(response) => {
-                        match response.json::<UserDataResponse>().await {
-                            Ok(data) => {
-                                let username = if data.data.user_data_body.data_type == "USER_DATA_TYPE_USERNAME" {
-                                    Some(data.data.user_data_body.value)
-                                } else {
-                                    None
-                                }.ok_or_else(|| "Username not found".to_string())?;
-                                info!("Username for fid {}: {}", fid, username);
-                                Ok(username)
-                            },
-                            Err(err) => {
-                                info!("Failed to parse user data for fid {}: {:?}", fid, err);
-                                Err(format!("Failed to parse user data for fid {}: {:?}", fid, err))
-                            }
-                        }
-                    },
-                    Err(err) => {
-                        info!("Failed to fetch user data for fid {}: {:?}", fid, err);
-                        Err(format!("Failed to fetch user data for fid {}: {:?}", fid, err))
-                    }
-                }
-            } else {
-                Ok(lead_usernames.with_untracked(|usernames| usernames.get(&fid).unwrap().clone()))
-            }
-        }
-    } else {
-        async fn fetch_channels() -> Result<ChannelsResponse, String> {
-            Err("Fetching channels not supported on server side".into())
-        }
-
-        async fn fetch_username(_fid: u64, _lead_usernames: Signal<HashMap<u64, String>>) -> Result<String, String> {
-            Err("Fetching usernames not supported on server side".into())
-        }
-    }
-}
-
-#[allow(deprecated)]
-fn format_date(timestamp: u64) -> String {
-    let naive_datetime = NaiveDateTime::from_timestamp(timestamp as i64, 0);
-    let datetime: DateTime<Utc> = DateTime::from_utc(naive_datetime, Utc);
-    datetime.format("%B '%y").to_string().to_lowercase()
-    
-}
+use crate::components::users::get_users;
+use crate::models::farcaster::{Channel, UserSummary};
+use crate::time::{from_warpcast_created_at, month_year};
 
 #[component]
 pub fn Channels(
-    set_active_channel: WriteSignal<String>
+    set_active_channel: WriteSignal<Option<String>>
 ) -> impl IntoView {
     let (channels, set_channels) = create_signal(Vec::new());
     let (lead_usernames, set_lead_usernames) = create_signal(HashMap::new());
     let (error_message, set_error_message) = create_signal(None);
     let (search_query, set_search_query) = create_signal(String::new());
+    let (show_catalog, set_show_catalog) = create_signal(false);
     let ongoing_requests = std::cell::RefCell::new(HashSet::new());
 
-    let desired_channels = [
-        "https://warpcast.com/~/channel/onthebrink".to_string(),
-        "https://warpcast.com/~/channel/piratewires".to_string(),
-        "https://warpcast.com/~/channel/moz".to_string(),
-        "https://warpcast.com/~/channel/networktimes".to_string(),
-        "https://warpcast.com/~/channel/gray".to_string(),
-        "https://warpcast.com/~/channel/all-in".to_string(),
-    ];
-
-
-    spawn_local(async move {
-        match fetch_channels().await {
-            Ok(data) => {
-                let filtered_channels: Vec<Channel> = data.result.channels.into_iter()
-                    .filter(|channel| desired_channels.contains(&channel.url))
-                    .collect();
-                info!("Filtered channels: {:?}", filtered_channels);
-                set_channels(filtered_channels);
-            },
-            Err(err) => set_error_message(Some(err)),
-        }
-    });
+    let fetch_followed = move || {
+        spawn_local(async move {
+            match get_followed_channels().await {
+                Ok(followed) => {
+                    info!("Followed channels: {:?}", followed);
+                    set_channels(followed);
+                },
+                Err(err) => set_error_message(Some(err.to_string())),
+            }
+        });
+    };
+
+    fetch_followed();
 
     let filtered_channels = create_memo(move |_| {
         let query = search_query().to_lowercase();
@@ -123,27 +42,37 @@ pub fn Channels(
             .collect::<Vec<_>>()
     });
 
+    // every lead we don't have a name for yet, in one request
     create_effect({
         let ongoing_requests = ongoing_requests.clone();
         move |_| {
-            for channel in channels().iter() {
-                let fid = channel.leadFid;
-                if !lead_usernames().contains_key(&fid) && !ongoing_requests.borrow().contains(&fid) {
-                    ongoing_requests.borrow_mut().insert(fid);
-                    let ongoing_requests = ongoing_requests.clone();
-                    spawn_local(async move {
-                        match fetch_username(fid, lead_usernames.into()).await {
-                            Ok(username) => {
-                                set_lead_usernames.update(|usernames| {
-                                    usernames.insert(fid, username);
-                                });
-                            },
-                            Err(err) => set_error_message(Some(err)),
-                        }
-                        ongoing_requests.borrow_mut().remove(&fid);
-                    });
-                }
+            let fids: Vec<u64> = channels()
+                .iter()
+                .map(|channel| channel.leadFid)
+                .filter(|fid| !lead_usernames().contains_key(fid) && !ongoing_requests.borrow().contains(fid))
+                .collect::<HashSet<_>>()
+                .into_iter()
+                .collect();
+            if fids.is_empty() {
+                return;
             }
+            ongoing_requests.borrow_mut().extend(fids.iter().copied());
+            let ongoing_requests = ongoing_requests.clone();
+            spawn_local(async move {
+                match get_users(fids.clone(), vec![UserSummary::USERNAME]).await {
+                    Ok(users) => {
+                        set_lead_usernames.update(|usernames| {
+                            // a lead without a username still needs an entry, or they'd be asked for again
+                            usernames.extend(users.into_iter().map(|user| (user.fid, user.username.unwrap_or_else(|| user.fid.to_string()))));
+                        });
+                    },
+                    Err(err) => set_error_message(Some(err.to_string())),
+                }
+                let mut ongoing_requests = ongoing_requests.borrow_mut();
+                for fid in &fids {
+                    ongoing_requests.remove(fid);
+                }
+            });
         }
     });
 
@@ -156,9 +85,24 @@ pub fn Channels(
                 on:input=move |ev| set_search_query(event_target_value(&ev))
                 class="w-full p-2 mb-4 bg-gray-100 dark:bg-teal-700 text-gray-800 dark:text-gray-200 border border-gray-300 dark:border-teal-600 rounded-md focus:outline-none focus:ring-2 focus:ring-seafoam-500 dark:focus:ring-aqua-400"
             />
+            <button
+                class="w-full mb-4 p-2 text-sm bg-gray-200 dark:bg-teal-800 text-teal-600 dark:text-mint-400 hover:bg-gray-300 dark:hover:bg-teal-700 rounded-md transition duration-300"
+                on:click=move |_| set_show_catalog.update(|v| *v = !*v)
+            >
+                {move || if show_catalog.get() { "done" } else { "manage channels" }}
+            </button>
+            {move || show_catalog.get().then(|| view! {
+                <ChannelCatalog followed=channels on_change=move |_| fetch_followed()/>
+            })}
             {move || error_message().map(|err| view! { <p class="text-salmon-600 dark:text-salmon-400">{err}</p> })}
     
             <ul class="channels-list flex flex-col space-y-2">
+                <button
+                    class="channel-item bg-gray-200 dark:bg-teal-800 p-3 rounded-md shadow hover:bg-gray-300 dark:hover:bg-teal-700 transition duration-300 text-left text-base text-seafoam-600 dark:text-aqua-400"
+                    on:click=move |_| set_active_channel(None)
+                >
+                    "all channels"
+                </button>
                 {move || {
                     filtered_channels()
                         .iter()
@@ -168,7 +112,7 @@ pub fn Channels(
                             view! {
                                 <button
                                     class="channel-item bg-gray-200 dark:bg-teal-800 p-3 rounded-md shadow hover:bg-gray-300 dark:hover:bg-teal-700 transition duration-300 group relative"
-                                    on:click=move |_| set_active_channel(channel_id.clone())
+                                    on:click=move |_| set_active_channel(Some(channel_id.clone()))
                                 >
                                     <div class="channel-item-info-container flex flex-col items-start">
                                         <div class="channel-avatar-chip flex flex-row items-center justify-between text-center space-x-4">
@@ -208,7 +152,7 @@ pub fn Channels(
                                                     }
                                                 })}
                                             <p class="text-xs text-teal-600 dark:text-teal-400">
-                                                {"created: "} {format_date(channel.createdAt)}
+                                                {"created: "} {month_year(from_warpcast_created_at(channel.createdAt))}
                                             </p>
                                             <p class="text-xs text-seafoam-600 dark:text-seafoam-400">
                                                 {"followers: "} {channel.followerCount}
@@ -224,3 +168,214 @@ pub fn Channels(
         </div>
     }
 }
+
+#[component]
+fn ChannelCatalog(
+    followed: ReadSignal<Vec<Channel>>,
+    #[prop(into)] on_change: Callback<()>,
+) -> impl IntoView {
+    let (catalog_query, set_catalog_query) = create_signal(String::new());
+    let (error_message, set_error_message) = create_signal(None::<String>);
+
+    let results = create_resource(catalog_query, |query| async move {
+        search_channels(query, 25).await
+    });
+
+    let toggle_follow = create_action(move |(channel_id, is_followed): &(String, bool)| {
+        let channel_id = channel_id.clone();
+        let is_followed = *is_followed;
+        async move {
+            let result = if is_followed {
+                unfollow_channel(channel_id).await
+            } else {
+                follow_channel(channel_id).await
+            };
+            match result {
+                Ok(_) => {
+                    set_error_message(None);
+                    leptos::Callable::call(&on_change, ());
+                },
+                Err(err) => set_error_message(Some(err.to_string())),
+            }
+        }
+    });
+
+    view! {
+        <div class="channel-catalog flex flex-col mb-4 p-2 bg-gray-100 dark:bg-teal-900 rounded-md">
+            <input
+                type="text"
+                placeholder="search all channels"
+                on:input=move |ev| set_catalog_query(event_target_value(&ev))
+                class="w-full p-2 mb-2 bg-white dark:bg-teal-700 text-gray-800 dark:text-gray-200 border border-gray-300 dark:border-teal-600 rounded-md focus:outline-none focus:ring-2 focus:ring-seafoam-500 dark:focus:ring-aqua-400"
+            />
+            {move || error_message().map(|err| view! { <p class="text-xs text-salmon-600 dark:text-salmon-400">{err}</p> })}
+            <Suspense fallback=|| view! { <p class="text-xs text-teal-500 dark:text-teal-400">"searching..."</p> }>
+                <ul class="flex flex-col space-y-1 max-h-96 overflow-y-auto">
+                    {move || results.get().map(|result| match result {
+                        Ok(channels) => channels
+                            .into_iter()
+                            .map(|channel| {
+                                let channel_id = channel.id.clone();
+                                let is_followed = {
+                                    let channel_id = channel_id.clone();
+                                    move || followed.with(|followed| followed.iter().any(|c| c.id == channel_id))
+                                };
+                                let is_followed_for_click = is_followed.clone();
+                                view! {
+                                    <li class="flex flex-row items-center justify-between space-x-2">
+                                        <div class="flex flex-row items-center space-x-2">
+                                            <img src=channel.imageUrl.clone() alt=channel.id.clone() class="w-6 h-6 rounded-full"/>
+                                            <span class="text-sm text-seafoam-600 dark:text-aqua-400">{format!("/{}", channel.id)}</span>
+                                            <span class="text-xs text-gray-500 dark:text-gray-400">{channel.followerCount}</span>
+                                        </div>
+                                        <button
+                                            class="text-xs px-2 py-1 bg-gray-200 dark:bg-teal-800 text-teal-600 dark:text-mint-400 hover:bg-gray-300 dark:hover:bg-teal-700 rounded transition duration-300 disabled:opacity-50"
+                                            disabled=move || toggle_follow.pending().get()
+                                            on:click=move |_| toggle_follow.dispatch((channel_id.clone(), is_followed_for_click()))
+                                        >
+                                            {move || if is_followed() { "unfollow" } else { "follow" }}
+                                        </button>
+                                    </li>
+                                }
+                            })
+                            .collect_view(),
+                        Err(err) => view! { <li class="text-xs text-salmon-600 dark:text-salmon-400">{err.to_string()}</li> }.into_view(),
+                    })}
+                </ul>
+            </Suspense>
+        </div>
+    }
+}
+
+cfg_if! {
+    if #[cfg(feature = "ssr")] {
+        use crate::database::db::interact;
+        use crate::error_template::AppError;
+        use crate::state::AppState;
+
+        // the all-channels response is big and rarely changes, so it lives in the cache
+        async fn load_channel_catalog(app_state: &AppState) -> Result<Vec<Channel>, AppError> {
+            use crate::models::farcaster::ChannelsResponse;
+            use crate::services::hubble::get_channels;
+            use axum::extract::State;
+
+            let response = app_state
+                .caches
+                .channels
+                .get_or_load(&"all", || async {
+                    let json = get_channels(State(app_state.config.clone())).await?;
+                    serde_json::from_value::<ChannelsResponse>(json.0)
+                        .map(Some)
+                        .map_err(|e| AppError::Upstream(format!("unreadable channel catalog: {}", e)))
+                })
+                .await?;
+
+            Ok(response.map(|response| response.result.channels).unwrap_or_default())
+        }
+    }
+}
+
+#[server(GetFollowedChannels, "/api")]
+pub async fn get_followed_channels() -> Result<Vec<Channel>, ServerFnError> {
+    use diesel::prelude::*;
+    use crate::models::channels::FollowedChannel;
+    use crate::schema::followed_channels;
+
+    let app_state = use_context::<AppState>()
+        .expect("failed to get AppState from context");
+
+    let followed = interact(&app_state.pool, |conn| {
+        followed_channels::table
+            .order(followed_channels::created_at.asc())
+            .load::<FollowedChannel>(conn)
+    })
+    .await
+    .map_err(|e| AppError::Internal(e.to_string()))?;
+
+    let catalog = load_channel_catalog(&app_state).await?;
+
+    Ok(followed
+        .into_iter()
+        .filter_map(|followed| {
+            let channel = catalog.iter().find(|channel| channel.id == followed.channel_id).cloned();
+            if channel.is_none() {
+                crate::log_warn!("followed channel {} is missing from the catalog", followed.channel_id);
+            }
+            channel
+        })
+        .collect())
+}
+
+#[server(SearchChannels, "/api")]
+pub async fn search_channels(query: String, limit: usize) -> Result<Vec<Channel>, ServerFnError> {
+    let app_state = use_context::<AppState>()
+        .expect("failed to get AppState from context");
+
+    let query = query.trim().to_lowercase();
+    let mut channels: Vec<Channel> = load_channel_catalog(&app_state)
+        .await?
+        .into_iter()
+        .filter(|channel| {
+            query.is_empty()
+                || channel.id.to_lowercase().contains(&query)
+                || channel.name.to_lowercase().contains(&query)
+                || channel.description.to_lowercase().contains(&query)
+        })
+        .collect();
+
+    channels.sort_by_key(|channel| std::cmp::Reverse(channel.followerCount));
+    channels.truncate(limit);
+
+    Ok(channels)
+}
+
+#[server(FollowChannel, "/api")]
+pub async fn follow_channel(channel_id: String) -> Result<(), ServerFnError> {
+    use diesel::prelude::*;
+    use crate::models::channels::NewFollowedChannel;
+    use crate::schema::followed_channels;
+
+    // the followed channels are everyone's sidebar, timeline and index
+    crate::services::auth::require_editor().await?;
+    let app_state = use_context::<AppState>()
+        .expect("failed to get AppState from context");
+
+    let channel = load_channel_catalog(&app_state)
+        .await?
+        .into_iter()
+        .find(|channel| channel.id == channel_id)
+        .ok_or_else(|| AppError::NotFound(format!("channel {}", channel_id)))?;
+
+    interact(&app_state.pool, move |conn| {
+        diesel::insert_into(followed_channels::table)
+            .values(&NewFollowedChannel {
+                channel_id: channel.id,
+                url: channel.url,
+            })
+            .on_conflict_do_nothing()
+            .execute(conn)
+    })
+    .await
+    .map_err(|e| AppError::Internal(e.to_string()))?;
+
+    Ok(())
+}
+
+#[server(UnfollowChannel, "/api")]
+pub async fn unfollow_channel(channel_id: String) -> Result<(), ServerFnError> {
+    use diesel::prelude::*;
+    use crate::schema::followed_channels;
+
+    crate::services::auth::require_editor().await?;
+    let app_state = use_context::<AppState>()
+        .expect("failed to get AppState from context");
+
+    interact(&app_state.pool, move |conn| {
+        diesel::delete(followed_channels::table.find(channel_id))
+            .execute(conn)
+    })
+    .await
+    .map_err(|e| AppError::Internal(e.to_string()))?;
+
+    Ok(())
+}
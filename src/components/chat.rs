@@ -15,19 +15,17 @@ cfg_if! {
         use anyhow::{anyhow, Error};
         use reqwest::Client;
         use regex::Regex;
-        use std::env;
         use std::pin::Pin;
         use std::task::{Context, Poll};
         use tokio::sync::mpsc;
         use futures::stream::{Stream, StreamExt};
-        use log::info;
-        use diesel::QueryDsl;
-        use diesel::RunQueryDsl;
-        use diesel::ExpressionMethods;
+        use log::{debug, trace};
 
-        use crate::database::db::establish_connection;
+        use crate::config::Config;
+        use crate::database::db::DbPool;
+        use crate::database::repository::MessageRepository;
         use crate::models::conversations::Message;
-        use crate::schema::messages;
+        use crate::services::rate_limit::estimate_tokens;
 
         pub struct SseStream {
             pub receiver: mpsc::Receiver<Result<Event, anyhow::Error>>,
@@ -56,18 +54,26 @@ cfg_if! {
         }
 
         impl AnthropicService {
-            pub fn new(model: String) -> Self {
-                let api_key = env::var("ANTHROPIC_API_KEY").expect("ANTHROPIC_API_KEY must be set.");
+            pub fn new(config: &Config, model: String) -> Result<Self, Error> {
+                let api_key = config
+                    .provider_key("anthropic")
+                    .ok_or_else(|| anyhow!("ANTHROPIC_API_KEY isn't set, anthropic models are off"))?
+                    .expose()
+                    .to_string();
                 let client = Client::new();
-                AnthropicService { client, api_key, model }
+                Ok(AnthropicService { client, api_key, model })
             }
 
-            pub async fn send_message(&self, thread_id: &str, tx: mpsc::Sender<Result<Event, anyhow::Error>>) -> Result<(), Error> {
-                info!("Sending message to OpenAI API");
-                info!("Current thread id: {}", thread_id.to_string());
+            /// Streams the reply into `tx`, returning roughly how many tokens the exchange used.
+            pub async fn send_message(&self, pool: &DbPool, thread_id: &str, tx: mpsc::Sender<Result<Event, anyhow::Error>>) -> Result<u64, Error> {
+                debug!("sending thread {} to {}", thread_id, "anthropic");
 
-                let history = fetch_message_history(thread_id).await?;
+                let history = fetch_message_history(pool, thread_id).await?;
         
+                let mut tokens = history.iter()
+                    .map(|msg| estimate_tokens(msg.content.as_deref().unwrap_or_default()))
+                    .sum::<u64>();
+
                 let api_messages = history.into_iter()
                     .map(|msg| serde_json::json!({
                         "role": msg.role,
@@ -99,18 +105,19 @@ cfg_if! {
                     match item {
                         Ok(bytes) => {
                             let event = String::from_utf8(bytes.to_vec()).map_err(|e| anyhow!("Failed to convert bytes to string: {}", e))?;
-                            info!("Trimmed event: {}", event.trim());
+                            trace!("stream event: {}", event.trim());
 
                             for line in event.trim().lines() {
                                 if line.trim() == "event: message_stop" {
-                                    info!("Received message_stop event");
+                                    debug!("received message_stop event");
                                     tx.send(Ok(Event::default().data("[DONE]"))).await.ok();
                                     break;
                                 } else if line.trim().starts_with("data: ") {
                                     let json_str = &line.trim()[6..];
                                     for cap in re.captures_iter(json_str) {
                                         let content = cap[1].to_string();
-                                        info!("Extracted content: {}", content);
+                                        trace!("stream content: {}", content);
+                                        tokens += estimate_tokens(&content);
                                         tx.send(Ok(Event::default().data(content))).await.ok();
                                     }
                                 }
@@ -124,24 +131,32 @@ cfg_if! {
                     }
                 }
 
-                info!("Stream closed");
-                Ok(())
+                debug!("stream closed after about {} tokens", tokens);
+                Ok(tokens)
             }
 
         }
 
         impl OpenAIService {
-            pub fn new(model: String) -> Self {
-                let api_key = env::var("OPENAI_API_KEY").expect("OPENAI_API_KEY must be set.");
+            pub fn new(config: &Config, model: String) -> Result<Self, Error> {
+                let api_key = config
+                    .provider_key("openai")
+                    .ok_or_else(|| anyhow!("OPENAI_API_KEY isn't set, openai models are off"))?
+                    .expose()
+                    .to_string();
                 let client = Client::new();
-                OpenAIService { client, api_key, model }
+                Ok(OpenAIService { client, api_key, model })
             }
 
-            pub async fn send_message(&self, thread_id: &str, tx: mpsc::Sender<Result<Event, anyhow::Error>>) -> Result<(), Error> {
-                info!("Sending message to OpenAI API");
-                info!("Current thread id: {}", thread_id.to_string());
+            /// Streams the reply into `tx`, returning roughly how many tokens the exchange used.
+            pub async fn send_message(&self, pool: &DbPool, thread_id: &str, tx: mpsc::Sender<Result<Event, anyhow::Error>>) -> Result<u64, Error> {
+                debug!("sending thread {} to {}", thread_id, "openai");
+
+                let history = fetch_message_history(pool, thread_id).await?;
 
-                let history = fetch_message_history(thread_id).await?;
+                let mut tokens = history.iter()
+                    .map(|msg| estimate_tokens(msg.content.as_deref().unwrap_or_default()))
+                    .sum::<u64>();
 
                 let api_messages = history.into_iter()
                     .map(|msg| serde_json::json!({
@@ -172,18 +187,19 @@ cfg_if! {
                     match item {
                         Ok(bytes) => {
                             let event = String::from_utf8(bytes.to_vec()).map_err(|e| anyhow!("Failed to convert bytes to string: {}", e))?;
-                            info!("Trimmed event: {}", event.trim());
+                            trace!("stream event: {}", event.trim());
 
                             for line in event.trim().lines() {
                                 if line.trim() == "data: [DONE]" {
-                                    info!("Received [DONE] event");
+                                    debug!("received [DONE] event");
                                     tx.send(Ok(Event::default().data("[DONE]"))).await.ok();
                                     break;
                                 } else if line.trim().starts_with("data: ") {
                                     let json_str = &line.trim()[6..];
                                     for cap in re.captures_iter(json_str) {
                                         let content = cap[1].to_string();
-                                        info!("Extracted content: {}", content);
+                                        trace!("stream content: {}", content);
+                                        tokens += estimate_tokens(&content);
                                         tx.send(Ok(Event::default().data(content))).await.ok();
                                     }
                                 }
@@ -197,55 +213,43 @@ cfg_if! {
                     }
                 }
 
-                info!("Stream closed");
-                Ok(())
+                debug!("stream closed after about {} tokens", tokens);
+                Ok(tokens)
             }
         }
 
-        pub async fn fetch_message_history(thread_id: &str) -> Result<Vec<Message>, Error> {
-            let database_url = std::env::var("DATABASE_URL").expect("DATABASE_URL must be set");
-            let pool = establish_connection(&database_url);
-            let conn = pool
-                .get()
-                .await
-                .map_err(|e| Error::msg(format!("Failed to get database connection: {:?}", e)))?;
-
-            let thread_id = thread_id.to_string();
-            let messages_result = conn
-                .interact(move |conn| {
-                    messages::table
-                        .filter(messages::thread_id.eq(thread_id))
-                        .load::<Message>(conn)
-                })
+        pub async fn fetch_message_history(pool: &DbPool, thread_id: &str) -> Result<Vec<Message>, Error> {
+            MessageRepository::new(pool.clone())
+                .for_thread(thread_id)
                 .await
-                .map_err(|e| Error::msg(format!("Database interaction error: {:?}", e)))?;
-
-            match messages_result {
-                Ok(msgs) => Ok(msgs),
-                Err(e) => Err(Error::msg(format!("Failed to fetch messages: {:?}", e))),
-            }
+                .map_err(|e| Error::msg(format!("Failed to fetch messages: {}", e)))
         }
 
-        pub async fn send_message_stream(thread_id: String, model: String, active_lab: String, tx: mpsc::Sender<Result<Event, anyhow::Error>>) {
+        /// Runs the reply for a thread, returning about how many tokens it cost.
+        #[tracing::instrument(name = "llm_stream", skip(pool, config, tx), fields(tokens = tracing::field::Empty))]
+        pub async fn send_message_stream(pool: DbPool, config: &Config, thread_id: String, model: String, active_lab: String, tx: mpsc::Sender<Result<Event, anyhow::Error>>) -> u64 {
             let decoded_thread_id = urlencoding::decode(&thread_id).expect("Failed to decode thread_id");
             let decoded_model = urlencoding::decode(&model).expect("Failed to decode model");
             let decoded_lab = urlencoding::decode(&active_lab).expect("failed to decode lab");
 
             let result = match decoded_lab.as_ref() {
-                "anthropic" => {
-                    let anthropic_service = AnthropicService::new(decoded_model.into_owned());
-                    anthropic_service.send_message(&decoded_thread_id, tx.clone()).await
+                "anthropic" => match AnthropicService::new(config, decoded_model.into_owned()) {
+                    Ok(anthropic_service) => anthropic_service.send_message(&pool, &decoded_thread_id, tx.clone()).await,
+                    Err(e) => Err(e),
                 },
-                "openai" => {
-                let openai_service = OpenAIService::new(decoded_model.into_owned());
-                openai_service.send_message(&decoded_thread_id, tx.clone()).await
+                "openai" => match OpenAIService::new(config, decoded_model.into_owned()) {
+                    Ok(openai_service) => openai_service.send_message(&pool, &decoded_thread_id, tx.clone()).await,
+                    Err(e) => Err(e),
                 },
                 _ => Err(anyhow::anyhow!("unsupported lab: {}", decoded_lab)),
             };
 
-            if let Err(e) = result {
+            let tokens = result.unwrap_or_else(|e| {
                 error!("Error in send_message_stream: {}", e);
-            }
+                0
+            });
+            tracing::Span::current().record("tokens", tokens);
+            tokens
         }
     }
 }
@@ -419,72 +423,22 @@ view! {
 
 #[server(CreateMessage, "/api")]
 pub async fn create_message(new_message_view: NewMessageView, is_llm: bool) -> Result<(), ServerFnError> {
-    use diesel::prelude::*;
-    use std::fmt;
-
     use crate::state::AppState;
-    use crate::models::conversations::{NewMessage, Thread};
-    use crate::schema::{messages, threads};
-
-    #[derive(Debug)]
-    enum CreateMessageError {
-        PoolError(String),
-        InteractionError(String),
-    }
-
-    impl fmt::Display for CreateMessageError {
-        fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
-            match self {
-                CreateMessageError::PoolError(e) => write!(f, "Pool error: {}", e),
-                CreateMessageError::InteractionError(e) => write!(f, "Interaction error: {}", e),
-            }
-        }
-    }
+    use crate::models::conversations::NewMessage;
 
-    impl From<CreateMessageError> for ServerFnError {
-        fn from(error: CreateMessageError) -> Self {
-            ServerFnError::ServerError(error.to_string())
-        }
-    }
+    let user = crate::services::auth::require_user().await?;
 
     let app_state = use_context::<AppState>()
         .expect("Failed to get AppState from context");
 
-    let pool = app_state.pool;
-
-    let conn = pool
-        .get()
-        .await
-        .map_err(|e| CreateMessageError::PoolError(e.to_string()))?;
-
-    conn.interact(move |conn| {
-        let new_message: NewMessage = new_message_view.into();
-
-        if !is_llm {
-            let thread_id = &new_message.thread_id;
-            if threads::table.find(thread_id).first::<Thread>(conn).optional()?.is_none() {
-                let new_thread = Thread {
-                    id: thread_id.clone(),
-                    created_at: None,
-                    updated_at: None,
-                    title: None, 
-                };
-                diesel::insert_into(threads::table)
-                    .values(&new_thread)
-                    .execute(conn)?;
-            }
-        }
+    let new_message: NewMessage = new_message_view.into();
 
-        diesel::insert_into(messages::table)
-            .values(&new_message)
-            .execute(conn)?;
+    // only the user's own turn can open a thread; a reply needs one to land in
+    app_state.messages().add(user.fid as i64, new_message.clone(), !is_llm).await?;
 
-        if !is_llm {
-            info!("Message successfully inserted into the database: {:?}", new_message);
-        }
-
-        Ok::<(), diesel::result::Error>(())
-    }).await.map_err(|e| CreateMessageError::InteractionError(e.to_string()))??;
+    if !is_llm {
+        debug!("added a message to thread {}", new_message.thread_id);
+    }
 
     Ok(())
 }
@@ -0,0 +1,41 @@
+use chrono::{DateTime, Utc};
+use leptos::*;
+use std::time::Duration;
+use crate::time::{relative_time, short_date, FarcasterTimestamp};
+
+/// "now" for relative times. It stays `None` through server rendering and
+/// hydration so both produce the same absolute dates, then starts ticking once
+/// the app is running in the browser.
+#[derive(Clone, Copy)]
+pub struct Clock(pub ReadSignal<Option<DateTime<Utc>>>);
+
+pub fn provide_clock() {
+    let (now, set_now) = create_signal(None::<DateTime<Utc>>);
+
+    // effects only run in the browser, after hydration
+    create_effect(move |_| {
+        set_now.set(Some(Utc::now()));
+        let _ = set_interval_with_handle(move || set_now.set(Some(Utc::now())), Duration::from_secs(30));
+    });
+
+    provide_context(Clock(now));
+}
+
+#[component]
+pub fn RelativeTime(timestamp: FarcasterTimestamp) -> impl IntoView {
+    let Clock(now) = use_context::<Clock>().expect("Clock should be provided");
+    let datetime = timestamp.to_datetime();
+
+    view! {
+        <time
+            datetime=datetime.to_rfc3339()
+            title=datetime.format("%Y-%m-%d %H:%M utc").to_string()
+            class="text-xs text-gray-500 dark:text-gray-400"
+        >
+            {move || match now.get() {
+                Some(now) => relative_time(datetime, now),
+                None => short_date(datetime),
+            }}
+        </time>
+    }
+}
@@ -1,50 +1,30 @@
 use leptos::*;
-use web_sys::{window, Storage};
 
-fn get_local_storage() -> Storage {
-    window()
-        .expect("Failed to get window")
-        .local_storage()
-        .expect("Failed to get local storage")
-        .expect("Local storage is not available")
-}
-
-fn get_item(key: &str) -> Option<String> {
-    get_local_storage().get_item(key).ok().flatten()
-}
-
-fn set_item(key: &str, value: &str) {
-    get_local_storage().set_item(key, value).expect("Failed to set item in local storage");
-}
+use crate::components::auth::use_session;
+use crate::components::preferences::{update_user_settings, use_preferences};
+use crate::models::settings::{Theme, UserSettingsView};
 
 #[component]
 pub fn DarkModeToggle() -> impl IntoView {
-    let (is_dark, set_is_dark) = create_signal(false);
-
-    create_effect(move |_| {
-        let is_dark_mode = get_item("darkMode").map(|v| v == "true").unwrap_or(false);
-        set_is_dark.set(is_dark_mode);
-        let window = window().expect("Failed to get window");
-        let document = window.document().expect("Failed to get document");
-        let body = document.body().expect("Failed to get body");
-        if is_dark_mode {
-            body.class_list().add_1("dark").expect("Failed to add dark class");
-        } else {
-            body.class_list().remove_1("dark").expect("Failed to remove dark class");
-        }
-    });
+    let preferences = use_preferences();
+    let session = use_session();
 
     let toggle_dark_mode = move |_| {
-        let new_state = !is_dark.get();
-        set_is_dark.set(new_state);
-        set_item("darkMode", &new_state.to_string());
-        let window = window().expect("Failed to get window");
-        let document = window.document().expect("Failed to get document");
-        let body = document.body().expect("Failed to get body");
-        if new_state {
-            body.class_list().add_1("dark").expect("Failed to add dark class");
-        } else {
-            body.class_list().remove_1("dark").expect("Failed to remove dark class");
+        let theme = if preferences.is_dark() { Theme::Light } else { Theme::Dark };
+        preferences.set_theme(theme);
+
+        // signed in, so remember it on their account too
+        if matches!(untrack(|| session.user.get()), Some(Some(_))) {
+            let settings = UserSettingsView {
+                theme,
+                ..untrack(|| preferences.settings.get()).unwrap_or_default()
+            };
+            preferences.settings.set(settings.clone());
+            spawn_local(async move {
+                if let Err(e) = update_user_settings(settings).await {
+                    log::error!("failed to save theme: {:?}", e);
+                }
+            });
         }
     };
 
@@ -53,7 +33,7 @@ pub fn DarkModeToggle() -> impl IntoView {
             class="p-2 rounded bg-gray-200 dark:bg-teal-700 text-gray-800 dark:text-gray-200"
             on:click=toggle_dark_mode
         >
-            {move || if is_dark.get() {
+            {move || if preferences.is_dark() {
                 "🌞"
             } else {
                 "🌙"
@@ -0,0 +1,266 @@
+use leptos::*;
+use leptos_router::A;
+use crate::models::farcaster::{Cast, CastId, Embed, EmbedKind, LinkPreview, UserSummary};
+use crate::components::cache_provider::ClientCache;
+use crate::components::cast_entry::{page_fids, CastText};
+use crate::components::users::get_users;
+use crate::services::cast_text::tokenize_cast;
+
+#[component]
+pub fn EmbedView(
+    embed: Embed,
+    /// the cast the embed belongs to
+    parent: CastId,
+    #[prop(into)] on_image_click: Callback<String>,
+) -> impl IntoView {
+    match (embed.cast_id, embed.url) {
+        (Some(cast_id), _) => view! { <QuotedCast cast_id=cast_id/> }.into_view(),
+        (None, Some(url)) => view! { <UrlEmbed url=url parent=parent on_image_click=on_image_click/> }.into_view(),
+        (None, None) => ().into_view(),
+    }
+}
+
+#[component]
+fn UrlEmbed(
+    url: String,
+    parent: CastId,
+    on_image_click: Callback<String>,
+) -> impl IntoView {
+    let preview = create_resource(
+        {
+            let url = url.clone();
+            move || (parent.clone(), url.clone())
+        },
+        |(parent, url)| get_link_preview(parent.fid, parent.hash, url),
+    );
+
+    let fallback_url = url.clone();
+
+    view! {
+        <Suspense fallback=move || view! { <PlainLink url=fallback_url.clone()/> }>
+            {
+                let url = url.clone();
+                move || {
+                    let preview = match preview.get() {
+                        Some(Ok(preview)) => preview,
+                        Some(Err(_)) => LinkPreview::bare(url.clone(), EmbedKind::Link),
+                        None => return view! { <PlainLink url=url.clone()/> }.into_view(),
+                    };
+                    match preview.kind {
+                        EmbedKind::Image => {
+                            let image_url = preview.url.clone();
+                            view! {
+                                <img
+                                    src=preview.url
+                                    alt="cast image"
+                                    class="mt-2 max-w-sm h-auto rounded-lg cursor-pointer"
+                                    on:click=move |_| leptos::Callable::call(&on_image_click, image_url.clone())
+                                />
+                            }
+                                .into_view()
+                        }
+                        EmbedKind::Video => {
+                            view! {
+                                <video
+                                    src=preview.url
+                                    controls=true
+                                    preload="metadata"
+                                    class="mt-2 max-w-sm h-auto rounded-lg"
+                                ></video>
+                            }
+                                .into_view()
+                        }
+                        EmbedKind::Link => view! { <LinkCard preview=preview/> }.into_view(),
+                    }
+                }
+            }
+        </Suspense>
+    }
+}
+
+#[component]
+fn PlainLink(url: String) -> impl IntoView {
+    view! {
+        <a
+            href=url.clone()
+            target="_blank"
+            rel="noopener noreferrer"
+            class="mt-2 text-sm text-teal-600 dark:text-teal-400 hover:underline break-all"
+        >
+            {url}
+        </a>
+    }
+}
+
+#[component]
+fn LinkCard(preview: LinkPreview) -> impl IntoView {
+    if preview.title.is_none() && preview.image.is_none() {
+        return view! { <PlainLink url=preview.url/> }.into_view();
+    }
+
+    view! {
+        <a
+            href=preview.url.clone()
+            target="_blank"
+            rel="noopener noreferrer"
+            class="link-card mt-2 flex flex-col max-w-sm border border-teal-700 dark:border-teal-300 rounded-lg overflow-hidden hover:bg-gray-100 dark:hover:bg-teal-800 transition-colors duration-300"
+        >
+            {preview.image.map(|image| view! {
+                <img src=image alt="link preview" class="w-full max-h-48 object-cover"/>
+            })}
+            <div class="flex flex-col p-2 space-y-1">
+                {preview.site_name.map(|site| view! {
+                    <span class="text-xs text-gray-500 dark:text-gray-400">{site}</span>
+                })}
+                {preview.title.map(|title| view! {
+                    <span class="text-sm font-medium text-teal-700 dark:text-mint-400">{title}</span>
+                })}
+                {preview.description.map(|description| view! {
+                    <span class="text-xs text-gray-700 dark:text-gray-300 line-clamp-3">{description}</span>
+                })}
+            </div>
+        </a>
+    }
+        .into_view()
+}
+
+#[component]
+fn QuotedCast(cast_id: CastId) -> impl IntoView {
+    let quoted = create_resource(
+        move || cast_id.clone(),
+        |cast_id| async move {
+            let cast = get_quoted_cast(cast_id.fid, cast_id.hash).await?;
+            // the author and everyone mentioned, in one call
+            let users = get_users(page_fids([&cast]), vec![UserSummary::USERNAME, UserSummary::PFP])
+                .await
+                .unwrap_or_default();
+            Ok::<_, ServerFnError>((cast, users))
+        },
+    );
+
+    // rendered on the server, so these are known to the client cache without asking again
+    let client_cache = use_context::<RwSignal<ClientCache>>().expect("ClientCache should be provided");
+    create_effect(move |_| {
+        if let Some(Ok((_, users))) = quoted.get() {
+            let client_cache = client_cache.get_untracked();
+            users.iter().for_each(|user| client_cache.seed(user));
+        }
+    });
+
+    view! {
+        <Suspense fallback=move || view! { <p class="mt-2 text-xs text-gray-600 dark:text-gray-400">"loading quoted cast..."</p> }>
+            {move || quoted.get().map(|result| match result {
+                Ok((cast, users)) => {
+                    let author = users
+                        .iter()
+                        .find(|user| user.fid == cast.data.fid)
+                        .and_then(|author| author.username.clone())
+                        .unwrap_or_else(|| cast.data.fid.to_string());
+                    let usernames = users
+                        .into_iter()
+                        .filter_map(|user| user.username.map(|username| (user.fid, username)))
+                        .collect();
+                    view! {
+                        <div class="quoted-cast mt-2 max-w-sm p-3 border border-teal-700 dark:border-teal-300 rounded-lg">
+                            <A
+                                href=format!("/profile/{}", cast.data.fid)
+                                class="text-sm text-seafoam-600 dark:text-aqua-400 hover:underline"
+                            >
+                                {format!("@{}", author)}
+                            </A>
+                            {match cast.data.castAddBody {
+                                Some(body) => {
+                                    let segments = tokenize_cast(
+                                        &body.text.unwrap_or_default(),
+                                        &body.mentions,
+                                        &body.mentionsPositions,
+                                        &usernames,
+                                    );
+                                    view! { <CastText segments=segments/> }.into_view()
+                                }
+                                None => ().into_view(),
+                            }}
+                        </div>
+                    }
+                        .into_view()
+                }
+                Err(_) => view! {
+                    <p class="mt-2 text-xs text-gray-600 dark:text-gray-400">"quoted cast unavailable"</p>
+                }
+                    .into_view(),
+            })}
+        </Suspense>
+    }
+}
+
+/// longer than any link worth previewing; keeps junk out of the preview cache
+pub const MAX_PREVIEW_URL_LEN: usize = 2048;
+
+/// A preview of `url` as embedded in the cast `fid`/`hash`. Only links that
+/// really are embedded in that cast get fetched, so this can't be pointed at
+/// arbitrary addresses.
+#[server(GetLinkPreview, "/api")]
+pub async fn get_link_preview(fid: u64, hash: String, url: String) -> Result<LinkPreview, ServerFnError> {
+    use crate::error_template::AppError;
+    use crate::services::opengraph::fetch_link_preview;
+    use crate::state::AppState;
+
+    if url.len() > MAX_PREVIEW_URL_LEN {
+        return Err(AppError::Validation("link is too long to preview".to_string()).into());
+    }
+
+    let app_state = use_context::<AppState>().expect("Failed to get AppState from context");
+
+    let parent = load_cast(&app_state, fid, hash).await?;
+    let embedded = parent
+        .data
+        .castAddBody
+        .is_some_and(|body| body.embeds.iter().any(|embed| embed.url.as_deref() == Some(url.as_str())));
+    if !embedded {
+        return Err(AppError::NotFound("link embed".to_string()).into());
+    }
+
+    let preview = app_state
+        .caches
+        .link_previews
+        .get_or_load(&url, || fetch_link_preview(&url))
+        .await;
+
+    // a dead link still renders, just without the card
+    match preview {
+        Ok(Some(preview)) => Ok(preview),
+        Ok(None) => Ok(LinkPreview::bare(url, EmbedKind::Link)),
+        Err(e) => {
+            crate::log_warn!("failed to fetch link preview for {}: {}", url, e);
+            Ok(LinkPreview::bare(url, EmbedKind::Link))
+        }
+    }
+}
+
+#[server(GetQuotedCast, "/api")]
+pub async fn get_quoted_cast(fid: u64, hash: String) -> Result<Cast, ServerFnError> {
+    use crate::state::AppState;
+
+    let app_state = use_context::<AppState>().expect("Failed to get AppState from context");
+    Ok(load_cast(&app_state, fid, hash).await?)
+}
+
+#[cfg(feature = "ssr")]
+async fn load_cast(app_state: &crate::state::AppState, fid: u64, hash: String) -> Result<Cast, crate::error_template::AppError> {
+    use crate::services::hubble::{found, get_cast_by_id};
+    use axum::extract::{Path, State};
+    use crate::error_template::AppError;
+
+    let cast = app_state
+        .caches
+        .casts
+        .get_or_load(&format!("{}:{}", fid, hash), || async {
+            found(get_cast_by_id(State(app_state.config.clone()), Path((fid, hash.clone()))).await)?
+                .map(|json| serde_json::from_value::<Cast>(json.0))
+                .transpose()
+                .map_err(|e| AppError::Upstream(format!("unreadable cast: {}", e)))
+        })
+        .await?;
+
+    cast.ok_or_else(|| AppError::NotFound("cast".to_string()))
+}
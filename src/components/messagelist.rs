@@ -133,53 +133,14 @@ pub fn MessageList(
 
 #[server(GetMessages, "/api")]
 pub async fn get_messages() -> Result<Vec<MessageView>, ServerFnError> {
-    use diesel::prelude::*;
-    use std::fmt;
-
     use crate::state::AppState;
-    use crate::models::conversations::Message;
-    use crate::schema::messages::dsl::messages as messages_table;
-
-    #[derive(Debug)]
-    enum MessageError {
-        Pool(String),
-        Database(diesel::result::Error),
-        Interaction(String),
-    }
-
-    impl fmt::Display for MessageError {
-        fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
-            match self {
-                MessageError::Pool(e) => write!(f, "Pool error: {}", e),
-                MessageError::Database(e) => write!(f, "Database error: {}", e),
-                MessageError::Interaction(e) => write!(f, "Interaction error: {}", e),
-            }
-        }
-    }
 
-    fn to_server_error(e: MessageError) -> ServerFnError {
-        ServerFnError::ServerError(e.to_string())
-    }
+    let user = crate::services::auth::require_user().await?;
 
     let app_state = use_context::<AppState>()
         .expect("Failed to get AppState from context");
 
-    let pool = app_state.pool;
-
-    let conn = pool
-        .get()
-        .await
-        .map_err(|e| MessageError::Pool(e.to_string()))
-        .map_err(to_server_error)?;
+    let messages = app_state.messages().for_owner(user.fid as i64).await?;
 
-    let result = conn
-        .interact(|conn| messages_table.load::<Message>(conn))
-        .await
-        .map_err(|e| MessageError::Interaction(e.to_string()))
-        .map_err(to_server_error)?
-        .map_err(MessageError::Database)
-        .map_err(to_server_error)?;
-
-    Ok(result.into_iter().map(MessageView::from).collect())
+    Ok(messages.into_iter().map(MessageView::from).collect())
 }
-
@@ -1,12 +1,20 @@
+pub mod auth;
 pub mod cache_provider;
 pub mod cast_entry;
 pub mod cast_list;
 pub mod channels;
 pub mod chat;
+pub mod clock;
 pub mod code_block;
+pub mod embed;
 pub mod messagelist;
 pub mod navbar;
+pub mod preferences;
 pub mod profile;
+pub mod publish;
+pub mod search;
 pub mod threadlist;
+pub mod timeline;
 pub mod toast;
+pub mod users;
 pub mod dark_mode_toggle;
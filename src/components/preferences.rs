@@ -0,0 +1,224 @@
+use cfg_if::cfg_if;
+use leptos::*;
+use crate::components::auth::use_session;
+use crate::models::settings::{Theme, UserSettingsView};
+
+/// Mirrors the saved theme so the server can render the right one before
+/// anything has loaded. Not `HttpOnly`: the toggle writes it from the browser too.
+pub const THEME_COOKIE: &str = "nwt_theme";
+pub const THEME_COOKIE_MAX_AGE: u64 = 60 * 60 * 24 * 365;
+
+/// Finds `name` in a `Cookie` header (or `document.cookie`) style string.
+pub fn cookie_value<'a>(cookies: &'a str, name: &str) -> Option<&'a str> {
+    cookies
+        .split(';')
+        .filter_map(|pair| pair.trim().split_once('='))
+        .find(|(key, _)| *key == name)
+        .map(|(_, value)| value)
+}
+
+/// `Set-Cookie` value for the theme cookie, also what the browser assigns to `document.cookie`.
+pub fn theme_cookie(theme: Theme) -> String {
+    format!("{}={}; Path=/; Max-Age={}; SameSite=Lax", THEME_COOKIE, theme.as_str(), THEME_COOKIE_MAX_AGE)
+}
+
+cfg_if! {
+    if #[cfg(feature = "ssr")] {
+        fn request_theme() -> Theme {
+            use_context::<http::request::Parts>()
+                .and_then(|parts| {
+                    parts
+                        .headers
+                        .get_all(http::header::COOKIE)
+                        .iter()
+                        .filter_map(|value| value.to_str().ok())
+                        .find_map(|cookies| cookie_value(cookies, THEME_COOKIE).map(Theme::parse))
+                })
+                .unwrap_or_default()
+        }
+
+        fn store_theme(_theme: Theme) {}
+
+        fn system_prefers_dark() -> bool {
+            false
+        }
+    } else if #[cfg(target_arch = "wasm32")] {
+        use wasm_bindgen::JsCast;
+        use web_sys::HtmlDocument;
+
+        fn html_document() -> Option<HtmlDocument> {
+            web_sys::window()?.document()?.dyn_into::<HtmlDocument>().ok()
+        }
+
+        fn request_theme() -> Theme {
+            html_document()
+                .and_then(|document| document.cookie().ok())
+                .and_then(|cookies| cookie_value(&cookies, THEME_COOKIE).map(Theme::parse))
+                .unwrap_or_default()
+        }
+
+        fn store_theme(theme: Theme) {
+            if let Some(document) = html_document() {
+                let _ = document.set_cookie(&theme_cookie(theme));
+            }
+        }
+
+        fn system_prefers_dark() -> bool {
+            web_sys::window()
+                .and_then(|window| window.match_media("(prefers-color-scheme: dark)").ok().flatten())
+                .map(|query| query.matches())
+                .unwrap_or(false)
+        }
+    } else {
+        fn request_theme() -> Theme {
+            Theme::System
+        }
+
+        fn store_theme(_theme: Theme) {}
+
+        fn system_prefers_dark() -> bool {
+            false
+        }
+    }
+}
+
+/// Settings for whoever is signed in, or the defaults for everyone else.
+#[server(GetUserSettings, "/api")]
+pub async fn get_user_settings() -> Result<UserSettingsView, ServerFnError> {
+    use crate::database::db::interact;
+    use crate::error_template::AppError;
+    use crate::database::settings;
+    use crate::state::AppState;
+
+    let Some(user) = crate::services::auth::current_user().await else {
+        return Ok(UserSettingsView::default());
+    };
+
+    let app_state = use_context::<AppState>()
+        .expect("failed to get AppState from context");
+
+    let saved = interact(&app_state.pool, move |conn| settings::get_user_settings(conn, user.fid as i64))
+        .await
+        .map_err(|e| AppError::Internal(format!("failed to load settings: {}", e)))?;
+
+    Ok(saved.map(UserSettingsView::from).unwrap_or_default())
+}
+
+#[server(UpdateUserSettings, "/api")]
+pub async fn update_user_settings(settings: UserSettingsView) -> Result<UserSettingsView, ServerFnError> {
+    use chrono::Utc;
+    use http::header::{HeaderValue, SET_COOKIE};
+    use crate::database::db::interact;
+    use crate::error_template::AppError;
+    use crate::database::settings::upsert_user_settings;
+    use crate::models::settings::UserSettings;
+    use crate::state::AppState;
+
+    let user = crate::services::auth::require_user().await?;
+    settings.validate().map_err(AppError::Validation)?;
+
+    let app_state = use_context::<AppState>()
+        .expect("failed to get AppState from context");
+
+    let row = UserSettings::from_view(user.fid as i64, settings.clone(), Utc::now().naive_utc());
+    interact(&app_state.pool, move |conn| upsert_user_settings(conn, &row))
+        .await
+        .map_err(|e| AppError::Internal(format!("failed to save settings: {}", e)))?;
+
+    if let (Some(response), Ok(cookie)) = (
+        use_context::<leptos_axum::ResponseOptions>(),
+        HeaderValue::from_str(&theme_cookie(settings.theme)),
+    ) {
+        response.append_header(SET_COOKIE, cookie);
+    }
+
+    Ok(settings)
+}
+
+#[derive(Clone, Copy)]
+pub struct Preferences {
+    /// refetched whenever someone signs in or out
+    pub settings: Resource<Option<u64>, UserSettingsView>,
+    pub theme: RwSignal<Theme>,
+    prefers_dark: RwSignal<bool>,
+}
+
+impl Preferences {
+    pub fn is_dark(&self) -> bool {
+        match self.theme.get() {
+            Theme::Dark => true,
+            Theme::Light => false,
+            Theme::System => self.prefers_dark.get(),
+        }
+    }
+
+    pub fn set_theme(&self, theme: Theme) {
+        self.theme.set(theme);
+        store_theme(theme);
+    }
+
+    /// Runs `apply` once, when settings first arrive. For pages that start from
+    /// the saved defaults but let the user change them afterwards.
+    pub fn on_first_load(&self, apply: impl Fn(&UserSettingsView) + 'static) {
+        let settings = self.settings;
+        create_effect(move |applied: Option<bool>| {
+            if applied == Some(true) {
+                return true;
+            }
+            settings.with(|settings| settings.as_ref().map(&apply).is_some())
+        });
+    }
+}
+
+/// Needs the session, so call it after `provide_session`.
+pub fn provide_preferences() -> Preferences {
+    let session = use_session();
+    let settings = create_resource(
+        move || session.user.get().flatten().map(|user| user.fid),
+        |_| async move { get_user_settings().await.unwrap_or_default() },
+    );
+    let preferences = Preferences {
+        settings,
+        theme: create_rw_signal(request_theme()),
+        prefers_dark: create_rw_signal(false),
+    };
+
+    // the server can't know what the browser prefers, so `system` resolves here
+    create_effect(move |_| preferences.prefers_dark.set(system_prefers_dark()));
+
+    // a signed in user's saved theme wins over whatever the cookie said
+    create_effect(move |_| {
+        let signed_in = session.user.with(|user| matches!(user, Some(Some(_))));
+        if let (true, Some(saved)) = (signed_in, settings.get()) {
+            if saved.theme != preferences.theme.get_untracked() {
+                preferences.set_theme(saved.theme);
+            }
+        }
+    });
+
+    provide_context(preferences);
+    preferences
+}
+
+pub fn use_preferences() -> Preferences {
+    expect_context::<Preferences>()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn finds_cookies_by_name() {
+        assert_eq!(cookie_value("nwt_session=abc; nwt_theme=dark", THEME_COOKIE), Some("dark"));
+        assert_eq!(cookie_value("nwt_theme_old=light", THEME_COOKIE), None);
+        assert_eq!(cookie_value("", THEME_COOKIE), None);
+    }
+
+    #[test]
+    fn theme_cookie_round_trips() {
+        let cookie = theme_cookie(Theme::Light);
+        let value = cookie_value(cookie.split(';').next().unwrap(), THEME_COOKIE);
+        assert_eq!(value.map(Theme::parse), Some(Theme::Light));
+    }
+}
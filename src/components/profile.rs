@@ -1,59 +1,127 @@
 use leptos::*;
 use leptos_router::*;
-use crate::models::farcaster::UserDataResponse;
+use serde::{Deserialize, Serialize};
+use crate::models::farcaster::{Cast, CastResponse, ProfileData, UserNameProof};
+use crate::components::cache_provider::ClientCache;
+use crate::components::cast_entry::{page_fids, CastEntry};
+use crate::components::users::prefetch_users;
 
-#[server(GetProfile, "/api")]
-pub async fn get_profile(fid: u64, user_data_type: u8) -> Result<UserDataResponse, ServerFnError> {
-    use crate::services::hubble::{UserDataParams, get_user_data_by_fid};
-    use axum::extract::Query;
-    use log::{info, error};
-    use std::fmt;
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum ProfileTab {
+    Casts,
+    Mentions,
+}
 
-    #[derive(Debug)]
-    enum UserDataError {
-        FetchError(String),
-        ParseError(String),
-    }
-    
-    impl fmt::Display for UserDataError {
-        fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
-            match self {
-                UserDataError::FetchError(e) => write!(f, "fetch error: {}", e),
-                UserDataError::ParseError(e) => write!(f, "parse error: {}", e),
-            }
+impl ProfileTab {
+    fn label(&self) -> &'static str {
+        match self {
+            ProfileTab::Casts => "casts",
+            ProfileTab::Mentions => "mentions",
         }
     }
-    
-    fn to_server_error(e: UserDataError) -> ServerFnError {
-        ServerFnError::ServerError(e.to_string())
-    }
+}
 
-    info!("getting profile for fid {} and user data type {}", fid, user_data_type);
+#[server(GetProfile, "/api")]
+pub async fn get_profile(fid: u64) -> Result<ProfileData, ServerFnError> {
+    use crate::services::hubble::{UserDataParams, found, get_user_data_by_fid, get_username_proofs_by_fid};
+    use crate::models::farcaster::{UserDataListResponse, UserNameProofsResponse};
+    use crate::state::AppState;
+    use axum::extract::{Path, Query, State};
+    use log::{info, error};
+    use crate::error_template::AppError;
 
-    let params = UserDataParams {
-        fid,
-        user_data_type: Some(user_data_type.to_string()),
-    };
+    info!("getting profile for fid {}", fid);
 
-    match get_user_data_by_fid(Query(params)).await {
-        Ok(json) => {
-            info!("successfully fetched user data");
-            match serde_json::from_value::<UserDataResponse>(json.0) {
-                Ok(data) => {
-                    info!("Successfully parsed user data");
-                    Ok(data)
-                },
-                Err(e) => {
-                    error!("failed to parse user data: {:?}", e);
-                    Err(to_server_error(UserDataError::ParseError(format!("failed to parse user data: {:?}", e))))
-                },
+    let app_state = use_context::<AppState>().expect("Failed to get AppState from context");
+
+    let profile = app_state.caches.profiles.get_or_load(&fid, || async {
+        // leaving user_data_type out gets every type for the fid in one request
+        let params = UserDataParams {
+            fid,
+            user_data_type: None,
+        };
+
+        let (user_data, proofs) = futures::join!(
+            get_user_data_by_fid(State(app_state.config.clone()), Query(params)),
+            get_username_proofs_by_fid(State(app_state.config.clone()), Path(fid)),
+        );
+
+        let Some(user_data) = found(user_data)
+            .and_then(|json| {
+                json.map(|json| serde_json::from_value::<UserDataListResponse>(json.0))
+                    .transpose()
+                    .map_err(|e| AppError::Upstream(format!("unreadable user data: {}", e)))
+            })
+            .map_err(|e| {
+                error!("failed to load user data: {}", e);
+                e
+            })?
+        else {
+            return Ok(None);
+        };
+
+        // a missing proof list shouldn't take the whole profile down with it
+        let proofs = match proofs {
+            Ok(json) => serde_json::from_value::<UserNameProofsResponse>(json.0)
+                .map(|response| response.proofs)
+                .unwrap_or_else(|e| {
+                    error!("failed to parse username proofs: {:?}", e);
+                    Vec::new()
+                }),
+            Err(e) => {
+                error!("failed to fetch username proofs: {:?}", e);
+                Vec::new()
             }
-        },
-        Err(e) => {
-            error!("failed to fetch user data: {:?}", e);
-            Err(to_server_error(UserDataError::FetchError(format!("failed to fetch user data: {:?}", e))))
-        },
-    }
+        };
+
+        info!("successfully fetched profile for fid {}", fid);
+        Ok::<_, AppError>(Some(ProfileData::from_user_data(fid, user_data.messages, proofs)))
+    })
+    .await?;
+
+    profile.ok_or_else(|| AppError::NotFound(format!("fid {}", fid)).into())
+}
+
+#[server(GetProfileCasts, "/api")]
+pub async fn get_profile_casts(
+    fid: u64,
+    tab: ProfileTab,
+    page_token: Option<String>,
+    page_size: u32,
+) -> Result<CastResponse, ServerFnError> {
+    use crate::services::hubble::{PageParams, get_casts_by_fid, get_casts_by_mention};
+    use crate::state::AppState;
+    use axum::extract::{Path, Query, State};
+    use crate::error_template::AppError;
+
+    let app_state = use_context::<AppState>().expect("Failed to get AppState from context");
+
+    let page_token = page_token.filter(|token| !token.is_empty());
+    let cache_key = format!("{}:{}:{}:{}", tab.label(), fid, page_token.as_deref().unwrap_or_default(), page_size);
+
+    let response = app_state.caches.cast_pages.get_or_load(&cache_key, || async {
+        let page = PageParams {
+            page_size: Some(page_size),
+            page_token,
+            reverse: Some(true),
+        };
+
+        let casts_response = match tab {
+            ProfileTab::Casts => get_casts_by_fid(State(app_state.config.clone()), Path(fid), Query(page)).await,
+            ProfileTab::Mentions => get_casts_by_mention(State(app_state.config.clone()), Path(fid), Query(page)).await,
+        }?;
+
+        let mut response: CastResponse = serde_json::from_value(casts_response.0)
+            .map_err(|e| AppError::Upstream(format!("unreadable {}: {}", tab.label(), e)))?;
+
+        // hubble hands back an empty token on the last page
+        response.next_page_token = response.next_page_token.filter(|token| !token.is_empty());
+
+        Ok::<_, AppError>(Some(response))
+    })
+    .await?;
+
+    response.ok_or_else(|| AppError::NotFound(tab.label().to_string()).into())
 }
 
 #[component]
@@ -62,33 +130,216 @@ pub fn Profile() -> impl IntoView {
     let fid = create_memo(move |_| {
         params.with(|params| params.get("id").cloned().unwrap_or_default().parse::<u64>().unwrap_or(0))
     });
+    let (tab, set_tab) = create_signal(ProfileTab::Casts);
 
-    let user_data: Resource<u64, Result<(UserDataResponse, UserDataResponse, UserDataResponse), ServerFnError>> = create_resource(
-        move || fid(),
-        |fid| async move {
-            let username = get_profile(fid, 6).await?;
-            let pfp = get_profile(fid, 1).await?;
-            let bio = get_profile(fid, 3).await?;
-            Ok((username, pfp, bio))
+    let profile = create_resource(fid, get_profile);
+
+    // the profile came down with the page, so their casts can skip asking for it
+    let client_cache = use_context::<RwSignal<ClientCache>>().expect("ClientCache should be provided");
+    create_effect(move |_| {
+        if let Some(Ok(profile)) = profile.get() {
+            client_cache.get_untracked().seed(&profile.summary());
         }
-    );
+    });
 
     view! {
-        <Suspense fallback=|| view! { <div class="text-3xl text-ucla-blue-700">"loading..."</div> }>
-            {move || match user_data.get() {
-                None => view! { <div class="text-celestial-blue-500">"loading..."</div> },
-                Some(Ok((username, pfp, bio))) => view! {
-                    <div class="flex flex-col items-center justify-center pt-4">
-                        <div class="flex flex-row items-center justify-center space-x-4">
-                            <img src={pfp.data.user_data_body.value} alt="pfp" class="profile-pic w-12 h-12 rounded-full" />
-                            <span class="ib text-base text-ucla-blue-700">{"@"}{username.data.user_data_body.value}</span>
+        <div class="profile-container w-11/12 lg:w-8/12 xl:w-5/12 mx-auto">
+            <Suspense fallback=|| view! { <div class="text-3xl text-ucla-blue-700">"loading..."</div> }>
+                {move || match profile.get() {
+                    None => view! { <div class="text-celestial-blue-500">"loading..."</div> },
+                    Some(Ok(profile)) => view! {
+                        <div class="flex flex-col items-center justify-center pt-4">
+                            <div class="flex flex-row items-center justify-center space-x-4">
+                                <img src={profile.pfp.unwrap_or_default()} alt="pfp" class="profile-pic w-12 h-12 rounded-full" />
+                                <div class="flex flex-col items-start">
+                                    {profile.display_name.map(|name| view! {
+                                        <span class="ib text-lg text-teal-700 dark:text-mint-400">{name}</span>
+                                    })}
+                                    <span class="ib text-base text-ucla-blue-700">{"@"}{profile.username.unwrap_or_default()}</span>
+                                </div>
+                            </div>
+                            <span class="ib text-lg text-dark-purple-600 pt-4">{profile.bio.unwrap_or_default()}</span>
+                            {profile.url.map(|url| view! {
+                                <a
+                                    href=url.clone()
+                                    target="_blank"
+                                    rel="noopener noreferrer"
+                                    class="text-sm text-teal-600 dark:text-teal-400 hover:underline pt-2"
+                                >
+                                    {url}
+                                </a>
+                            })}
+                            <ProofList proofs=profile.proofs/>
                         </div>
-                        <span class="ib text-lg text-dark-purple-600 pt-4">{bio.data.user_data_body.value}</span>
-                    </div>
-                },
-                Some(Err(_)) => view! { <div class="text-celestial-blue-500">"Error loading user data"</div> },
-            }}
-        </Suspense>
+                    },
+                    Some(Err(_)) => view! { <div class="text-celestial-blue-500">"Error loading user data"</div> },
+                }}
+            </Suspense>
+
+            <div class="profile-tabs flex flex-row justify-center space-x-4 pt-6 pb-4">
+                {[ProfileTab::Casts, ProfileTab::Mentions]
+                    .into_iter()
+                    .map(|t| {
+                        view! {
+                            <button
+                                class=move || format!(
+                                    "px-4 py-2 rounded-md transition-colors duration-300 {}",
+                                    if tab.get() == t {
+                                        "bg-seafoam-600 dark:bg-teal-600 text-white"
+                                    } else {
+                                        "bg-gray-200 dark:bg-teal-800 text-teal-600 dark:text-mint-400 hover:bg-gray-300 dark:hover:bg-teal-700"
+                                    }
+                                )
+                                on:click=move |_| set_tab(t)
+                            >
+                                {t.label()}
+                            </button>
+                        }
+                    })
+                    .collect::<Vec<_>>()}
+            </div>
+
+            <ProfileCastList fid=fid tab=tab/>
+        </div>
+    }
+}
+
+#[component]
+fn ProofList(proofs: Vec<UserNameProof>) -> impl IntoView {
+    if proofs.is_empty() {
+        return view! { <div></div> };
+    }
+
+    view! {
+        <div class="proof-list flex flex-row flex-wrap justify-center gap-2 pt-4">
+            {proofs
+                .into_iter()
+                .map(|proof| {
+                    let kind = if proof.is_ens() { "ens" } else { "fname" };
+                    view! {
+                        <span
+                            class="text-xs px-2 py-1 rounded-md bg-gray-200 dark:bg-teal-800 text-teal-700 dark:text-mint-300"
+                            title=format!("owner: {}", proof.owner)
+                        >
+                            {"✓ "}{proof.name}{" · "}{kind}
+                        </span>
+                    }
+                })
+                .collect::<Vec<_>>()}
+        </div>
     }
 }
 
+#[component]
+fn ProfileCastList(
+    #[prop(into)] fid: Signal<u64>,
+    tab: ReadSignal<ProfileTab>,
+) -> impl IntoView {
+    let (cast_list, set_cast_list) = create_signal(Vec::<Cast>::new());
+    let (next_page_token, set_next_page_token) = create_signal(None::<String>);
+    let (error, set_error) = create_signal(None::<String>);
+    let (is_loading, set_is_loading) = create_signal(false);
+    let (has_more, set_has_more) = create_signal(true);
+    let page_size = 10u32;
+    let client_cache = use_context::<RwSignal<ClientCache>>().expect("ClientCache should be provided");
+    // bumped whenever the list is reset, so a response for the profile or tab
+    // being left behind is dropped instead of appended
+    let generation = store_value(0u64);
+
+    let fetch_casts = create_action(move |_: &()| {
+        let current_fid = fid.get_untracked();
+        let current_tab = tab.get_untracked();
+        let page_token = next_page_token.get_untracked();
+        let client_cache = client_cache.get_untracked();
+        let request_generation = generation.get_value();
+        async move {
+            set_is_loading.set(true);
+            let result = get_profile_casts(current_fid, current_tab, page_token, page_size).await;
+            if let Ok(response) = &result {
+                prefetch_users(&client_cache, page_fids(&response.messages)).await;
+            }
+            if generation.get_value() != request_generation {
+                return;
+            }
+            match result {
+                Ok(response) => {
+                    set_has_more.set(response.next_page_token.is_some() && !response.messages.is_empty());
+                    set_next_page_token.set(response.next_page_token);
+                    set_cast_list.update(|list| list.extend(response.messages));
+                    set_error.set(None);
+                }
+                Err(e) => {
+                    set_error.set(Some(format!("failed to fetch {}: {}", current_tab.label(), e)));
+                }
+            }
+            set_is_loading.set(false);
+        }
+    });
+
+    create_effect(move |_| {
+        fid.track();
+        tab.track();
+        generation.update_value(|generation| *generation += 1);
+        set_cast_list.set(Vec::new());
+        set_next_page_token.set(None);
+        set_has_more.set(true);
+        set_error.set(None);
+        fetch_casts.dispatch(());
+    });
+
+    let load_more = move |_| {
+        if !is_loading.get() && has_more.get() {
+            fetch_casts.dispatch(());
+        }
+    };
+
+    view! {
+        <div class="profile-casts-container">
+            {move || error.get().map(|err| view! { <p class="text-salmon-600 dark:text-salmon-400">{err}</p> })}
+            <div class="cast-list">
+                <For
+                    each=move || cast_list.get()
+                    key=|cast| cast.hash.clone()
+                    children=move |cast| {
+                        // the list is reset and extended under each entry, so the position has to follow it
+                        let index = {
+                            let hash = cast.hash.clone();
+                            Signal::derive(move || cast_list.with(|list| list.iter().position(|c| c.hash == hash).unwrap_or(0)))
+                        };
+                        view! {
+                            <div class=move || format!(
+                                "border-l border-r border-b last:border-b-0 border-teal-700 dark:border-teal-300 {} p-4 transition-colors duration-300 ease-in-out hover:bg-gray-100 dark:hover:bg-teal-800 group",
+                                if index.get() == 0 { "border-t" } else { "" }
+                            )>
+                                <CastEntry
+                                    cast=cast
+                                    lazy_load_index=Signal::derive(move || index.get() < 22)
+                                />
+                            </div>
+                        }
+                    }
+                />
+            </div>
+            <div>
+                {move || {
+                    if is_loading.get() {
+                        view! { <div><p class="text-teal-500 dark:text-teal-400">"loading..."</p></div> }
+                    } else if has_more.get() {
+                        view! {
+                            <div>
+                                <button
+                                    on:click=load_more
+                                    class="mt-4 px-4 py-2 bg-seafoam-600 dark:bg-teal-600 text-white hover:bg-seafoam-700 dark:hover:bg-teal-700 transition-colors duration-300 rounded-md focus:outline-none focus:ring-2 focus:ring-offset-2 focus:ring-seafoam-500 dark:focus:ring-teal-500"
+                                >
+                                    "load more"
+                                </button>
+                            </div>
+                        }
+                    } else {
+                        view! { <div><p class="text-gray-500 dark:text-gray-400">{move || format!("no more {} to load.", tab.get().label())}</p></div> }
+                    }
+                }}
+            </div>
+        </div>
+    }
+}
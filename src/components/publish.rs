@@ -0,0 +1,158 @@
+use leptos::*;
+use crate::models::farcaster::PublishedCast;
+use crate::components::channels::get_followed_channels;
+
+#[server(PublishCast, "/api")]
+pub async fn publish_cast(
+    text: String,
+    embeds: Vec<String>,
+    channel_id: Option<String>,
+    dry_run: bool,
+) -> Result<PublishedCast, ServerFnError> {
+    use diesel::prelude::*;
+    use crate::database::db::interact;
+    use crate::error_template::AppError;
+    use crate::schema::followed_channels;
+    use crate::services::signer::{CastDraft, CastPublisher};
+    use crate::state::AppState;
+
+    // anyone else would be casting as the app's account
+    let user = crate::services::auth::require_editor().await?;
+    let app_state = use_context::<AppState>()
+        .expect("failed to get AppState from context");
+    crate::log_info!("fid {} is publishing a cast (dry run: {})", user.fid, dry_run);
+
+    // casts land in a channel by carrying its url as their parent
+    let parent_url = match channel_id {
+        Some(channel_id) => {
+            let lookup_id = channel_id.clone();
+            let url = interact(&app_state.pool, move |conn| {
+                followed_channels::table
+                    .find(lookup_id)
+                    .select(followed_channels::url)
+                    .first::<String>(conn)
+                    .optional()
+            })
+            .await
+            .map_err(|e| AppError::Internal(e.to_string()))?;
+            Some(url.ok_or_else(|| AppError::Validation(format!("not following a channel called {}", channel_id)))?)
+        }
+        None => None,
+    };
+
+    let publisher = CastPublisher::from_config(&app_state.config)
+        .map_err(|e| AppError::Internal(format!("signer unavailable: {}", e)))?;
+
+    let draft = CastDraft {
+        text: text.trim().to_string(),
+        embeds: embeds.into_iter().map(|url| url.trim().to_string()).filter(|url| !url.is_empty()).collect(),
+        parent_url,
+    };
+
+    publisher
+        .publish(draft, dry_run)
+        .await
+        .map_err(|e| AppError::Upstream(format!("failed to publish cast: {}", e)).into())
+}
+
+#[component]
+pub fn PublishCast() -> impl IntoView {
+    let (text, set_text) = create_signal(String::new());
+    let (embed, set_embed) = create_signal(String::new());
+    let (channel_id, set_channel_id) = create_signal(String::new());
+    let (dry_run, set_dry_run) = create_signal(true);
+    let (published, set_published) = create_signal(None::<PublishedCast>);
+    let (error, set_error) = create_signal(None::<String>);
+
+    let followed = create_local_resource(|| (), |_| get_followed_channels());
+
+    let publish = create_action(move |_: &()| {
+        let text = text.get_untracked();
+        let embeds = vec![embed.get_untracked()];
+        let channel_id = Some(channel_id.get_untracked()).filter(|id| !id.is_empty());
+        let dry_run = dry_run.get_untracked();
+        async move {
+            match publish_cast(text, embeds, channel_id, dry_run).await {
+                Ok(cast) => {
+                    if !cast.dry_run {
+                        set_text.set(String::new());
+                        set_embed.set(String::new());
+                    }
+                    set_published.set(Some(cast));
+                    set_error.set(None);
+                }
+                Err(e) => {
+                    set_published.set(None);
+                    set_error.set(Some(e.to_string()));
+                }
+            }
+        }
+    });
+
+    let on_submit = move |ev: ev::SubmitEvent| {
+        ev.prevent_default();
+        if !publish.pending().get_untracked() {
+            publish.dispatch(());
+        }
+    };
+
+    let input_class = "p-2 bg-gray-100 dark:bg-teal-800 text-teal-600 dark:text-mint-400 border-2 border-gray-300 dark:border-teal-600 focus:border-teal-500 dark:focus:border-mint-300 focus:outline-none transition duration-300 ease-in-out rounded-md";
+
+    view! {
+        <form on:submit=on_submit class="publish-form flex flex-col space-y-2 pb-2 text-xs md:text-sm">
+            <textarea
+                placeholder="cast something"
+                rows="3"
+                prop:value=text
+                on:input=move |ev| set_text(event_target_value(&ev))
+                class=format!("w-full {}", input_class)
+            />
+            <div class="flex flex-row flex-wrap items-center gap-2">
+                <input
+                    type="url"
+                    placeholder="embed url"
+                    prop:value=embed
+                    on:input=move |ev| set_embed(event_target_value(&ev))
+                    class=format!("flex-grow {}", input_class)
+                />
+                <select on:change=move |ev| set_channel_id(event_target_value(&ev)) class=input_class>
+                    <option value="">"no channel"</option>
+                    {move || followed.get().map(|result| {
+                        result
+                            .unwrap_or_default()
+                            .into_iter()
+                            .map(|channel| view! { <option value=channel.id.clone()>{format!("/{}", channel.id)}</option> })
+                            .collect::<Vec<_>>()
+                    })}
+                </select>
+                <label class="flex items-center space-x-1 text-gray-900 dark:text-gray-100">
+                    <input
+                        type="checkbox"
+                        prop:checked=dry_run
+                        on:change=move |ev| set_dry_run(event_target_checked(&ev))
+                    />
+                    <span>"dry run"</span>
+                </label>
+                <button
+                    type="submit"
+                    disabled=move || publish.pending().get()
+                    class="px-4 py-2 bg-seafoam-600 dark:bg-teal-600 text-white hover:bg-seafoam-700 dark:hover:bg-teal-700 transition-colors duration-300 rounded-md"
+                >
+                    {move || if publish.pending().get() { "signing..." } else { "cast" }}
+                </button>
+            </div>
+            {move || error.get().map(|err| view! { <p class="text-salmon-600 dark:text-salmon-400">{err}</p> })}
+            {move || published.get().map(|cast| {
+                let unresolved = (!cast.unresolved_mentions.is_empty())
+                    .then(|| format!("; couldn't find @{}", cast.unresolved_mentions.join(", @")));
+                view! {
+                    <p class="text-teal-600 dark:text-mint-400">
+                        {if cast.dry_run { "signed (dry run) " } else { "published " }}
+                        <code>{cast.hash}</code>
+                        {unresolved}
+                    </p>
+                }
+            })}
+        </form>
+    }
+}
@@ -0,0 +1,211 @@
+use leptos::*;
+use chrono::NaiveDate;
+use crate::models::farcaster::Cast;
+use crate::models::search::CastSearchFilters;
+use crate::components::cache_provider::ClientCache;
+use crate::components::cast_entry::{page_fids, CastEntry};
+use crate::components::channels::get_followed_channels;
+use crate::components::users::prefetch_users;
+
+#[server(SearchCasts, "/api")]
+pub async fn search_casts(filters: CastSearchFilters, page: u64, limit: u64) -> Result<Vec<Cast>, ServerFnError> {
+    use crate::database::casts;
+    use crate::database::db::interact;
+    use crate::error_template::AppError;
+    use crate::state::AppState;
+
+    const MAX_SEARCH_LIMIT: u64 = 50;
+
+    let app_state = use_context::<AppState>().expect("Failed to get AppState from context");
+    let limit = limit.clamp(1, MAX_SEARCH_LIMIT);
+    let offset = (page.max(1) - 1) * limit;
+
+    crate::log_debug!("searching casts: {:?} (offset {}, limit {})", filters, offset, limit);
+
+    let found = interact(&app_state.pool, move |conn| casts::search_casts(conn, &filters, offset as i64, limit as i64))
+        .await
+        .map_err(|e| AppError::Internal(format!("failed to search casts: {}", e)))?;
+
+    found
+        .into_iter()
+        .map(Cast::try_from)
+        .collect::<Result<Vec<_>, _>>()
+        .map_err(|e| AppError::Internal(format!("unreadable indexed cast: {:?}", e)).into())
+}
+
+fn non_empty(value: String) -> Option<String> {
+    let value = value.trim().to_string();
+    (!value.is_empty()).then_some(value)
+}
+
+#[component]
+pub fn Search() -> impl IntoView {
+    let (query, set_query) = create_signal(String::new());
+    let (channel_id, set_channel_id) = create_signal(String::new());
+    let (fid, set_fid) = create_signal(String::new());
+    let (from_date, set_from_date) = create_signal(String::new());
+    let (to_date, set_to_date) = create_signal(String::new());
+    let (has_embed, set_has_embed) = create_signal(String::new());
+
+    let (filters, set_filters) = create_signal(None::<CastSearchFilters>);
+    let (results, set_results) = create_signal(Vec::<Cast>::new());
+    let (page, set_page) = create_signal(1u64);
+    let (error, set_error) = create_signal(None::<String>);
+    let (is_loading, set_is_loading) = create_signal(false);
+    let (has_more, set_has_more) = create_signal(false);
+    let limit = 10u64;
+    let client_cache = use_context::<RwSignal<ClientCache>>().expect("ClientCache should be provided");
+
+    // only feeds the channel picker, so there's no need to render it on the server
+    let followed = create_local_resource(|| (), |_| get_followed_channels());
+
+    let fetch_results = create_action(move |_: &()| {
+        let current_filters = filters.get_untracked();
+        let current_page = page.get_untracked();
+        let client_cache = client_cache.get_untracked();
+        async move {
+            let Some(current_filters) = current_filters else { return };
+            set_is_loading.set(true);
+            match search_casts(current_filters, current_page, limit).await {
+                Ok(found) => {
+                    prefetch_users(&client_cache, page_fids(&found)).await;
+                    set_has_more.set(found.len() as u64 == limit);
+                    set_results.update(|list| list.extend(found));
+                    set_error.set(None);
+                }
+                Err(e) => {
+                    set_error.set(Some(format!("search failed: {}", e)));
+                }
+            }
+            set_is_loading.set(false);
+        }
+    });
+
+    let on_submit = move |ev: ev::SubmitEvent| {
+        ev.prevent_default();
+
+        let fid_value = non_empty(fid.get());
+        let parsed_fid = match fid_value.as_deref().map(str::parse::<u64>) {
+            Some(Err(_)) => {
+                set_error.set(Some("fid has to be a number".to_string()));
+                return;
+            }
+            Some(Ok(fid)) => Some(fid),
+            None => None,
+        };
+
+        set_filters.set(Some(CastSearchFilters {
+            query: non_empty(query.get()),
+            channel_id: non_empty(channel_id.get()),
+            fid: parsed_fid,
+            from_date: NaiveDate::parse_from_str(&from_date.get(), "%Y-%m-%d").ok(),
+            to_date: NaiveDate::parse_from_str(&to_date.get(), "%Y-%m-%d").ok(),
+            has_embed: match has_embed.get().as_str() {
+                "with" => Some(true),
+                "without" => Some(false),
+                _ => None,
+            },
+        }));
+        set_results.set(Vec::new());
+        set_page.set(1);
+        set_error.set(None);
+        fetch_results.dispatch(());
+    };
+
+    let load_more = move |_| {
+        if !is_loading.get() && has_more.get() {
+            set_page.update(|p| *p += 1);
+            fetch_results.dispatch(());
+        }
+    };
+
+    let input_class = "p-2 bg-gray-100 dark:bg-teal-800 text-teal-600 dark:text-mint-400 border-2 border-gray-300 dark:border-teal-600 focus:border-teal-500 dark:focus:border-mint-300 focus:outline-none transition duration-300 ease-in-out rounded-md";
+
+    view! {
+        <div class="search-container w-11/12 lg:w-8/12 xl:w-5/12 mx-auto pt-4">
+            <form on:submit=on_submit class="search-form flex flex-col space-y-2 pb-6">
+                <input
+                    type="text"
+                    placeholder="grep casts"
+                    on:input=move |ev| set_query(event_target_value(&ev))
+                    class=format!("grep-box w-full {}", input_class)
+                />
+                <div class="flex flex-row flex-wrap gap-2">
+                    <select on:change=move |ev| set_channel_id(event_target_value(&ev)) class=input_class>
+                        <option value="">"any channel"</option>
+                        {move || followed.get().map(|result| {
+                            result
+                                .unwrap_or_default()
+                                .into_iter()
+                                .map(|channel| view! { <option value=channel.id.clone()>{format!("/{}", channel.id)}</option> })
+                                .collect::<Vec<_>>()
+                        })}
+                    </select>
+                    <input
+                        type="text"
+                        inputmode="numeric"
+                        placeholder="author fid"
+                        on:input=move |ev| set_fid(event_target_value(&ev))
+                        class=format!("w-28 {}", input_class)
+                    />
+                    <input type="date" title="from" on:input=move |ev| set_from_date(event_target_value(&ev)) class=input_class/>
+                    <input type="date" title="to" on:input=move |ev| set_to_date(event_target_value(&ev)) class=input_class/>
+                    <select on:change=move |ev| set_has_embed(event_target_value(&ev)) class=input_class>
+                        <option value="">"any embeds"</option>
+                        <option value="with">"with embeds"</option>
+                        <option value="without">"without embeds"</option>
+                    </select>
+                    <button
+                        type="submit"
+                        class="px-4 py-2 bg-seafoam-600 dark:bg-teal-600 text-white hover:bg-seafoam-700 dark:hover:bg-teal-700 transition-colors duration-300 rounded-md"
+                    >
+                        "search"
+                    </button>
+                </div>
+            </form>
+            {move || error.get().map(|err| view! { <p class="text-salmon-600 dark:text-salmon-400">{err}</p> })}
+            <div class="cast-list">
+                <For
+                    each=move || results.get()
+                    key=|cast| cast.hash.clone()
+                    children=move |cast| {
+                        let index = results.with(|list| list.iter().position(|c| c.hash == cast.hash).unwrap_or(0));
+                        view! {
+                            <div class=move || format!(
+                                "border-l border-r border-b last:border-b-0 border-teal-700 dark:border-teal-300 {} p-4 transition-colors duration-300 ease-in-out hover:bg-gray-100 dark:hover:bg-teal-800 group",
+                                if index == 0 { "border-t" } else { "" }
+                            )>
+                                <CastEntry
+                                    cast=cast
+                                    lazy_load_index=Signal::derive(move || index < 22)
+                                />
+                            </div>
+                        }
+                    }
+                />
+            </div>
+            <div>
+                {move || {
+                    if is_loading.get() {
+                        view! { <div><p class="text-teal-500 dark:text-teal-400">"searching..."</p></div> }
+                    } else if has_more.get() {
+                        view! {
+                            <div>
+                                <button
+                                    on:click=load_more
+                                    class="mt-4 px-4 py-2 bg-seafoam-600 dark:bg-teal-600 text-white hover:bg-seafoam-700 dark:hover:bg-teal-700 transition-colors duration-300 rounded-md focus:outline-none focus:ring-2 focus:ring-offset-2 focus:ring-seafoam-500 dark:focus:ring-teal-500"
+                                >
+                                    "load more"
+                                </button>
+                            </div>
+                        }
+                    } else if filters.with(Option::is_some) && results.with(Vec::is_empty) {
+                        view! { <div><p class="text-gray-500 dark:text-gray-400">"no casts found."</p></div> }
+                    } else {
+                        view! { <div></div> }
+                    }
+                }}
+            </div>
+        </div>
+    }
+}
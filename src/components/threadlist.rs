@@ -2,7 +2,7 @@ use leptos::*;
 use log::error;
 use web_sys::Event;
 
-use crate::models::conversations::ThreadView;
+use crate::models::conversations::{ThreadShareView, ThreadView};
 
 #[component]
 pub fn ThreadList(
@@ -82,8 +82,32 @@ pub fn ThreadList(
         }
     });
 
+    let (share_link, set_share_link) = create_signal(None::<ThreadShareView>);
+    let (share_expiry, set_share_expiry) = create_signal(String::new());
+
+    let share_thread_action = create_action(move |thread_id: &String| {
+        let thread_id = thread_id.clone();
+        let expires_in_hours = share_expiry.get_untracked().parse::<u32>().ok();
+        async move {
+            match share_thread(thread_id, expires_in_hours).await {
+                Ok(share) => set_share_link(Some(share)),
+                Err(e) => error!("failed to share thread: {:?}", e),
+            }
+        }
+    });
+
+    let revoke_share_action = create_action(move |token: &String| {
+        let token = token.clone();
+        async move {
+            match revoke_thread_share(token).await {
+                Ok(_) => set_share_link(None),
+                Err(e) => error!("failed to revoke share link: {:?}", e),
+            }
+        }
+    });
+
     // New action for generating thread titles
-    let generate_title_action = create_action(move |params: &(String, String)| {
+    let generate_title_action = create_action(move |params: &(String, Option<String>)| {
         let (thread_id, provider) = params.clone();
         async move {
             match generate_thread_title(thread_id.clone(), provider).await {
@@ -116,6 +140,31 @@ pub fn ThreadList(
                 border-2 border-gray-300 dark:border-teal-600 focus:border-teal-500 dark:focus:border-mint-300
                 focus:outline-none transition duration-300 ease-in-out"
             />
+            <div class="share-settings flex flex-row items-center gap-2 mb-2 text-xs text-teal-600 dark:text-mint-400">
+                <span>"share links expire"</span>
+                <select
+                    class="bg-gray-100 dark:bg-teal-800 border border-gray-300 dark:border-teal-600"
+                    on:change=move |ev| set_share_expiry(event_target_value(&ev))
+                >
+                    <option value="">"never"</option>
+                    <option value="24">"in a day"</option>
+                    <option value="168">"in a week"</option>
+                </select>
+            </div>
+            {move || share_link.get().map(|share| {
+                let token = share.token.clone();
+                view! {
+                    <div class="share-link flex flex-row items-center gap-2 mb-2 text-xs text-teal-600 dark:text-mint-400">
+                        <a href=share.path() target="_blank" class="underline break-all">{share.path()}</a>
+                        <button
+                            class="px-2 py-1 bg-gray-200 dark:bg-teal-900 hover:bg-gray-300 dark:hover:bg-teal-800 rounded"
+                            on:click=move |_| revoke_share_action.dispatch(token.clone())
+                        >
+                            "revoke"
+                        </button>
+                    </div>
+                }
+            })}
             {move || {
                 thread_list()
                     .into_iter()
@@ -135,8 +184,8 @@ pub fn ThreadList(
                         };
                         let thread_id_for_set = thread_id.clone();
                         let thread_id_for_delete = thread_id.clone();
-                        let thread_id_for_title_openai = thread_id.clone();
-                        let thread_id_for_title_anthropic = thread_id.clone();
+                        let thread_id_for_share = thread_id.clone();
+                        let thread_id_for_title = thread_id.clone();
                         let display_text = thread
                             .title
                             .clone()
@@ -192,59 +241,40 @@ pub fn ThreadList(
 
                                     <div class="button-group flex items-center gap-1">
                                         // Title generation buttons
-                                        <div class="title-buttons flex flex-col gap-1">
-                                            <button
-                                                class="generate-title-btn text-xs px-2 py-1 text-teal-600 dark:text-mint-400 
-                                                hover:text-teal-400 dark:hover:text-mint-300 bg-gray-200 dark:bg-teal-900 
-                                                hover:bg-gray-300 dark:hover:bg-teal-800 rounded transition duration-300 ease-in-out
-                                                disabled:opacity-50 disabled:cursor-not-allowed"
-                                                on:click=move |_| {
-                                                    generate_title_action
-                                                        .dispatch((
-                                                            thread_id_for_title_openai.clone(),
-                                                            "openai".to_string(),
-                                                        ));
-                                                }
+                                        <button
+                                            class="generate-title-btn text-xs px-2 py-1 text-teal-600 dark:text-mint-400 
+                                            hover:text-teal-400 dark:hover:text-mint-300 bg-gray-200 dark:bg-teal-900 
+                                            hover:bg-gray-300 dark:hover:bg-teal-800 rounded transition duration-300 ease-in-out
+                                            disabled:opacity-50 disabled:cursor-not-allowed"
+                                            on:click=move |_| {
+                                                generate_title_action
+                                                    .dispatch((thread_id_for_title.clone(), None));
+                                            }
 
-                                                disabled=move || generate_title_action.pending().get()
-                                                title="Generate title with OpenAI"
-                                            >
-                                                {move || {
-                                                    if generate_title_action.pending().get() {
-                                                        "..."
-                                                    } else {
-                                                        "GPT"
-                                                    }
-                                                }}
-
-                                            </button>
-
-                                            <button
-                                                class="generate-title-btn text-xs px-2 py-1 text-teal-600 dark:text-mint-400 
-                                                hover:text-teal-400 dark:hover:text-mint-300 bg-gray-200 dark:bg-teal-900 
-                                                hover:bg-gray-300 dark:hover:bg-teal-800 rounded transition duration-300 ease-in-out
-                                                disabled:opacity-50 disabled:cursor-not-allowed"
-                                                on:click=move |_| {
-                                                    generate_title_action
-                                                        .dispatch((
-                                                            thread_id_for_title_anthropic.clone(),
-                                                            "anthropic".to_string(),
-                                                        ));
+                                            disabled=move || generate_title_action.pending().get()
+                                            title="Generate a title with your title provider"
+                                        >
+                                            {move || {
+                                                if generate_title_action.pending().get() {
+                                                    "..."
+                                                } else {
+                                                    "title"
                                                 }
+                                            }}
 
-                                                disabled=move || generate_title_action.pending().get()
-                                                title="Generate title with Anthropic"
-                                            >
-                                                {move || {
-                                                    if generate_title_action.pending().get() {
-                                                        "..."
-                                                    } else {
-                                                        "Claude"
-                                                    }
-                                                }}
-
-                                            </button>
-                                        </div>
+                                        </button>
+
+                                        <button
+                                            class="share-button text-teal-600 dark:text-mint-400 hover:text-teal-400 dark:hover:text-mint-300 
+                                            text-sm p-2 bg-gray-400 dark:bg-teal-900 hover:bg-gray-500 dark:hover:bg-teal-800 
+                                            rounded transition duration-300 ease-in-out"
+                                            on:click=move |_| {
+                                                share_thread_action.dispatch(thread_id_for_share.clone())
+                                            }
+                                        >
+
+                                            "shar"
+                                        </button>
 
                                         // Delete button
                                         <button
@@ -272,180 +302,44 @@ pub fn ThreadList(
 
 #[server(SearchThreads, "/api")]
 pub async fn search_threads(query: String) -> Result<Vec<ThreadView>, ServerFnError> {
-    use diesel::prelude::*;
-    use std::fmt;
-
     use crate::state::AppState;
-    use crate::models::conversations::Thread;
-    use crate::schema::{threads, messages};
-
-    #[derive(Debug)]
-    enum SearchError {
-        Pool(String),
-        Database(diesel::result::Error),
-        Interaction(String),
-    }
-
-    impl fmt::Display for SearchError {
-        fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
-            match self {
-                SearchError::Pool(e) => write!(f, "pool error: {}", e),
-                SearchError::Database(e) => write!(f, "database error: {}", e),
-                SearchError::Interaction(e) => write!(f, "interaction error: {}", e),
-            }
-        }
-    }
 
-    fn to_server_error(e: SearchError) -> ServerFnError {
-        ServerFnError::ServerError(e.to_string())
-    }
+    let user = crate::services::auth::require_user().await?;
 
     let app_state = use_context::<AppState>()
         .expect("failed to get AppState from context");
 
-    let pool = app_state.pool;
-
-    let conn = pool
-        .get()
-        .await
-        .map_err(|e| SearchError::Pool(e.to_string()))
-        .map_err(to_server_error)?;
-
-    let result = conn
-        .interact(move |conn| {
-            threads::table
-                .left_join(messages::table)
-                .filter(
-                    threads::id.like(format!("%{}%", query))
-                        .or(messages::content.like(format!("%{}%", query)))
-                )
-                .select(threads::all_columns)
-                .distinct()
-                .load::<Thread>(conn)
-        })
-        .await
-        .map_err(|e| SearchError::Interaction(e.to_string()))
-        .map_err(to_server_error)?
-        .map_err(SearchError::Database)
-        .map_err(to_server_error)?;
+    let threads = app_state.threads().search(user.fid as i64, &query).await?;
 
-    Ok(result.into_iter().map(ThreadView::from).collect())
+    Ok(threads.into_iter().map(ThreadView::from).collect())
 }
 
 #[server(DeleteThread, "/api")]
 pub async fn delete_thread(thread_id: String) -> Result<(), ServerFnError> {
-    use diesel::prelude::*;
-    use crate::schema::{threads, messages};
-    use std::fmt;
     use crate::state::AppState;
 
-    #[derive(Debug)]
-    enum ThreadError {
-        Pool(String),
-        Database(diesel::result::Error),
-        Interaction(String),
-    }
-
-    impl fmt::Display for ThreadError {
-        fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
-            match self {
-                ThreadError::Pool(e) => write!(f, "pool error: {}", e),
-                ThreadError::Database(e)=> write!(f, "database error: {}", e),
-                ThreadError::Interaction(e) => write!(f, "interaction error: {}", e),
-            }
-        }
-    }
-
-    fn to_server_error(e: ThreadError) -> ServerFnError {
-        ServerFnError::ServerError(e.to_string())
-    }
+    let user = crate::services::auth::require_user().await?;
 
     let app_state = use_context::<AppState>()
         .expect("failed to get AppState from context");
-    let pool = app_state.pool;
 
-    let conn = pool
-        .get()
-        .await
-        .map_err(|e| ThreadError::Pool(e.to_string()))
-        .map_err(to_server_error)?;
-
-    conn.interact(move |conn| {
-        conn.transaction(|conn| {
-            // first, delete all messages associated with thread
-            diesel::delete(messages::table.filter(messages::thread_id.eq(&thread_id)))
-                .execute(conn)?;
-
-            // then, delete thread itself
-            diesel::delete(threads::table.find(thread_id))
-                .execute(conn)?;
-
-            Ok(())
-        })
-    })
-    .await
-    .map_err(|e| ThreadError::Interaction(e.to_string()))
-    .map_err(to_server_error)?
-    .map_err(ThreadError::Database)
-    .map_err(to_server_error)?;
+    app_state.threads().delete(&thread_id, user.fid as i64).await?;
 
     Ok(())
 }
 
 #[server(GetThreads, "/api")]
 pub async fn get_threads() -> Result<Vec<ThreadView>, ServerFnError> {
-    use diesel::prelude::*;
-    use std::fmt;
-
     use crate::state::AppState;
-    use crate::models::conversations::Thread;
-    use crate::schema::threads::dsl::threads as threads_table;
-
-    #[derive(Debug)]
-    enum ThreadError {
-        Pool(String),
-        Database(diesel::result::Error),
-        Interaction(String),
-    }
 
-    impl fmt::Display for ThreadError {
-        fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
-            match self {
-                ThreadError::Pool(e) => write!(f, "Pool error: {}", e),
-                ThreadError::Database(e) => write!(f, "Database error: {}", e),
-                ThreadError::Interaction(e) => write!(f, "Interaction error: {}", e),
-            }
-        }
-    }
-
-    fn to_server_error(e: ThreadError) -> ServerFnError {
-        ServerFnError::ServerError(e.to_string())
-    }
+    let user = crate::services::auth::require_user().await?;
 
     let app_state = use_context::<AppState>()
         .expect("Failed to get AppState from context");
 
-    let pool = app_state.pool;
-
-    let conn = pool
-        .get()
-        .await
-        .map_err(|e| ThreadError::Pool(e.to_string()))
-        .map_err(to_server_error)?;
+    let threads = app_state.threads().list(user.fid as i64).await?;
 
-    let result = conn
-        .interact(|conn| {
-            threads_table
-                .order(crate::schema::threads::created_at.desc())
-                .load::<Thread>(conn)
-        })
-        .await
-        .map_err(|e| ThreadError::Interaction(e.to_string()))
-        .map_err(to_server_error)?
-        .map_err(ThreadError::Database)
-        .map_err(to_server_error)?;
-
-    Ok(result.into_iter().map(ThreadView::from).collect())
+    Ok(threads.into_iter().map(ThreadView::from).collect())
 }
 
 #[server(RenameThread, "/api")]
@@ -453,78 +347,14 @@ pub async fn rename_thread(
     thread_id: String,
     new_title: String,
 ) -> Result<(), ServerFnError> {
-    use diesel::prelude::*;
-    use crate::models::conversations::Thread;
-    use crate::schema::threads;
-    use std::fmt;
     use crate::state::AppState;
 
-    #[derive(Debug)]
-    enum ThreadError {
-        Pool(String),
-        Database(diesel::result::Error),
-        Interaction(String),
-        NotFound
-    }
-
-    impl fmt::Display for ThreadError {
-        fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
-            match self {
-                ThreadError::Pool(e) => write!(f, "pool error: {}", e),
-                ThreadError::Database(e)=> write!(f, "database error: {}", e),
-                ThreadError::Interaction(e) => write!(f, "interaction error: {}", e),
-                ThreadError::NotFound => write!(f, "thread not found"),
-            }
-        }
-    }
-
-    fn to_server_error(e: ThreadError) -> ServerFnError {
-        ServerFnError::ServerError(e.to_string())
-    }
+    let user = crate::services::auth::require_user().await?;
 
     let app_state = use_context::<AppState>()
         .expect("failed to get AppState from context");
-    let pool = app_state.pool;
-
-    let conn = pool
-        .get()
-        .await
-        .map_err(|e| ThreadError::Pool(e.to_string()))
-        .map_err(to_server_error)?;
-
-    let thread_id_clone = thread_id.clone();
-    let new_title_clone = new_title.clone();
-
-    conn.interact(move |conn| {
-        conn.transaction(|conn| {
-            let thread_exists = threads::table
-                .find(&thread_id_clone)
-                .first::<Thread>(conn)
-                .optional()?
-                .is_some();
-
-            if !thread_exists {
-                return Err(diesel::result::Error::NotFound);
-            }
 
-            diesel::update(threads::table.find(&thread_id_clone))
-                .set((
-                    threads::title.eq(&new_title_clone),
-                    threads::updated_at.eq(chrono::Utc::now().naive_utc()),
-                ))
-                .execute(conn)?;
-
-            Ok(())
-        })
-    })
-    .await
-    .map_err(|e| ThreadError::Interaction(e.to_string()))
-    .map_err(to_server_error)?
-    .map_err(|e| match e {
-        diesel::result::Error::NotFound => ThreadError::NotFound,
-        other => ThreadError::Database(other),
-    })
-    .map_err(to_server_error)?;
+    app_state.threads().rename(&thread_id, user.fid as i64, &new_title).await?;
 
     Ok(())
 }
@@ -532,29 +362,35 @@ pub async fn rename_thread(
 #[server(GenerateThreadTitle, "/api")]
 pub async fn generate_thread_title(
     thread_id: String,
-    provider: String,
+    // `None` uses the provider picked in settings
+    provider: Option<String>,
 ) -> Result<String, ServerFnError> {
-    use crate::components::chat::{OpenAIService, AnthropicService, fetch_message_history};
-    use std::fmt;
-    #[derive(Debug)]
-    enum TitleGenError {
-        History(String),
-    }
+    use crate::components::chat::{OpenAIService, AnthropicService};
+    use crate::database::db::interact;
+    use crate::database::settings::get_user_settings;
+    use crate::error_template::AppError;
+    use crate::models::settings::UserSettingsView;
+    use crate::services::rate_limit::{estimate_tokens, record_llm_usage};
+    use crate::state::AppState;
 
-    impl fmt::Display for TitleGenError {
-        fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
-            match self {
-                TitleGenError::History(e) => write!(f, "history error: {}", e),
-            }
-        }
-    }
+    let user = crate::services::auth::require_user().await?;
+    let owner = user.fid as i64;
 
-    fn to_server_error(e: TitleGenError) -> ServerFnError {
-        ServerFnError::ServerError(e.to_string())
-    }
+    let app_state = use_context::<AppState>()
+        .expect("failed to get AppState from context");
+    app_state.threads().require_owner(&thread_id, owner).await?;
+
+    let provider = match provider {
+        Some(provider) => provider,
+        None => interact(&app_state.pool, move |conn| get_user_settings(conn, owner))
+            .await
+            .map_err(|e| AppError::Internal(format!("failed to load settings: {}", e)))?
+            .map(UserSettingsView::from)
+            .unwrap_or_default()
+            .title_provider,
+    };
 
-    let history = fetch_message_history(&thread_id).await
-        .map_err(|e| to_server_error(TitleGenError::History(e.to_string())))?;
+    let history = app_state.messages().for_thread(&thread_id).await?;
 
     let context_messages = history.iter()
         .take(3)
@@ -573,43 +409,29 @@ pub async fn generate_thread_title(
 
     let title = match provider.as_str() {
         "openai" => {
-            let service = OpenAIService::new("gpt-3.5-turbo".to_string());
+            let service = OpenAIService::new(&app_state.config, "gpt-3.5-turbo".to_string())
+                .map_err(|e| AppError::Validation(e.to_string()))?;
             generate_title_openai(&service, &title_prompt).await?
         }
         "anthropic" => {
-            let service = AnthropicService::new("claude-3-haiku-20240307".to_string());
+            let service = AnthropicService::new(&app_state.config, "claude-3-haiku-20240307".to_string())
+                .map_err(|e| AppError::Validation(e.to_string()))?;
             generate_title_anthropic(&service, &title_prompt).await?
         }
-        _ => return Err(ServerFnError::ServerError("Invalid provider".to_string())),
+        other => return Err(AppError::Validation(format!("unknown title provider {}", other)).into()),
     };
 
     rename_thread(thread_id, title.clone()).await?;
 
+    let tokens = estimate_tokens(&title_prompt) + estimate_tokens(&title);
+    record_llm_usage(&app_state.cache, user.fid, tokens).await;
+
 
     async fn generate_title_openai(
         service: &OpenAIService,
         prompt: &str,
     ) -> Result<String, ServerFnError> {
-        use std::fmt;
-    
-        #[derive(Debug)]
-        enum TitleGenError {
-            History(String),
-            Json(String)
-        }
-    
-        impl fmt::Display for TitleGenError {
-            fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
-                match self {
-                    TitleGenError::History(e) => write!(f, "history error: {}", e),
-                    TitleGenError::Json(e) => write!(f, "json prob: {}", e),
-                }
-            }
-        }
-    
-        fn to_server_error(e: TitleGenError) -> ServerFnError {
-            ServerFnError::ServerError(e.to_string())
-        }
+        use crate::error_template::AppError;
     
         let response = service.client
             .post("https://api.openai.com/v1/chat/completions")
@@ -628,10 +450,10 @@ pub async fn generate_thread_title(
             }))
             .send()
             .await
-            .map_err(|e| to_server_error(TitleGenError::History(e.to_string())))?;
+            .map_err(|e| AppError::Upstream(format!("title request failed: {}", e)))?;
     
         let json: serde_json::Value = response.json().await
-            .map_err(|e| to_server_error(TitleGenError::Json(e.to_string())))?;
+            .map_err(|e| AppError::Upstream(format!("unreadable title response: {}", e)))?;
     
         let title = json["choices"][0]["message"]["content"]
             .as_str()
@@ -648,26 +470,7 @@ pub async fn generate_thread_title(
         service: &AnthropicService,
         prompt: &str,
     ) -> Result<String, ServerFnError> {
-        use std::fmt;
-    
-        #[derive(Debug)]
-        enum TitleGenError {
-            History(String),
-            Json(String)
-        }
-    
-        impl fmt::Display for TitleGenError {
-            fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
-                match self {
-                    TitleGenError::History(e) => write!(f, "history error: {}", e),
-                    TitleGenError::Json(e) => write!(f, "json prob: {}", e),
-                }
-            }
-        }
-    
-        fn to_server_error(e: TitleGenError) -> ServerFnError {
-            ServerFnError::ServerError(e.to_string())
-        }
+        use crate::error_template::AppError;
     
         let response = service.client
             .post("https://api.anthropic.com/v1/messages")
@@ -686,10 +489,10 @@ pub async fn generate_thread_title(
             }))
             .send()
             .await
-            .map_err(|e| to_server_error(TitleGenError::History(e.to_string())))?;
+            .map_err(|e| AppError::Upstream(format!("title request failed: {}", e)))?;
     
         let json: serde_json::Value = response.json().await
-            .map_err(|e| to_server_error(TitleGenError::Json(e.to_string())))?;
+            .map_err(|e| AppError::Upstream(format!("unreadable title response: {}", e)))?;
     
         let title = json["content"][0]["text"]
             .as_str()
@@ -704,3 +507,47 @@ pub async fn generate_thread_title(
     
     Ok(title)
 }
+
+#[server(ShareThread, "/api")]
+pub async fn share_thread(
+    thread_id: String,
+    expires_in_hours: Option<u32>,
+) -> Result<ThreadShareView, ServerFnError> {
+    use chrono::{Duration, Utc};
+    use crate::models::conversations::ThreadShare;
+    use crate::services::auth::random_token;
+    use crate::state::AppState;
+
+    let user = crate::services::auth::require_user().await?;
+
+    let app_state = use_context::<AppState>()
+        .expect("failed to get AppState from context");
+
+    let now = Utc::now().naive_utc();
+    let share = ThreadShare {
+        token: random_token(),
+        thread_id,
+        created_at: now,
+        expires_at: expires_in_hours.map(|hours| now + Duration::hours(hours as i64)),
+        revoked_at: None,
+    };
+
+    let share = app_state.threads().share(share, user.fid as i64).await?;
+
+    Ok(share.into())
+}
+
+/// Kills a share link for good. Only the thread's owner can.
+#[server(RevokeThreadShare, "/api")]
+pub async fn revoke_thread_share(token: String) -> Result<(), ServerFnError> {
+    use crate::state::AppState;
+
+    let user = crate::services::auth::require_user().await?;
+
+    let app_state = use_context::<AppState>()
+        .expect("failed to get AppState from context");
+
+    app_state.threads().revoke_share(&token, user.fid as i64).await?;
+
+    Ok(())
+}
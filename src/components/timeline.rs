@@ -0,0 +1,156 @@
+use leptos::*;
+use std::collections::HashMap;
+use crate::components::cache_provider::ClientCache;
+use crate::components::cast_entry::{page_fids, CastEntry};
+use crate::components::users::prefetch_users;
+use crate::services::timeline::{TimelineCast, TimelinePage};
+
+#[component]
+pub fn Timeline(
+    set_active_channel: WriteSignal<Option<String>>,
+) -> impl IntoView {
+    let (cast_list, set_cast_list) = create_signal(Vec::<TimelineCast>::new());
+    let (cursors, set_cursors) = create_signal(HashMap::<String, u64>::new());
+    let (error, set_error) = create_signal(None::<String>);
+    let (is_loading, set_is_loading) = create_signal(false);
+    let (has_more, set_has_more) = create_signal(true);
+    let limit = 8u64;
+    let client_cache = use_context::<RwSignal<ClientCache>>().expect("ClientCache should be provided");
+
+    let fetch_casts = create_action(move |_: &()| {
+        let current_cursors = cursors.get_untracked();
+        let client_cache = client_cache.get_untracked();
+        async move {
+            set_is_loading.set(true);
+            match get_timeline(Some(current_cursors), limit).await {
+                Ok(page) => {
+                    prefetch_users(&client_cache, page_fids(page.casts.iter().map(|entry| &entry.cast))).await;
+                    set_has_more.set(page.has_more);
+                    set_cursors.set(page.cursors);
+                    set_cast_list.update(|list| {
+                        let fresh: Vec<TimelineCast> = page
+                            .casts
+                            .into_iter()
+                            .filter(|entry| !list.iter().any(|c| c.cast.hash == entry.cast.hash))
+                            .collect();
+                        list.extend(fresh);
+                    });
+                    set_error.set(None);
+                }
+                Err(e) => {
+                    set_error.set(Some(format!("failed to fetch timeline: {}", e)));
+                }
+            }
+            set_is_loading.set(false);
+        }
+    });
+
+    create_effect(move |_| {
+        fetch_casts.dispatch(());
+    });
+
+    let load_more = move |_| {
+        if !is_loading.get() && has_more.get() {
+            fetch_casts.dispatch(());
+        }
+    };
+
+    view! {
+        <div class="timeline-container w-11/12 lg:w-8/12 xl:w-5/12 mx-auto">
+            <h2 class="text-2xl font-bold text-teal-600 dark:text-mint-400 pb-6">"all channels"</h2>
+            {move || error.get().map(|err| view! { <p class="text-salmon-600 dark:text-salmon-400">{err}</p> })}
+            <div class="cast-list">
+                <For
+                    each=move || cast_list.get()
+                    key=|entry| entry.cast.hash.clone()
+                    children=move |entry| {
+                        let index = cast_list.with(|list| list.iter().position(|c| c.cast.hash == entry.cast.hash).unwrap_or(0));
+                        let channel_id = entry.channel_id.clone();
+                        view! {
+                            <div class=move || format!(
+                                "border-l border-r border-b last:border-b-0 border-teal-700 dark:border-teal-300 {} p-4 transition-colors duration-300 ease-in-out hover:bg-gray-100 dark:hover:bg-teal-800 group",
+                                if index == 0 { "border-t" } else { "" }
+                            )>
+                                <button
+                                    class="channel-label text-xs text-seafoam-600 dark:text-aqua-400 hover:underline mb-1"
+                                    on:click=move |_| set_active_channel(Some(channel_id.clone()))
+                                >
+                                    {format!("/{}", entry.channel_id)}
+                                </button>
+                                <CastEntry
+                                    cast=entry.cast
+                                    lazy_load_index=Signal::derive(move || index < 22)
+                                />
+                            </div>
+                        }
+                    }
+                />
+            </div>
+            <div>
+                {move || {
+                    if is_loading.get() {
+                        view! { <div><p class="text-teal-500 dark:text-teal-400">"loading..."</p></div> }
+                    } else if has_more.get() {
+                        view! {
+                            <div>
+                                <button
+                                    on:click=load_more
+                                    class="mt-4 px-4 py-2 bg-seafoam-600 dark:bg-teal-600 text-white hover:bg-seafoam-700 dark:hover:bg-teal-700 transition-colors duration-300 rounded-md focus:outline-none focus:ring-2 focus:ring-offset-2 focus:ring-seafoam-500 dark:focus:ring-teal-500"
+                                >
+                                    "load more"
+                                </button>
+                            </div>
+                        }
+                    } else {
+                        view! { <div><p class="text-gray-500 dark:text-gray-400">"no more casts to load."</p></div> }
+                    }
+                }}
+            </div>
+        </div>
+    }
+}
+
+#[server(GetTimeline, "/api")]
+pub async fn get_timeline(cursors: Option<HashMap<String, u64>>, limit: u64) -> Result<TimelinePage, ServerFnError> {
+    use crate::components::cast_list::load_channel_casts;
+    use crate::database::db::interact;
+    use crate::error_template::AppError;
+    use crate::schema::followed_channels;
+    use crate::services::timeline::{merge_timeline, ChannelWindow};
+    use crate::state::AppState;
+    use diesel::prelude::*;
+
+    let app_state = use_context::<AppState>().expect("Failed to get AppState from context");
+    let cursors = cursors.unwrap_or_default();
+
+    let channel_ids: Vec<String> = interact(&app_state.pool, |conn| {
+        followed_channels::table
+            .select(followed_channels::channel_id)
+            .order(followed_channels::channel_id)
+            .load(conn)
+    })
+    .await
+    .map_err(|e| AppError::Internal(format!("failed to load followed channels: {}", e)))?;
+
+    // every channel is read from its own cursor; the merge decides how far each one moves
+    let windows = futures::future::join_all(channel_ids.into_iter().map(|channel_id| {
+        let offset = cursors.get(&channel_id).copied().unwrap_or(0);
+        let app_state = &app_state;
+        async move {
+            match load_channel_casts(app_state, &channel_id, offset, limit).await {
+                Ok(casts) => {
+                    let has_more = casts.len() as u64 >= limit;
+                    ChannelWindow { channel_id, offset, casts, has_more }
+                }
+                Err(e) => {
+                    // one broken channel shouldn't empty the whole timeline
+                    crate::log_warn!("failed to load /{} for the timeline: {}", channel_id, e);
+                    ChannelWindow { channel_id, offset, casts: Vec::new(), has_more: false }
+                }
+            }
+        }
+    }))
+    .await;
+
+    Ok(merge_timeline(windows, limit as usize))
+}
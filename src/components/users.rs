@@ -0,0 +1,130 @@
+use cfg_if::cfg_if;
+use leptos::*;
+use crate::components::cache_provider::ClientCache;
+use crate::models::farcaster::UserSummary;
+
+/// how many fids one call can ask about
+pub const MAX_FIDS: usize = 100;
+
+cfg_if! {
+    if #[cfg(feature = "ssr")] {
+        use axum::extract::{Query, State};
+        use futures::stream::{self, StreamExt};
+        use std::collections::{HashMap, HashSet};
+        use crate::error_template::AppError;
+        use crate::models::farcaster::UserDataListResponse;
+        use crate::services::hubble::{found, get_user_data_by_fid, UserDataParams};
+        use crate::state::AppState;
+
+        /// how many fids missing from the cache are fetched from the hub at once
+        const HUB_CONCURRENCY: usize = 8;
+
+        /// Summaries for `fids` in the order given, read from the cache in one
+        /// round trip. Only fids with something missing go to the hub, one request
+        /// each for all their user data; a fid the hub fails on comes back with
+        /// whatever was cached.
+        pub async fn resolve_users(app_state: &AppState, fids: Vec<u64>, types: Vec<u8>) -> Result<Vec<UserSummary>, AppError> {
+            let mut seen = HashSet::new();
+            let fids: Vec<u64> = fids.into_iter().filter(|fid| seen.insert(*fid)).collect();
+            if fids.len() > MAX_FIDS {
+                return Err(AppError::Validation(format!("ask for at most {} users at a time", MAX_FIDS)));
+            }
+            let types = if types.is_empty() { UserSummary::TYPES.to_vec() } else { types };
+            if let Some(unknown) = types.iter().find(|t| UserSummary::type_name(**t).is_none()) {
+                return Err(AppError::Validation(format!("user data type {} isn't part of a summary", unknown)));
+            }
+
+            let keys: Vec<String> = fids
+                .iter()
+                .flat_map(|fid| types.iter().map(move |t| format!("{}:{}", fid, t)))
+                .collect();
+            let cached = app_state.caches.user_data.get_many(&keys).await;
+
+            let mut summaries: HashMap<u64, UserSummary> = fids.iter().map(|fid| (*fid, UserSummary::new(*fid))).collect();
+            let mut missing = Vec::new();
+            for (fid, entries) in fids.iter().zip(cached.chunks(types.len())) {
+                for entry in entries {
+                    match entry {
+                        Some(Some(user_data)) => summaries.get_mut(fid).unwrap().apply(&user_data.data.user_data_body),
+                        Some(None) => {}
+                        None => missing.push(*fid),
+                    }
+                }
+            }
+            missing.dedup();
+
+            if !missing.is_empty() {
+                crate::log_debug!("user data cache miss for {} of {} fids", missing.len(), fids.len());
+            }
+            let fetched: Vec<_> = stream::iter(missing)
+                .map(|fid| async move {
+                    let params = UserDataParams { fid, user_data_type: None };
+                    let result = found(get_user_data_by_fid(State(app_state.config.clone()), Query(params)).await)
+                        .and_then(|json| {
+                            json.map(|json| serde_json::from_value::<UserDataListResponse>(json.0))
+                                .transpose()
+                                .map_err(|e| AppError::Upstream(format!("unreadable user data: {}", e)))
+                        });
+                    (fid, result)
+                })
+                .buffer_unordered(HUB_CONCURRENCY)
+                .collect()
+                .await;
+
+            for (fid, result) in fetched {
+                let messages = match result {
+                    Ok(list) => list.map(|list| list.messages).unwrap_or_default(),
+                    Err(e) => {
+                        crate::log_warn!("failed to fetch user data for fid {}: {}", fid, e);
+                        continue;
+                    }
+                };
+                let summary = summaries.get_mut(&fid).unwrap();
+                for t in &types {
+                    let user_data = messages
+                        .iter()
+                        .find(|message| Some(message.data.user_data_body.data_type.as_str()) == UserSummary::type_name(*t));
+                    // types the fid never set are cached as missing, so they aren't asked for again
+                    app_state.caches.user_data.set(&format!("{}:{}", fid, t), user_data).await;
+                    if let Some(user_data) = user_data {
+                        summary.apply(&user_data.data.user_data_body);
+                    }
+                }
+            }
+
+            Ok(fids.iter().filter_map(|fid| summaries.remove(fid)).collect())
+        }
+    }
+}
+
+/// Usernames, display names, pfps and bios for a batch of fids in one request.
+/// `types` narrows which of those are filled in; empty means all of them.
+#[server(GetUsers, "/api")]
+pub async fn get_users(fids: Vec<u64>, types: Vec<u8>) -> Result<Vec<UserSummary>, ServerFnError> {
+    let app_state = use_context::<AppState>().expect("Failed to get AppState from context");
+
+    Ok(resolve_users(&app_state, fids, types).await?)
+}
+
+/// Fills the client cache with everyone in `fids` it doesn't have yet, so a
+/// page of casts costs one `get_users` call instead of one per author.
+pub async fn prefetch_users(client_cache: &ClientCache, fids: impl IntoIterator<Item = u64>) {
+    let mut missing: Vec<u64> = fids.into_iter().filter(|fid| client_cache.get(*fid).is_none()).collect();
+    missing.sort_unstable();
+    missing.dedup();
+
+    for chunk in missing.chunks(MAX_FIDS) {
+        match get_users(chunk.to_vec(), vec![UserSummary::USERNAME, UserSummary::PFP]).await {
+            Ok(users) => users.iter().for_each(|user| client_cache.seed(user)),
+            Err(e) => {
+                crate::log_warn!("failed to prefetch {} users: {}", chunk.len(), e);
+            }
+        }
+    }
+}
+
+/// The summary for a single fid.
+pub async fn get_user(fid: u64, types: Vec<u8>) -> Result<UserSummary, ServerFnError> {
+    let users = get_users(vec![fid], types).await?;
+    Ok(users.into_iter().next().unwrap_or_else(|| UserSummary::new(fid)))
+}
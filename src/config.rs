@@ -0,0 +1,440 @@
+use cfg_if::cfg_if;
+
+cfg_if! {
+    if #[cfg(feature = "ssr")] {
+        use log::LevelFilter;
+        use std::collections::HashMap;
+        use std::fmt;
+        use std::path::Path;
+        use std::str::FromStr;
+        use std::time::Duration;
+        use thiserror::Error;
+        use url::Url;
+
+        use crate::services::signer::AppSigner;
+
+        /// read when `CONFIG_FILE` doesn't point somewhere else; fine if it's missing
+        const DEFAULT_CONFIG_FILE: &str = "thenetworktimes.toml";
+
+        #[derive(Debug, Error)]
+        pub enum ConfigError {
+            #[error("failed to read {path}: {reason}")]
+            File { path: String, reason: String },
+            #[error("invalid configuration:\n  {}", .0.join("\n  "))]
+            Invalid(Vec<String>),
+        }
+
+        /// A value that shouldn't end up in logs.
+        #[derive(Clone, PartialEq, Eq)]
+        pub struct Secret(String);
+
+        impl Secret {
+            pub fn expose(&self) -> &str {
+                &self.0
+            }
+        }
+
+        impl fmt::Debug for Secret {
+            fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+                write!(f, "Secret(..)")
+            }
+        }
+
+        /// How log lines are written: plain text to read, the default, or one JSON
+        /// object each for deployments that ship their logs.
+        #[derive(Clone, Copy, Debug, PartialEq, Eq)]
+        pub enum LogFormat {
+            Json,
+            Text,
+        }
+
+        impl FromStr for LogFormat {
+            type Err = String;
+
+            fn from_str(value: &str) -> Result<Self, Self::Err> {
+                match value.to_lowercase().as_str() {
+                    "json" => Ok(LogFormat::Json),
+                    "text" => Ok(LogFormat::Text),
+                    other => Err(format!("expected json or text, got {}", other)),
+                }
+            }
+        }
+
+        /// The fid and key casts are published under.
+        #[derive(Clone, Debug)]
+        pub struct SignerConfig {
+            pub fid: u64,
+            pub key: Secret,
+        }
+
+        /// Everything the server reads from its environment, loaded and checked once
+        /// at boot. Each key comes from the environment or, lowercased, from the
+        /// TOML file; the environment wins. Optional integrations left unset are
+        /// off rather than broken.
+        #[derive(Clone, Debug)]
+        pub struct Config {
+            pub database_url: Secret,
+            /// without it caches and sessions live in this process only
+            pub redis_url: Option<Secret>,
+            pub pool_size: usize,
+            pub log_level: LevelFilter,
+            pub log_format: LogFormat,
+            /// without it hub lookups, the live feed, sign in and publishing are off
+            pub hubble_url: Option<String>,
+            /// channel catalog root, ending in `/`
+            pub warpcast_url: Option<String>,
+            pub openai_api_key: Option<Secret>,
+            pub anthropic_api_key: Option<Secret>,
+            pub signer: Option<SignerConfig>,
+            /// fids besides the signer's own that may publish as the app and change the followed channels
+            pub editor_fids: Vec<u64>,
+            pub siwf_domain: Option<String>,
+            pub siwf_uri: Option<String>,
+            pub indexer_enabled: bool,
+            pub indexer_interval: Duration,
+            pub hub_events_enabled: bool,
+            pub hub_events_poll: Duration,
+            /// estimated llm tokens each user gets per utc day, 0 for no limit
+            pub llm_daily_tokens: u64,
+            /// take the client address from `X-Forwarded-For`; only behind a proxy that sets it
+            pub trust_proxy: bool,
+        }
+
+        impl Config {
+            /// The process environment over `CONFIG_FILE` (or `thenetworktimes.toml`
+            /// if it exists).
+            pub fn load() -> Result<Config, ConfigError> {
+                let file = file_source(std::env::var("CONFIG_FILE").ok())?;
+                Config::from_sources(std::env::vars().collect(), file)
+            }
+
+            /// Builds and validates a config, reporting every problem at once.
+            pub fn from_sources(env: HashMap<String, String>, file: toml::Table) -> Result<Config, ConfigError> {
+                let source = Source { env, file };
+                let mut problems = Vec::new();
+
+                let database_url = url_with_scheme(&source, &mut problems, "DATABASE_URL", &["postgres", "postgresql"]);
+                let redis_url = url_with_scheme(&source, &mut problems, "REDIS_URL", &["redis", "rediss", "redis+unix", "unix"]);
+                if source.get("DATABASE_URL").is_none() {
+                    problems.push("DATABASE_URL must be set".to_string());
+                }
+
+                let pool_size = parsed(&source, &mut problems, "POOL_SIZE", 8usize);
+                if pool_size == 0 {
+                    problems.push("POOL_SIZE must be at least 1".to_string());
+                }
+                let log_level = parsed(&source, &mut problems, "LOG_LEVEL", LevelFilter::Info);
+                let log_format = parsed(&source, &mut problems, "LOG_FORMAT", LogFormat::Text);
+
+                let hubble_url = url_with_scheme(&source, &mut problems, "HUBBLE_URL", &["http", "https"])
+                    .map(|url| url.trim_end_matches('/').to_string());
+                let warpcast_url = url_with_scheme(&source, &mut problems, "WARPCAST_URL", &["http", "https"])
+                    .map(|url| format!("{}/", url.trim_end_matches('/')));
+
+                let signer = match (source.get("FARCASTER_FID"), source.get("FARCASTER_SIGNER_KEY")) {
+                    (Some(fid), Some(key)) => match fid.parse::<u64>() {
+                        Ok(fid) => match AppSigner::from_hex(fid, &key) {
+                            Ok(_) => Some(SignerConfig { fid, key: Secret(key) }),
+                            Err(e) => {
+                                problems.push(e.to_string());
+                                None
+                            }
+                        },
+                        Err(e) => {
+                            problems.push(format!("FARCASTER_FID is not a number: {}", e));
+                            None
+                        }
+                    },
+                    (None, None) => None,
+                    _ => {
+                        problems.push("FARCASTER_FID and FARCASTER_SIGNER_KEY go together, set both to publish casts".to_string());
+                        None
+                    }
+                };
+
+                let editor_fids = source
+                    .get("EDITOR_FIDS")
+                    .map(|fids| {
+                        fids.split(',')
+                            .map(str::trim)
+                            .filter(|fid| !fid.is_empty())
+                            .filter_map(|fid| {
+                                fid.parse::<u64>()
+                                    .map_err(|e| problems.push(format!("EDITOR_FIDS has {:?}, which is not a fid: {}", fid, e)))
+                                    .ok()
+                            })
+                            .collect()
+                    })
+                    .unwrap_or_default();
+
+                let indexer_interval = parsed(&source, &mut problems, "INDEXER_INTERVAL_SECS", 60u64);
+                let hub_events_poll = parsed(&source, &mut problems, "HUB_EVENTS_POLL_MS", 2000u64);
+                if indexer_interval == 0 {
+                    problems.push("INDEXER_INTERVAL_SECS must be at least 1".to_string());
+                }
+                if hub_events_poll == 0 {
+                    problems.push("HUB_EVENTS_POLL_MS must be at least 1".to_string());
+                }
+
+                let config = Config {
+                    database_url: Secret(database_url.unwrap_or_default()),
+                    redis_url: redis_url.map(Secret),
+                    pool_size,
+                    log_level,
+                    log_format,
+                    hubble_url,
+                    warpcast_url,
+                    openai_api_key: source.get("OPENAI_API_KEY").map(Secret),
+                    anthropic_api_key: source.get("ANTHROPIC_API_KEY").map(Secret),
+                    signer,
+                    editor_fids,
+                    siwf_domain: source.get("SIWF_DOMAIN"),
+                    siwf_uri: source.get("SIWF_URI"),
+                    indexer_enabled: flag(&source, &mut problems, "INDEXER_ENABLED", true),
+                    indexer_interval: Duration::from_secs(indexer_interval),
+                    hub_events_enabled: flag(&source, &mut problems, "HUB_EVENTS_ENABLED", true),
+                    hub_events_poll: Duration::from_millis(hub_events_poll),
+                    llm_daily_tokens: parsed(&source, &mut problems, "LLM_DAILY_TOKENS", 200_000u64),
+                    trust_proxy: flag(&source, &mut problems, "TRUST_PROXY", false),
+                };
+
+                if problems.is_empty() {
+                    Ok(config)
+                } else {
+                    Err(ConfigError::Invalid(problems))
+                }
+            }
+
+            /// the key for a model lab, if that lab is turned on
+            pub fn provider_key(&self, lab: &str) -> Option<&Secret> {
+                match lab {
+                    "openai" => self.openai_api_key.as_ref(),
+                    "anthropic" => self.anthropic_api_key.as_ref(),
+                    _ => None,
+                }
+            }
+
+            /// whether `fid` may publish as the app and change what it follows
+            pub fn is_editor(&self, fid: u64) -> bool {
+                self.signer.as_ref().is_some_and(|signer| signer.fid == fid) || self.editor_fids.contains(&fid)
+            }
+
+            /// what's running without its settings, for the boot log
+            pub fn disabled(&self) -> Vec<&'static str> {
+                let mut disabled = Vec::new();
+                if self.redis_url.is_none() {
+                    disabled.push("a shared cache and lasting sessions (REDIS_URL)");
+                }
+                if self.hubble_url.is_none() {
+                    disabled.push("hub lookups, live feed and sign in (HUBBLE_URL)");
+                }
+                if self.warpcast_url.is_none() {
+                    disabled.push("channel catalog (WARPCAST_URL)");
+                }
+                if self.openai_api_key.is_none() {
+                    disabled.push("openai models (OPENAI_API_KEY)");
+                }
+                if self.anthropic_api_key.is_none() {
+                    disabled.push("anthropic models (ANTHROPIC_API_KEY)");
+                }
+                if self.signer.is_none() {
+                    disabled.push("publishing (FARCASTER_FID, FARCASTER_SIGNER_KEY)");
+                }
+                disabled
+            }
+        }
+
+        struct Source {
+            env: HashMap<String, String>,
+            file: toml::Table,
+        }
+
+        impl Source {
+            /// blank counts as unset, so an empty line in `.env` doesn't switch anything on
+            fn get(&self, key: &str) -> Option<String> {
+                let set = |value: String| Some(value.trim().to_string()).filter(|value| !value.is_empty());
+                self.env.get(key).cloned().and_then(set).or_else(|| {
+                    self.file
+                        .get(&key.to_lowercase())
+                        .map(|value| match value {
+                            toml::Value::String(s) => s.clone(),
+                            other => other.to_string(),
+                        })
+                        .and_then(set)
+                })
+            }
+        }
+
+        /// `CONFIG_FILE` if it's set and not blank, else the default file if it's there
+        fn file_source(config_file: Option<String>) -> Result<toml::Table, ConfigError> {
+            match config_file.filter(|path| !path.trim().is_empty()) {
+                Some(path) => read_file(Path::new(path.trim())),
+                None if Path::new(DEFAULT_CONFIG_FILE).exists() => read_file(Path::new(DEFAULT_CONFIG_FILE)),
+                None => Ok(toml::Table::new()),
+            }
+        }
+
+        fn read_file(path: &Path) -> Result<toml::Table, ConfigError> {
+            let error = |reason: String| ConfigError::File { path: path.display().to_string(), reason };
+            let text = std::fs::read_to_string(path).map_err(|e| error(e.to_string()))?;
+            text.parse::<toml::Table>().map_err(|e| error(e.to_string()))
+        }
+
+        fn parsed<T>(source: &Source, problems: &mut Vec<String>, key: &str, default: T) -> T
+        where
+            T: FromStr,
+            T::Err: fmt::Display,
+        {
+            match source.get(key) {
+                Some(value) => value.parse().unwrap_or_else(|e| {
+                    problems.push(format!("{} is invalid ({:?}): {}", key, value, e));
+                    default
+                }),
+                None => default,
+            }
+        }
+
+        fn flag(source: &Source, problems: &mut Vec<String>, key: &str, default: bool) -> bool {
+            match source.get(key).map(|value| value.to_lowercase()) {
+                Some(value) => match value.as_str() {
+                    "true" | "1" | "yes" | "on" => true,
+                    "false" | "0" | "no" | "off" => false,
+                    _ => {
+                        problems.push(format!("{} should be true or false, not {:?}", key, value));
+                        default
+                    }
+                },
+                None => default,
+            }
+        }
+
+        fn url_with_scheme(source: &Source, problems: &mut Vec<String>, key: &str, schemes: &[&str]) -> Option<String> {
+            let value = source.get(key)?;
+            match Url::parse(&value) {
+                Ok(url) if schemes.contains(&url.scheme()) => Some(value),
+                Ok(url) => {
+                    problems.push(format!("{} should be a {} url, not {}", key, schemes.join("/"), url.scheme()));
+                    None
+                }
+                Err(e) => {
+                    problems.push(format!("{} is not a url: {}", key, e));
+                    None
+                }
+            }
+        }
+
+        #[cfg(test)]
+        mod tests {
+            use super::*;
+
+            const SIGNER_KEY: &str = "0x0101010101010101010101010101010101010101010101010101010101010101";
+
+            fn env(pairs: &[(&str, &str)]) -> HashMap<String, String> {
+                pairs.iter().map(|(k, v)| (k.to_string(), v.to_string())).collect()
+            }
+
+            fn required() -> Vec<(&'static str, &'static str)> {
+                vec![("DATABASE_URL", "postgres://nwt@localhost/nwt")]
+            }
+
+            #[test]
+            fn defaults_leave_optional_integrations_off() {
+                let config = Config::from_sources(env(&required()), toml::Table::new()).unwrap();
+                assert_eq!(config.pool_size, 8);
+                assert_eq!(config.log_level, LevelFilter::Info);
+                assert_eq!(config.log_format, LogFormat::Text);
+                assert!(config.indexer_enabled && config.hub_events_enabled);
+                assert_eq!(config.indexer_interval, Duration::from_secs(60));
+                assert!(config.hubble_url.is_none() && config.signer.is_none());
+                assert!(config.provider_key("openai").is_none());
+                assert!(config.redis_url.is_none());
+                assert_eq!(config.llm_daily_tokens, 200_000);
+                assert!(!config.trust_proxy);
+                assert!(!config.is_editor(3));
+                assert_eq!(config.disabled().len(), 6);
+            }
+
+            #[test]
+            fn environment_overrides_the_file() {
+                let file: toml::Table = r#"
+                    hubble_url = "http://hub.internal/"
+                    warpcast_url = "https://api.warpcast.com/v2"
+                    pool_size = 4
+                    indexer_enabled = false
+                    openai_api_key = "from-file"
+                "#
+                .parse()
+                .unwrap();
+                let mut vars = required();
+                vars.push(("POOL_SIZE", "16"));
+                vars.push(("OPENAI_API_KEY", ""));
+                vars.push(("LOG_FORMAT", "JSON"));
+                let config = Config::from_sources(env(&vars), file).unwrap();
+
+                assert_eq!(config.pool_size, 16);
+                assert_eq!(config.log_format, LogFormat::Json);
+                assert_eq!(config.hubble_url.as_deref(), Some("http://hub.internal"));
+                assert_eq!(config.warpcast_url.as_deref(), Some("https://api.warpcast.com/v2/"));
+                assert!(!config.indexer_enabled);
+                // blank in the environment falls through to the file
+                assert_eq!(config.provider_key("openai").map(Secret::expose), Some("from-file"));
+            }
+
+            #[test]
+            fn blank_config_file_counts_as_unset() {
+                assert!(file_source(Some("  ".to_string())).is_ok());
+                assert!(file_source(None).is_ok());
+                let Err(ConfigError::File { path, .. }) = file_source(Some("missing.toml".to_string())) else {
+                    panic!("expected a missing file to be an error");
+                };
+                assert_eq!(path, "missing.toml");
+            }
+
+            #[test]
+            fn every_problem_is_reported() {
+                let vars = env(&[
+                    ("REDIS_URL", "http://localhost"),
+                    ("POOL_SIZE", "lots"),
+                    ("HUB_EVENTS_ENABLED", "maybe"),
+                    ("FARCASTER_FID", "3"),
+                ]);
+                let Err(ConfigError::Invalid(problems)) = Config::from_sources(vars, toml::Table::new()) else {
+                    panic!("expected the config to be rejected");
+                };
+                assert_eq!(problems.len(), 5, "{:?}", problems);
+                assert!(problems.iter().any(|p| p == "DATABASE_URL must be set"));
+                assert!(problems.iter().any(|p| p.starts_with("REDIS_URL should be a redis")));
+                assert!(problems.iter().any(|p| p.starts_with("FARCASTER_FID and FARCASTER_SIGNER_KEY")));
+            }
+
+            #[test]
+            fn editor_fids_are_a_comma_separated_list() {
+                let mut vars = required();
+                vars.push(("EDITOR_FIDS", "3, 194,"));
+                let config = Config::from_sources(env(&vars), toml::Table::new()).unwrap();
+                assert_eq!(config.editor_fids, vec![3, 194]);
+                assert!(config.is_editor(194) && !config.is_editor(2));
+
+                vars.pop();
+                vars.push(("EDITOR_FIDS", "3,dwr"));
+                assert!(Config::from_sources(env(&vars), toml::Table::new()).is_err());
+            }
+
+            #[test]
+            fn signer_is_checked_at_load() {
+                let mut vars = required();
+                vars.push(("FARCASTER_FID", "3"));
+                vars.push(("FARCASTER_SIGNER_KEY", SIGNER_KEY));
+                let config = Config::from_sources(env(&vars), toml::Table::new()).unwrap();
+                assert_eq!(config.signer.as_ref().map(|signer| signer.fid), Some(3));
+                assert_eq!(format!("{:?}", config.signer.as_ref().unwrap().key), "Secret(..)");
+
+                assert!(config.is_editor(3) && !config.is_editor(4));
+
+                vars.pop();
+                vars.push(("FARCASTER_SIGNER_KEY", "0xabc"));
+                assert!(Config::from_sources(env(&vars), toml::Table::new()).is_err());
+            }
+        }
+    }
+}
@@ -0,0 +1,113 @@
+use cfg_if::cfg_if;
+cfg_if! {
+if #[cfg(feature = "ssr")] {
+        use chrono::{Days, NaiveDate, NaiveDateTime};
+        use diesel::dsl::sql;
+        use diesel::prelude::*;
+        use diesel::sql_types::{Bool, Text};
+        use diesel::upsert::excluded;
+        use diesel::PgConnection;
+
+        use crate::models::indexed::{IndexedCast, IndexedReaction, NewIndexedCast, NewIndexedUserData};
+        use crate::models::search::CastSearchFilters;
+        use crate::schema::{casts, reactions, user_data};
+        use crate::time::FarcasterTimestamp;
+
+        pub fn upsert_casts(conn: &mut PgConnection, new_casts: &[NewIndexedCast]) -> QueryResult<usize> {
+            // casts are immutable once they hit the hub, so a repeat is a no-op
+            diesel::insert_into(casts::table)
+                .values(new_casts)
+                .on_conflict(casts::hash)
+                .do_nothing()
+                .execute(conn)
+        }
+
+        pub fn upsert_reactions(conn: &mut PgConnection, new_reactions: &[IndexedReaction]) -> QueryResult<usize> {
+            diesel::insert_into(reactions::table)
+                .values(new_reactions)
+                .on_conflict((reactions::fid, reactions::target_hash, reactions::reaction_type))
+                .do_nothing()
+                .execute(conn)
+        }
+
+        pub fn upsert_user_data(conn: &mut PgConnection, new_user_data: &[NewIndexedUserData]) -> QueryResult<usize> {
+            diesel::insert_into(user_data::table)
+                .values(new_user_data)
+                .on_conflict((user_data::fid, user_data::data_type))
+                .do_update()
+                .set((
+                    user_data::value.eq(excluded(user_data::value)),
+                    user_data::timestamp.eq(excluded(user_data::timestamp)),
+                    user_data::updated_at.eq(diesel::dsl::now),
+                ))
+                .execute(conn)
+        }
+
+        pub fn get_casts_by_channel(conn: &mut PgConnection, channel_id: &str, offset: i64, limit: i64) -> QueryResult<Vec<IndexedCast>> {
+            casts::table
+                .filter(casts::channel_id.eq(channel_id))
+                .order(casts::timestamp.desc())
+                .offset(offset)
+                .limit(limit)
+                .load::<IndexedCast>(conn)
+        }
+
+        /// the timestamp of the newest cast indexed for a channel, if any are
+        pub fn newest_cast_timestamp(conn: &mut PgConnection, channel_id: &str) -> QueryResult<Option<i64>> {
+            casts::table
+                .filter(casts::channel_id.eq(channel_id))
+                .select(diesel::dsl::max(casts::timestamp))
+                .first(conn)
+        }
+
+        fn farcaster_seconds(date: NaiveDate) -> i64 {
+            FarcasterTimestamp::from_datetime(date.and_hms_opt(0, 0, 0).unwrap_or_default().and_utc()).0 as i64
+        }
+
+        pub fn search_casts(conn: &mut PgConnection, filters: &CastSearchFilters, offset: i64, limit: i64) -> QueryResult<Vec<IndexedCast>> {
+            let mut query = casts::table.into_boxed();
+
+            if let Some(text) = filters.query.as_deref().map(str::trim).filter(|text| !text.is_empty()) {
+                // matches the expression behind casts_text_search_idx so the gin index gets used
+                query = query.filter(
+                    sql::<Bool>("to_tsvector('english', coalesce(text, '')) @@ websearch_to_tsquery('english', ")
+                        .bind::<Text, _>(text.to_string())
+                        .sql(")"),
+                );
+            }
+            if let Some(channel_id) = &filters.channel_id {
+                query = query.filter(casts::channel_id.eq(channel_id.clone()));
+            }
+            if let Some(fid) = filters.fid {
+                query = query.filter(casts::fid.eq(fid as i64));
+            }
+            if let Some(from_date) = filters.from_date {
+                query = query.filter(casts::timestamp.ge(farcaster_seconds(from_date)));
+            }
+            if let Some(to_date) = filters.to_date.and_then(|date| date.checked_add_days(Days::new(1))) {
+                query = query.filter(casts::timestamp.lt(farcaster_seconds(to_date)));
+            }
+            if let Some(has_embed) = filters.has_embed {
+                let embed_count = "coalesce(jsonb_array_length(raw->'data'->'castAddBody'->'embeds'), 0)";
+                let condition = if has_embed { "> 0" } else { "= 0" };
+                query = query.filter(sql::<Bool>(&format!("{} {}", embed_count, condition)));
+            }
+
+            query
+                .order(casts::timestamp.desc())
+                .offset(offset)
+                .limit(limit)
+                .load::<IndexedCast>(conn)
+        }
+
+        /// fids out of `fids` whose user data hasn't been indexed since `since`
+        pub fn get_stale_user_data_fids(conn: &mut PgConnection, fids: &[i64], since: NaiveDateTime) -> QueryResult<Vec<i64>> {
+            let fresh: Vec<i64> = user_data::table
+                .filter(user_data::fid.eq_any(fids))
+                .filter(user_data::updated_at.ge(since))
+                .select(user_data::fid)
+                .distinct()
+                .load(conn)?;
+            Ok(fids.iter().copied().filter(|fid| !fresh.contains(fid)).collect())
+        }
+}}
@@ -5,19 +5,58 @@ if #[cfg(feature = "ssr")] {
         use diesel::PgConnection;
         use deadpool_diesel::{Manager, Pool, Runtime};
 
+        use crate::database::repository::{RepositoryError, RepositoryResult};
         use crate::models::conversations::{NewMessage, Thread, Message};
         use crate::schema::{threads, messages};
 
         pub type DbPool = Pool<Manager<PgConnection>>;
 
-        pub fn establish_connection(database_url: &str) -> DbPool {
+        pub fn establish_connection(database_url: &str, max_size: usize) -> DbPool {
             let manager = Manager::new(database_url, Runtime::Tokio1);
             Pool::builder(manager)
-                .max_size(8)
+                .max_size(max_size)
                 .build()
                 .expect("Failed to create pool.")
         }
+
+        /// runs `f` on a pooled connection, keeping which layer failed
+        #[tracing::instrument(name = "db", level = "debug", skip_all)]
+        pub async fn interact<T, F>(pool: &DbPool, f: F) -> RepositoryResult<T>
+        where
+            F: FnOnce(&mut PgConnection) -> QueryResult<T> + Send + 'static,
+            T: Send + 'static,
+        {
+            let conn = pool
+                .get()
+                .await
+                .map_err(|e| RepositoryError::Pool(e.to_string()))?;
+            conn.interact(f)
+                .await
+                .map_err(|e| RepositoryError::Interaction(e.to_string()))?
+                .map_err(RepositoryError::from)
+        }
         
+        /// how `fid` stands with a thread
+        #[derive(Debug, Clone, Copy, PartialEq, Eq)]
+        pub enum ThreadAccess {
+            Owner,
+            Missing,
+            Forbidden,
+        }
+
+        pub fn thread_access(conn: &mut PgConnection, thread_id: &str, fid: i64) -> QueryResult<ThreadAccess> {
+            let owner = threads::table
+                .find(thread_id)
+                .select(threads::owner)
+                .first::<i64>(conn)
+                .optional()?;
+            Ok(match owner {
+                Some(owner) if owner == fid => ThreadAccess::Owner,
+                Some(_) => ThreadAccess::Forbidden,
+                None => ThreadAccess::Missing,
+            })
+        }
+
         pub fn create_thread(conn: &mut PgConnection, new_thread: &Thread) -> QueryResult<usize> {
             diesel::insert_into(threads::table)
                 .values(new_thread)
@@ -1 +1,4 @@
+pub mod casts;
 pub mod db;
+pub mod repository;
+pub mod settings;
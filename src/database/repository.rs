@@ -0,0 +1,274 @@
+use cfg_if::cfg_if;
+cfg_if! {
+if #[cfg(feature = "ssr")] {
+        use chrono::{NaiveDateTime, Utc};
+        use diesel::prelude::*;
+        use diesel::PgConnection;
+        use thiserror::Error;
+
+        use crate::error_template::AppError;
+        use crate::database::db::{add_message, create_thread, get_messages_by_thread, thread_access, DbPool, ThreadAccess};
+        use crate::models::conversations::{Message, NewMessage, Thread, ThreadShare};
+        use crate::schema::{messages, thread_shares, threads};
+
+        #[derive(Debug, Error)]
+        pub enum RepositoryError {
+            /// missing, or not the caller's to see; the two read the same from outside
+            #[error("{0} not found")]
+            NotFound(&'static str),
+            #[error("pool error: {0}")]
+            Pool(String),
+            #[error("interaction error: {0}")]
+            Interaction(String),
+            #[error("database error: {0}")]
+            Database(#[from] diesel::result::Error),
+        }
+
+        pub type RepositoryResult<T> = Result<T, RepositoryError>;
+
+        impl From<RepositoryError> for AppError {
+            fn from(error: RepositoryError) -> Self {
+                match error {
+                    RepositoryError::NotFound(what) => AppError::NotFound(what.to_string()),
+                    other => AppError::Internal(other.to_string()),
+                }
+            }
+        }
+
+        /// a `LIKE` pattern matching `query` anywhere, with its own `%` and `_` escaped
+        fn contains_pattern(query: &str) -> String {
+            let escaped = query.replace('\\', "\\\\").replace('%', "\\%").replace('_', "\\_");
+            format!("%{}%", escaped)
+        }
+
+        /// runs `f` on a pooled connection, keeping which layer failed
+        async fn run<T, F>(pool: &DbPool, f: F) -> RepositoryResult<T>
+        where
+            F: FnOnce(&mut PgConnection) -> RepositoryResult<T> + Send + 'static,
+            T: Send + 'static,
+        {
+            let conn = pool
+                .get()
+                .await
+                .map_err(|e| RepositoryError::Pool(e.to_string()))?;
+            conn.interact(f)
+                .await
+                .map_err(|e| RepositoryError::Interaction(e.to_string()))?
+        }
+
+        fn require_owner(conn: &mut PgConnection, thread_id: &str, fid: i64) -> RepositoryResult<()> {
+            match thread_access(conn, thread_id, fid)? {
+                ThreadAccess::Owner => Ok(()),
+                ThreadAccess::Missing | ThreadAccess::Forbidden => Err(RepositoryError::NotFound("thread")),
+            }
+        }
+
+        /// Threads and their share links. Every call that touches an existing
+        /// thread takes the caller's fid and acts as if other people's threads
+        /// don't exist.
+        #[derive(Clone)]
+        pub struct ThreadRepository {
+            pool: DbPool,
+        }
+
+        impl ThreadRepository {
+            pub fn new(pool: DbPool) -> Self {
+                ThreadRepository { pool }
+            }
+
+            pub async fn access(&self, thread_id: &str, fid: i64) -> RepositoryResult<ThreadAccess> {
+                let thread_id = thread_id.to_string();
+                run(&self.pool, move |conn| Ok(thread_access(conn, &thread_id, fid)?)).await
+            }
+
+            /// `Ok` only if `fid` owns the thread
+            pub async fn require_owner(&self, thread_id: &str, fid: i64) -> RepositoryResult<()> {
+                let thread_id = thread_id.to_string();
+                run(&self.pool, move |conn| require_owner(conn, &thread_id, fid)).await
+            }
+
+            pub async fn create(&self, thread: Thread) -> RepositoryResult<Thread> {
+                run(&self.pool, move |conn| {
+                    create_thread(conn, &thread)?;
+                    Ok(thread)
+                })
+                .await
+            }
+
+            /// newest first
+            pub async fn list(&self, fid: i64) -> RepositoryResult<Vec<Thread>> {
+                run(&self.pool, move |conn| {
+                    Ok(threads::table
+                        .filter(threads::owner.eq(fid))
+                        .order(threads::created_at.desc())
+                        .load::<Thread>(conn)?)
+                })
+                .await
+            }
+
+            /// threads whose id or any message contains `query`, taken literally
+            pub async fn search(&self, fid: i64, query: &str) -> RepositoryResult<Vec<Thread>> {
+                let pattern = contains_pattern(query);
+                run(&self.pool, move |conn| {
+                    Ok(threads::table
+                        .left_join(messages::table)
+                        .filter(threads::owner.eq(fid))
+                        .filter(
+                            threads::id
+                                .like(&pattern)
+                                .escape('\\')
+                                .or(messages::content.like(&pattern).escape('\\')),
+                        )
+                        .select(threads::all_columns)
+                        .distinct()
+                        .load::<Thread>(conn)?)
+                })
+                .await
+            }
+
+            pub async fn rename(&self, thread_id: &str, fid: i64, title: &str) -> RepositoryResult<()> {
+                let thread_id = thread_id.to_string();
+                let title = title.to_string();
+                run(&self.pool, move |conn| {
+                    conn.transaction(|conn| {
+                        require_owner(conn, &thread_id, fid)?;
+                        diesel::update(threads::table.find(&thread_id))
+                            .set((
+                                threads::title.eq(&title),
+                                threads::updated_at.eq(Utc::now().naive_utc()),
+                            ))
+                            .execute(conn)?;
+                        Ok(())
+                    })
+                })
+                .await
+            }
+
+            /// drops the thread along with its messages
+            pub async fn delete(&self, thread_id: &str, fid: i64) -> RepositoryResult<()> {
+                let thread_id = thread_id.to_string();
+                run(&self.pool, move |conn| {
+                    conn.transaction(|conn| {
+                        require_owner(conn, &thread_id, fid)?;
+                        diesel::delete(messages::table.filter(messages::thread_id.eq(&thread_id)))
+                            .execute(conn)?;
+                        diesel::delete(threads::table.find(&thread_id))
+                            .execute(conn)?;
+                        Ok(())
+                    })
+                })
+                .await
+            }
+
+            pub async fn share(&self, share: ThreadShare, fid: i64) -> RepositoryResult<ThreadShare> {
+                run(&self.pool, move |conn| {
+                    require_owner(conn, &share.thread_id, fid)?;
+                    diesel::insert_into(thread_shares::table)
+                        .values(&share)
+                        .execute(conn)?;
+                    Ok(share)
+                })
+                .await
+            }
+
+            pub async fn revoke_share(&self, token: &str, fid: i64) -> RepositoryResult<()> {
+                let token = token.to_string();
+                let revoked = run(&self.pool, move |conn| {
+                    let owned_threads = threads::table
+                        .filter(threads::owner.eq(fid))
+                        .select(threads::id);
+                    Ok(diesel::update(
+                        thread_shares::table
+                            .filter(thread_shares::token.eq(token))
+                            .filter(thread_shares::thread_id.eq_any(owned_threads)),
+                    )
+                    .set(thread_shares::revoked_at.eq(Utc::now().naive_utc()))
+                    .execute(conn)?)
+                })
+                .await?;
+
+                if revoked == 0 {
+                    return Err(RepositoryError::NotFound("share link"));
+                }
+                Ok(())
+            }
+
+            /// The thread behind a share token, if the link is still live at `now`.
+            /// No fid: holding the token is the permission.
+            pub async fn shared(&self, token: &str, now: NaiveDateTime) -> RepositoryResult<(Thread, Vec<Message>)> {
+                let token = token.to_string();
+                run(&self.pool, move |conn| {
+                    let share = thread_shares::table
+                        .find(token)
+                        .first::<ThreadShare>(conn)
+                        .optional()?
+                        .filter(|share| share.is_active(now))
+                        .ok_or(RepositoryError::NotFound("share link"))?;
+                    let thread = threads::table.find(&share.thread_id).first::<Thread>(conn)?;
+                    let thread_messages = messages::table
+                        .filter(messages::thread_id.eq(&share.thread_id))
+                        .order((messages::created_at.asc(), messages::id.asc()))
+                        .load::<Message>(conn)?;
+                    Ok((thread, thread_messages))
+                })
+                .await
+            }
+        }
+
+        #[derive(Clone)]
+        pub struct MessageRepository {
+            pool: DbPool,
+        }
+
+        impl MessageRepository {
+            pub fn new(pool: DbPool) -> Self {
+                MessageRepository { pool }
+            }
+
+            /// A thread's history, for feeding back to a model. Callers check access first.
+            pub async fn for_thread(&self, thread_id: &str) -> RepositoryResult<Vec<Message>> {
+                let thread_id = thread_id.to_string();
+                run(&self.pool, move |conn| Ok(get_messages_by_thread(conn, &thread_id)?)).await
+            }
+
+            /// every message across `fid`'s threads
+            pub async fn for_owner(&self, fid: i64) -> RepositoryResult<Vec<Message>> {
+                run(&self.pool, move |conn| {
+                    Ok(messages::table
+                        .inner_join(threads::table)
+                        .filter(threads::owner.eq(fid))
+                        .select(messages::all_columns)
+                        .load::<Message>(conn)?)
+                })
+                .await
+            }
+
+            /// Appends to a thread `fid` owns. With `start_thread`, a thread that
+            /// doesn't exist yet is created for them first; someone else's never is.
+            pub async fn add(&self, fid: i64, message: NewMessage, start_thread: bool) -> RepositoryResult<()> {
+                run(&self.pool, move |conn| {
+                    conn.transaction(|conn| {
+                        match thread_access(conn, &message.thread_id, fid)? {
+                            ThreadAccess::Owner => {}
+                            ThreadAccess::Missing if start_thread => {
+                                let thread = Thread {
+                                    id: message.thread_id.clone(),
+                                    created_at: None,
+                                    updated_at: None,
+                                    title: None,
+                                    owner: fid,
+                                };
+                                create_thread(conn, &thread)?;
+                            }
+                            ThreadAccess::Missing | ThreadAccess::Forbidden => {
+                                return Err(RepositoryError::NotFound("thread"));
+                            }
+                        }
+                        add_message(conn, &message)?;
+                        Ok(())
+                    })
+                })
+                .await
+            }
+        }
+}}
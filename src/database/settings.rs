@@ -0,0 +1,25 @@
+use cfg_if::cfg_if;
+cfg_if! {
+if #[cfg(feature = "ssr")] {
+        use diesel::prelude::*;
+        use diesel::PgConnection;
+
+        use crate::models::settings::UserSettings;
+        use crate::schema::user_settings;
+
+        pub fn get_user_settings(conn: &mut PgConnection, fid: i64) -> QueryResult<Option<UserSettings>> {
+            user_settings::table
+                .find(fid)
+                .first::<UserSettings>(conn)
+                .optional()
+        }
+
+        pub fn upsert_user_settings(conn: &mut PgConnection, settings: &UserSettings) -> QueryResult<usize> {
+            diesel::insert_into(user_settings::table)
+                .values(settings)
+                .on_conflict(user_settings::fid)
+                .do_update()
+                .set(settings)
+                .execute(conn)
+        }
+}}
@@ -1,17 +1,131 @@
 use http::status::StatusCode;
 use leptos::*;
+use leptos::server_fn::error::ServerFnErrorErr;
+use std::str::FromStr;
 use thiserror::Error;
 
-#[derive(Clone, Debug, Error)]
+/// Every way a request can fail, whether it comes back from a server fn, a
+/// REST handler or the router. The messages double as the wire format inside
+/// `ServerFnError::ServerError`, so `FromStr` has to keep reading them back.
+#[derive(Clone, Debug, Error, PartialEq, Eq)]
 pub enum AppError {
-    #[error("Not Found")]
-    NotFound,
+    /// what's missing, e.g. "thread"; also covers things the caller may not see
+    #[error("{0} not found")]
+    NotFound(String),
+    #[error("sign in to continue")]
+    Unauthorized,
+    #[error("invalid input: {0}")]
+    Validation(String),
+    /// the hub, warpcast or a model provider let us down
+    #[error("upstream error: {0}")]
+    Upstream(String),
+    #[error("too many requests, try again in {retry_after}s")]
+    RateLimited { retry_after: u64 },
+    #[error("internal error: {0}")]
+    Internal(String),
 }
 
+const SERVER_FN_PREFIX: &str = "error running server function: ";
+
 impl AppError {
     pub fn status_code(&self) -> StatusCode {
         match self {
-            AppError::NotFound => StatusCode::NOT_FOUND,
+            AppError::NotFound(_) => StatusCode::NOT_FOUND,
+            AppError::Unauthorized => StatusCode::UNAUTHORIZED,
+            AppError::Validation(_) => StatusCode::UNPROCESSABLE_ENTITY,
+            AppError::Upstream(_) => StatusCode::BAD_GATEWAY,
+            AppError::RateLimited { .. } => StatusCode::TOO_MANY_REQUESTS,
+            AppError::Internal(_) => StatusCode::INTERNAL_SERVER_ERROR,
+        }
+    }
+
+    /// stable name for the `error` field of JSON bodies
+    pub fn kind(&self) -> &'static str {
+        match self {
+            AppError::NotFound(_) => "not_found",
+            AppError::Unauthorized => "unauthorized",
+            AppError::Validation(_) => "validation",
+            AppError::Upstream(_) => "upstream",
+            AppError::RateLimited { .. } => "rate_limited",
+            AppError::Internal(_) => "internal",
+        }
+    }
+
+    /// Reads a message back into the error that produced it. Anything
+    /// unrecognised becomes `Internal`.
+    pub fn from_message(message: &str) -> AppError {
+        let message = message.strip_prefix(SERVER_FN_PREFIX).unwrap_or(message);
+        if message == AppError::Unauthorized.to_string() {
+            AppError::Unauthorized
+        } else if let Some(reason) = message.strip_prefix("invalid input: ") {
+            AppError::Validation(reason.to_string())
+        } else if let Some(reason) = message.strip_prefix("upstream error: ") {
+            AppError::Upstream(reason.to_string())
+        } else if let Some(reason) = message.strip_prefix("internal error: ") {
+            AppError::Internal(reason.to_string())
+        } else if let Some(retry_after) = message
+            .strip_prefix("too many requests, try again in ")
+            .and_then(|rest| rest.strip_suffix('s'))
+            .and_then(|secs| secs.parse().ok())
+        {
+            AppError::RateLimited { retry_after }
+        } else if let Some(what) = message.strip_suffix(" not found") {
+            AppError::NotFound(what.to_string())
+        } else {
+            AppError::Internal(message.to_string())
+        }
+    }
+
+    /// What it's safe to show outside the server. Internal details stay in the logs.
+    pub fn public_message(&self) -> String {
+        match self {
+            AppError::Internal(_) => "something went wrong on our end".to_string(),
+            other => other.to_string(),
+        }
+    }
+}
+
+impl FromStr for AppError {
+    type Err = std::convert::Infallible;
+
+    fn from_str(message: &str) -> Result<Self, Self::Err> {
+        Ok(AppError::from_message(message))
+    }
+}
+
+// the other direction comes from server_fn's blanket impl for `std::error::Error`
+impl From<ServerFnError> for AppError {
+    fn from(error: ServerFnError) -> Self {
+        match error {
+            ServerFnError::ServerError(message) => AppError::from_message(&message),
+            ServerFnError::Request(e) => AppError::Upstream(format!("request failed: {}", e)),
+            ServerFnError::Args(e) | ServerFnError::MissingArg(e) => AppError::Validation(e),
+            other => AppError::Internal(other.to_string()),
+        }
+    }
+}
+
+cfg_if::cfg_if! {
+    if #[cfg(feature = "ssr")] {
+        use axum::response::{IntoResponse, Response};
+        use axum::Json;
+        use http::header::{HeaderValue, RETRY_AFTER};
+
+        impl IntoResponse for AppError {
+            fn into_response(self) -> Response {
+                if let AppError::Internal(detail) = &self {
+                    crate::log_warn!("internal error: {}", detail);
+                }
+                let body = Json(serde_json::json!({
+                    "error": self.kind(),
+                    "message": self.public_message(),
+                }));
+                let mut response = (self.status_code(), body).into_response();
+                if let AppError::RateLimited { retry_after } = self {
+                    response.headers_mut().insert(RETRY_AFTER, HeaderValue::from(retry_after));
+                }
+                response
+            }
         }
     }
 }
@@ -33,12 +147,20 @@ pub fn ErrorTemplate(
     // Get Errors from Signal
     let errors = errors.get_untracked();
 
-    // Downcast lets us take a type that implements `std::error::Error`
+    // Downcast lets us take a type that implements `std::error::Error`;
+    // server fn failures carry an `AppError` in their message
     let errors: Vec<AppError> = errors
         .into_iter()
-        .filter_map(|(_k, v)| v.downcast_ref::<AppError>().cloned())
+        .map(|(_k, v)| {
+            if let Some(error) = v.downcast_ref::<AppError>() {
+                error.clone()
+            } else if let Some(error) = v.downcast_ref::<ServerFnErrorErr>() {
+                AppError::from(ServerFnError::from(error.clone()))
+            } else {
+                AppError::Internal(v.to_string())
+            }
+        })
         .collect();
-    println!("Errors: {errors:#?}");
 
     // Only the response code for the first error is actually sent from the server
     // this may be customized by the specific application
@@ -46,27 +168,76 @@ pub fn ErrorTemplate(
     {
         use leptos_axum::ResponseOptions;
         let response = use_context::<ResponseOptions>();
-        if let Some(response) = response {
-            response.set_status(errors[0].status_code());
+        if let (Some(response), Some(error)) = (response, errors.first()) {
+            response.set_status(error.status_code());
         }
     }
 
     view! {
-        <h1>{if errors.len() > 1 {"Errors"} else {"Error"}}</h1>
-        <For
-            // a function that returns the items we're iterating over; a signal is fine
-            each= move || {errors.clone().into_iter().enumerate()}
-            // a unique key for each item as a reference
-            key=|(index, _error)| *index
-            // renders each item to a view
-            children=move |error| {
-                let error_string = error.1.to_string();
-                let error_code= error.1.status_code();
-                view! {
-                    <h2>{error_code.to_string()}</h2>
-                    <p>"Error: " {error_string}</p>
+        <div class="w-11/12 lg:w-8/12 xl:w-6/12 mx-auto pt-12 pb-12 flex flex-col space-y-8">
+            <For
+                // a function that returns the items we're iterating over; a signal is fine
+                each= move || {errors.clone().into_iter().enumerate()}
+                // a unique key for each item as a reference
+                key=|(index, _error)| *index
+                // renders each item to a view
+                children=move |(_, error)| {
+                    let hint = match &error {
+                        AppError::NotFound(_) => "it may have been moved, deleted or never existed.",
+                        AppError::Unauthorized => "sign in with farcaster from the top bar, then try again.",
+                        AppError::Validation(_) => "check what you entered and try again.",
+                        AppError::Upstream(_) => "a service we rely on isn't answering, try again in a bit.",
+                        AppError::RateLimited { .. } => "you're going a little fast, wait a moment.",
+                        AppError::Internal(_) => "it's on our end, try again in a bit.",
+                    };
+                    view! {
+                        <div class="flex flex-col space-y-2">
+                            <h1 class="text-4xl text-teal-700 dark:text-mint-400">{error.status_code().as_u16()}</h1>
+                            <h2 class="text-xl text-teal-600 dark:text-aqua-300">{error.public_message()}</h2>
+                            <p class="text-sm text-gray-600 dark:text-gray-400">{hint}</p>
+                            <a href="/" class="text-sm text-seafoam-600 dark:text-aqua-400 hover:underline">"back to the front page"</a>
+                        </div>
+                    }
                 }
-            }
-        />
+            />
+        </div>
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn messages_parse_back_into_the_same_error() {
+        let errors = [
+            AppError::NotFound("thread".to_string()),
+            AppError::Unauthorized,
+            AppError::Validation("unknown model gpt-2".to_string()),
+            AppError::Upstream("hub returned 503".to_string()),
+            AppError::RateLimited { retry_after: 30 },
+            AppError::Internal("pool timed out".to_string()),
+        ];
+        for error in errors {
+            assert_eq!(error.to_string().parse::<AppError>(), Ok(error));
+        }
+    }
+
+    #[test]
+    fn survives_a_trip_through_server_fn_error() {
+        let error = AppError::NotFound("share link".to_string());
+        let server_error: ServerFnError = error.clone().into();
+        assert_eq!(AppError::from(server_error.clone()), error);
+        // what the client sees once the server fn machinery has formatted it
+        assert_eq!(server_error.to_string().parse::<AppError>(), Ok(error));
+    }
+
+    #[test]
+    fn unrecognised_messages_are_internal() {
+        assert_eq!(
+            AppError::from(ServerFnError::ServerError("boom".to_string())),
+            AppError::Internal("boom".to_string()),
+        );
+        assert_eq!(AppError::Internal("password=hunter2".to_string()).public_message(), "something went wrong on our end");
     }
 }
@@ -2,14 +2,25 @@ use cfg_if::cfg_if;
 
 cfg_if! {
     if #[cfg(feature = "ssr")] {
-        use axum::{extract::State, Json};
-        use diesel::prelude::*;
-        use deadpool_diesel::postgres::{Manager, Pool, Runtime};
-        use http::StatusCode;
+        use axum::{
+            extract::{Query, State},
+            response::sse::Sse,
+            Json,
+        };
+        use http::HeaderMap;
         use serde::Deserialize;
+        use std::sync::Arc;
+        use tokio::sync::mpsc;
+        use tracing::Instrument;
+        use crate::components::chat::{send_message_stream, SseStream};
+        use crate::config::Config;
         use crate::database::db::DbPool;
-        use crate::models::conversations::{NewMessage, Thread};
-        use log::error;
+        use crate::database::repository::{MessageRepository, ThreadRepository};
+        use crate::error_template::AppError;
+        use crate::models::conversations::NewMessage;
+        use crate::services::auth::session_user;
+        use crate::services::cache::CacheStore;
+        use crate::services::rate_limit::record_llm_usage;
 
         #[derive(Deserialize)]
         pub struct MessagePayload {
@@ -22,61 +33,68 @@ cfg_if! {
 
         pub async fn create_message(
             State(pool): State<DbPool>,
+            State(cache): State<CacheStore>,
+            headers: HeaderMap,
             Json(payload): Json<MessagePayload>,
-        ) -> Result<(), StatusCode> {
-            use crate::schema::{messages, threads};
+        ) -> Result<(), AppError> {
+            let owner = session_user(&cache, &headers)
+                .await
+                .ok_or(AppError::Unauthorized)?
+                .fid as i64;
 
             let new_message = NewMessage {
-                thread_id: payload.thread_id.clone(),
+                thread_id: payload.thread_id,
                 content: Some(payload.content),
                 role: payload.role,
                 active_model: payload.active_model,
                 active_lab: payload.active_lab,
             };
 
-            let conn = pool.get().await.map_err(|err| {
-                tracing::error!("Failed to get database connection: {:?}", err);
-                StatusCode::INTERNAL_SERVER_ERROR
-            })?;
-
-            let result: Result<(), diesel::result::Error> = conn.interact(move |conn| {
-                if threads::table.find(&payload.thread_id).first::<Thread>(conn).optional()?.is_none() {
-                    let new_thread = Thread {
-                        id: payload.thread_id.clone(),
-                        created_at: None,
-                        updated_at: None,
-                        title: None,
-                    };
-                    diesel::insert_into(threads::table)
-                        .values(&new_thread)
-                        .execute(conn)?;
-                }
-
-                diesel::insert_into(messages::table)
-                    .values(&new_message)
-                    .execute(conn)?;
+            MessageRepository::new(pool)
+                .add(owner, new_message, true)
+                .await?;
+            Ok(())
+        }
 
-                Ok(())
-            }).await.map_err(|err| {
-                error!("Failed to insert message: {}", err);
-                StatusCode::INTERNAL_SERVER_ERROR
-            })?;
+        #[derive(Deserialize)]
+        pub struct StreamParams {
+            thread_id: String,
+            model: String,
+            lab: String,
+        }
 
-            result.map_err(|err| {
-                error!("Failed to insert message: {}", err);
-                StatusCode::INTERNAL_SERVER_ERROR
-            })?;
+        /// Streams the model's reply to a thread the caller owns, reading history
+        /// through the shared pool.
+        pub async fn stream_message(
+            State(pool): State<DbPool>,
+            State(config): State<Arc<Config>>,
+            State(cache): State<CacheStore>,
+            headers: HeaderMap,
+            Query(params): Query<StreamParams>,
+        ) -> Result<Sse<SseStream>, AppError> {
+            let fid = session_user(&cache, &headers)
+                .await
+                .ok_or(AppError::Unauthorized)?
+                .fid;
+            let owner = fid as i64;
 
-            Ok(())
-        }
+            ThreadRepository::new(pool.clone())
+                .require_owner(&params.thread_id, owner)
+                .await?;
+            if config.provider_key(&params.lab).is_none() {
+                return Err(AppError::Validation(format!("{} models aren't available on this server", params.lab)));
+            }
 
-        pub fn setup_database(database_url: &str) -> DbPool {
-            let manager = Manager::new(database_url, Runtime::Tokio1);
-            let pool = Pool::builder(manager)
-                .max_size(8)
-                .build()
-                .expect("Failed to create pool.");
-            pool
+            let (tx, rx) = mpsc::channel(1);
+            // the stream outlives the request, but its logs should still carry the request's id
+            tokio::spawn(
+                async move {
+                    let tokens = send_message_stream(pool, &config, params.thread_id, params.model, params.lab, tx).await;
+                    record_llm_usage(&cache, fid, tokens).await;
+                }
+                .in_current_span(),
+            );
+            Ok(Sse::new(SseStream { receiver: rx }))
         }
     }
 }
@@ -1,5 +1,5 @@
-#![feature(iter_intersperse)]
 use cfg_if::cfg_if;
+pub mod config;
 pub mod error_template;
 pub mod fileserv;
 pub mod handlers;
@@ -12,6 +12,7 @@ pub mod database;
 pub mod models;
 pub mod schema;
 pub mod services;
+pub mod time;
 pub mod wogging;
 
 cfg_if! { if #[cfg(feature = "hydrate")] {
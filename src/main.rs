@@ -4,51 +4,83 @@ cfg_if! {
     if #[cfg(feature = "ssr")] {
         use axum::{
             body::Body as AxumBody,
-            extract::{Query, State},
+            extract::State,
             http::Request,
+            middleware,
             response::IntoResponse,
             routing::{get, post},
             Router,
-            response::sse::Sse,
         };
         use dotenv::dotenv;
         use leptos::*;
         use leptos_axum::{generate_route_list, handle_server_fns_with_context, LeptosRoutes};
-        use tokio::sync::mpsc;
-        use redis::Client as RedisClient; 
-        use std::collections::HashMap;
+        use axum::http::HeaderName;
+        use std::net::SocketAddr;
+        use std::sync::Arc;
+        use tower::ServiceBuilder;
+        use tower_http::request_id::{MakeRequestUuid, PropagateRequestIdLayer, SetRequestIdLayer};
+        use tower_http::trace::TraceLayer;
+        use tracing::Instrument;
         use thenetworktimes::app::*;
+        use thenetworktimes::config::Config;
         use thenetworktimes::fileserv::file_and_error_handler;
-        use thenetworktimes::components::chat::{SseStream, send_message_stream};
         use thenetworktimes::database::db::establish_connection;
+        use thenetworktimes::services::cache::{CacheStore, Caches};
         use thenetworktimes::state::AppState;
         use thenetworktimes::wogging;
-        use thenetworktimes::handlers::create_message;
+        use thenetworktimes::handlers::{create_message, stream_message};
         use thenetworktimes::services::hubble::*;
+        use thenetworktimes::services::hub_events::{spawn_hub_event_stream, stream_channel_casts, CastFeed, HubEventSettings};
+        use thenetworktimes::services::indexer::{spawn_indexer, IndexerSettings};
+        use thenetworktimes::services::rate_limit::rate_limit;
 
         #[tokio::main]
         async fn main() {
         
             dotenv().ok();
-            wogging::init_logging();
-//            env_logger::init_from_env(Env::default().default_filter_or("info"));
+            // nothing is logging yet, so a bad config goes straight to stderr
+            let config = match Config::load() {
+                Ok(config) => Arc::new(config),
+                Err(e) => {
+                    eprintln!("{}", e);
+                    std::process::exit(1);
+                }
+            };
+            wogging::init_logging(config.log_level, config.log_format);
+            for integration in config.disabled() {
+                logging::warn!("running without {}", integration);
+            }
         
             let conf = get_configuration(None).await.unwrap();
             let leptos_options = conf.leptos_options.clone();
             let addr = leptos_options.site_addr;
             let routes = generate_route_list(App);
         
-            let database_url = std::env::var("DATABASE_URL").expect("DATABASE_URL must be set");
-            let pool = establish_connection(&database_url);
+            let pool = establish_connection(config.database_url.expose(), config.pool_size);
+
+            // connects on first use, and keeps the app up if redis never shows
+            let cache = CacheStore::new(config.redis_url.as_ref().map(|url| url.expose()))
+                .expect("REDIS_URL was checked at load");
+            let caches = Caches::new(&cache);
+
+            if config.indexer_enabled {
+                spawn_indexer(pool.clone(), config.clone(), caches.clone(), IndexerSettings::from_config(&config));
+            }
+
+            let cast_feed = CastFeed::new(256);
+            if config.hub_events_enabled {
+                if let Some(settings) = HubEventSettings::from_config(&config) {
+                    spawn_hub_event_stream(pool.clone(), cast_feed.clone(), settings);
+                }
+            }
 
-            let redis_url = std::env::var("REDIS_URL").expect("REDIS_URL must be set");
-            let redis_client = RedisClient::open(redis_url).expect("failed to create Redis client");
-            let redis_conn = redis_client.get_multiplexed_async_connection().await.expect("failed to create redis connection pool");
-        
             let app_state = AppState {
                 leptos_options: leptos_options.clone(),
                 pool: pool.clone(),
-                redis_pool: redis_conn,
+                cache,
+                caches,
+                cast_feed,
+                config,
             };
         
         
@@ -56,15 +88,19 @@ cfg_if! {
                 State(app_state): State<AppState>,
                 request: Request<AxumBody>,
             ) -> impl IntoResponse {
+                let span = tracing::info_span!("server_fn", name = %request.uri().path().trim_start_matches("/api/"));
                 handle_server_fns_with_context(
                     move || {
                         provide_context(app_state.clone());
                     },
                     request,
                 )
+                .instrument(span)
                 .await
             }
         
+            let request_id = HeaderName::from_static(wogging::REQUEST_ID_HEADER);
+
             let app = Router::new()
                 .route(
                     "/api/*fn_name",
@@ -79,6 +115,7 @@ cfg_if! {
                 .route("/api/castsByChannel/:channel", get(get_casts_by_parent))
                 .route("/api/castsByMention/:fid", get(get_casts_by_mention))
                 .route("/api/reactionsByCast", get(get_reactions_by_cast))
+                .route("/api/cast_stream", get(stream_channel_casts))
                 .leptos_routes_with_handler(routes, get(|State(app_state): State<AppState>, request: Request<AxumBody>| async move {
                     let handler = leptos_axum::render_app_async_with_context(
                         app_state.leptos_options.clone(),
@@ -90,24 +127,22 @@ cfg_if! {
         
                     handler(request).await.into_response()
                 }))
-                .route("/api/send_message_stream", axum::routing::get(|Query(params): Query<HashMap<String, String>>| async move {
-                    let (tx, rx) = mpsc::channel(1);
-                    if let (Some(thread_id), Some(model), Some(lab)) = (params.get("thread_id"), params.get("model"), params.get("lab")) {
-                        let thread_id = thread_id.clone();
-                        let model = model.clone();
-                        let lab = lab.clone();
-                        tokio::spawn(async move {
-                            send_message_stream(thread_id, model, lab, tx).await;
-                        });
-                    }
-                    Sse::new(SseStream { receiver: rx })
-                }))
+                .route("/api/send_message_stream", get(stream_message))
                 .fallback(file_and_error_handler)
+                // per route limits on the llm endpoints and hub proxies
+                .layer(middleware::from_fn_with_state(app_state.clone(), rate_limit))
+                .layer(
+                    ServiceBuilder::new()
+                        .layer(SetRequestIdLayer::new(request_id.clone(), MakeRequestUuid))
+                        .layer(TraceLayer::new_for_http().make_span_with(wogging::request_span))
+                        .layer(PropagateRequestIdLayer::new(request_id)),
+                )
                 .with_state(app_state);
         
             let listener = tokio::net::TcpListener::bind(&addr).await.unwrap();
             logging::log!("listening on http://{}", &addr);
-            axum::serve(listener, app.into_make_service()).await.unwrap();
+            // the rate limiter keys anonymous clients by their address
+            axum::serve(listener, app.into_make_service_with_connect_info::<SocketAddr>()).await.unwrap();
         }
     } else {
         pub fn main() {
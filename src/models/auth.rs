@@ -0,0 +1,16 @@
+use serde::{Deserialize, Serialize};
+
+/// Whoever the session cookie belongs to.
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+pub struct SessionUser {
+    pub fid: u64,
+    /// custody address that signed the sign in message, lowercase hex
+    pub address: String,
+}
+
+/// A ready-to-sign SIWF message, minted for one custody address.
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+pub struct SiwfChallenge {
+    pub message: String,
+    pub nonce: String,
+}
@@ -0,0 +1,23 @@
+use cfg_if::cfg_if;
+
+cfg_if! { if #[cfg(feature = "ssr")] {
+    use crate::schema::*;
+    use chrono::NaiveDateTime;
+    use diesel::prelude::*;
+    use serde::{Deserialize, Serialize};
+
+    #[derive(Debug, Serialize, Deserialize, Queryable, Identifiable)]
+    #[diesel(table_name = followed_channels, primary_key(channel_id))]
+    pub struct FollowedChannel {
+        pub channel_id: String,
+        pub url: String,
+        pub created_at: Option<NaiveDateTime>,
+    }
+
+    #[derive(Debug, Insertable)]
+    #[diesel(table_name = followed_channels)]
+    pub struct NewFollowedChannel {
+        pub channel_id: String,
+        pub url: String,
+    }
+}}
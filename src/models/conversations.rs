@@ -22,6 +22,28 @@ pub struct MessageView {
     pub updated_at: Option<DateTime<Utc>>,
 }
 
+/// A read-only link to a thread, for people who can't sign in as its owner.
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct ThreadShareView {
+    pub token: String,
+    pub thread_id: String,
+    pub expires_at: Option<DateTime<Utc>>,
+}
+
+impl ThreadShareView {
+    pub fn path(&self) -> String {
+        format!("/share/{}", self.token)
+    }
+}
+
+/// Everything `/share/:token` shows.
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct SharedThreadView {
+    pub title: Option<String>,
+    pub created_at: Option<DateTime<Utc>>,
+    pub messages: Vec<MessageView>,
+}
+
 #[derive(Debug, Serialize, Deserialize, Clone)]
 pub struct NewMessageView {
     pub thread_id: String,
@@ -44,6 +66,8 @@ cfg_if! { if #[cfg(feature = "ssr")] {
         pub created_at: Option<NaiveDateTime>,
         pub updated_at: Option<NaiveDateTime>,
         pub title: Option<String>,
+        /// fid of whoever created the thread
+        pub owner: i64,
     }
 
     impl From<Thread> for ThreadView {
@@ -88,8 +112,34 @@ cfg_if! { if #[cfg(feature = "ssr")] {
         }
     }
 
+    #[derive(Debug, Queryable, Insertable)]
+    #[diesel(table_name = thread_shares)]
+    pub struct ThreadShare {
+        pub token: String,
+        pub thread_id: String,
+        pub created_at: NaiveDateTime,
+        pub expires_at: Option<NaiveDateTime>,
+        pub revoked_at: Option<NaiveDateTime>,
+    }
+
+    impl ThreadShare {
+        pub fn is_active(&self, now: NaiveDateTime) -> bool {
+            self.revoked_at.is_none() && self.expires_at.is_none_or(|expires_at| expires_at > now)
+        }
+    }
+
+    impl From<ThreadShare> for ThreadShareView {
+        fn from(share: ThreadShare) -> Self {
+            ThreadShareView {
+                token: share.token,
+                thread_id: share.thread_id,
+                expires_at: share.expires_at.map(|dt| DateTime::<Utc>::from_naive_utc_and_offset(dt, Utc)),
+            }
+        }
+    }
+
     // message data from the client ("new type" or "insert type" pattern)
-    #[derive(Debug, Insertable, Deserialize)]
+    #[derive(Debug, Clone, Insertable, Deserialize)]
     #[diesel(table_name = messages)]
     pub struct NewMessage {
         pub thread_id: String,
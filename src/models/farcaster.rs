@@ -19,7 +19,7 @@ pub struct CastData {
     pub cast_type: String,
 }
 
-#[derive(Debug, Serialize, Deserialize, Clone)]
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
 #[allow(non_snake_case)]
 pub struct CastAddBody {
     pub embeds: Vec<Embed>,
@@ -27,15 +27,26 @@ pub struct CastAddBody {
     pub mentions: Vec<u64>,
     pub mentionsPositions: Vec<u32>,
     pub parentCastId: Option<ParentCastId>,
+    #[serde(default)]
+    pub parentUrl: Option<String>,
     pub text: Option<String>,
 }
 
-#[derive(Debug, Serialize, Deserialize, Clone)]
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
 pub struct Embed {
+    #[serde(default)]
     pub url: Option<String>,
+    #[serde(rename = "castId", default)]
+    pub cast_id: Option<CastId>,
 }
 
-#[derive(Debug, Serialize, Deserialize, Clone)]
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+pub struct CastId {
+    pub fid: u64,
+    pub hash: String,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
 pub struct ParentCastId  {
     pub fid: u64,
     pub hash: String,
@@ -44,6 +55,40 @@ pub struct ParentCastId  {
 #[derive(Debug, Serialize, Deserialize, Clone)]
 pub struct CastResponse {
     pub messages: Vec<Cast>,
+    #[serde(rename = "nextPageToken", default)]
+    pub next_page_token: Option<String>,
+}
+
+// embeds
+
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Eq)]
+pub enum EmbedKind {
+    Image,
+    Video,
+    Link,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+pub struct LinkPreview {
+    pub url: String,
+    pub kind: EmbedKind,
+    pub title: Option<String>,
+    pub description: Option<String>,
+    pub image: Option<String>,
+    pub site_name: Option<String>,
+}
+
+impl LinkPreview {
+    pub fn bare(url: String, kind: EmbedKind) -> Self {
+        LinkPreview {
+            url,
+            kind,
+            title: None,
+            description: None,
+            image: None,
+            site_name: None,
+        }
+    }
 }
 
 // user
@@ -78,6 +123,120 @@ pub struct UserDataBody {
     pub value: String,
 }
 
+// every user data type for a fid, as returned when no user_data_type is given
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct UserDataListResponse {
+    pub messages: Vec<UserDataResponse>,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, Default, PartialEq)]
+pub struct ProfileData {
+    pub fid: u64,
+    pub username: Option<String>,
+    pub display_name: Option<String>,
+    pub pfp: Option<String>,
+    pub bio: Option<String>,
+    pub url: Option<String>,
+    pub proofs: Vec<UserNameProof>,
+}
+
+impl ProfileData {
+    pub fn from_user_data(fid: u64, messages: Vec<UserDataResponse>, proofs: Vec<UserNameProof>) -> Self {
+        let mut profile = ProfileData { fid, proofs, ..Default::default() };
+        for message in messages {
+            let body = message.data.user_data_body;
+            match body.data_type.as_str() {
+                "USER_DATA_TYPE_PFP" => profile.pfp = Some(body.value),
+                "USER_DATA_TYPE_DISPLAY" => profile.display_name = Some(body.value),
+                "USER_DATA_TYPE_BIO" => profile.bio = Some(body.value),
+                "USER_DATA_TYPE_URL" => profile.url = Some(body.value),
+                "USER_DATA_TYPE_USERNAME" => profile.username = Some(body.value),
+                _ => {}
+            }
+        }
+        profile
+    }
+
+    pub fn summary(&self) -> UserSummary {
+        UserSummary {
+            fid: self.fid,
+            username: self.username.clone(),
+            display_name: self.display_name.clone(),
+            pfp: self.pfp.clone(),
+            bio: self.bio.clone(),
+        }
+    }
+}
+
+/// The few user data fields shown next to someone's casts.
+#[derive(Debug, Serialize, Deserialize, Clone, Default, PartialEq)]
+pub struct UserSummary {
+    pub fid: u64,
+    pub username: Option<String>,
+    pub display_name: Option<String>,
+    pub pfp: Option<String>,
+    pub bio: Option<String>,
+}
+
+impl UserSummary {
+    // the hub's numbers for the user data types a summary carries
+    pub const PFP: u8 = 1;
+    pub const DISPLAY: u8 = 2;
+    pub const BIO: u8 = 3;
+    pub const USERNAME: u8 = 6;
+    pub const TYPES: [u8; 4] = [Self::PFP, Self::DISPLAY, Self::BIO, Self::USERNAME];
+
+    pub fn new(fid: u64) -> Self {
+        UserSummary { fid, ..Default::default() }
+    }
+
+    /// the name the hub gives `user_data_type` in user data bodies
+    pub fn type_name(user_data_type: u8) -> Option<&'static str> {
+        match user_data_type {
+            Self::PFP => Some("USER_DATA_TYPE_PFP"),
+            Self::DISPLAY => Some("USER_DATA_TYPE_DISPLAY"),
+            Self::BIO => Some("USER_DATA_TYPE_BIO"),
+            Self::USERNAME => Some("USER_DATA_TYPE_USERNAME"),
+            _ => None,
+        }
+    }
+
+    pub fn apply(&mut self, body: &UserDataBody) {
+        let value = Some(body.value.clone());
+        match body.data_type.as_str() {
+            "USER_DATA_TYPE_PFP" => self.pfp = value,
+            "USER_DATA_TYPE_DISPLAY" => self.display_name = value,
+            "USER_DATA_TYPE_BIO" => self.bio = value,
+            "USER_DATA_TYPE_USERNAME" => self.username = value,
+            _ => {}
+        }
+    }
+}
+
+// username proofs (fnames and ens names)
+
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+pub struct UserNameProof {
+    pub timestamp: u64,
+    pub name: String,
+    pub owner: String,
+    pub fid: u64,
+    #[serde(rename = "type")]
+    pub proof_type: String,
+}
+
+impl UserNameProof {
+    pub fn is_ens(&self) -> bool {
+        self.proof_type == "USERNAME_TYPE_ENS_L1"
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct UserNameProofsResponse {
+    #[serde(default)]
+    pub proofs: Vec<UserNameProof>,
+}
+
 // reactions
 
 #[derive(Debug, Serialize, Deserialize, Clone)]
@@ -90,10 +249,17 @@ pub struct ReactionResponse {
     pub reaction_type: String,
 }
 
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct Reaction {
+    pub data: ReactionData,
+    pub hash: String,
+}
+
 #[derive(Debug, Serialize, Deserialize, Clone)]
 pub struct ReactionData {
     pub fid: u64,
     pub network: String,
+    #[serde(rename = "reactionBody")]
     pub reaction_body: ReactionBody,
     pub timestamp: i64,
     #[serde(rename = "type")]
@@ -102,7 +268,8 @@ pub struct ReactionData {
 
 #[derive(Debug, Serialize, Deserialize, Clone)]
 pub struct ReactionBody {
-    pub target_cast_id: TargetCastId,
+    #[serde(rename = "targetCastId")]
+    pub target_cast_id: Option<TargetCastId>,
     #[serde(rename = "type")]
     pub reaction_type: String,
 }
@@ -113,9 +280,16 @@ pub struct TargetCastId {
     pub hash: String,
 }
 
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct ReactionsResponse {
+    pub messages: Vec<Reaction>,
+    #[serde(rename = "nextPageToken", default)]
+    pub next_page_token: Option<String>,
+}
+
 // channels
 
-#[derive(Clone, Debug, Deserialize, PartialEq)]
+#[derive(Clone, Debug, Serialize, Deserialize, PartialEq)]
 #[allow(non_snake_case)]
 pub struct Channel {
     pub createdAt: u64,
@@ -129,12 +303,66 @@ pub struct Channel {
     pub url: String,
 }
 
-#[derive(Clone, Debug, Deserialize)]
+#[derive(Clone, Debug, Serialize, Deserialize)]
 pub struct ChannelsResult {
     pub channels: Vec<Channel>,
 }
 
-#[derive(Clone, Debug, Deserialize)]
+#[derive(Clone, Debug, Serialize, Deserialize)]
 pub struct ChannelsResponse {
     pub result: ChannelsResult,
 }
+
+// hub events
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct HubEventsResponse {
+    pub events: Vec<HubEvent>,
+    #[serde(rename = "nextPageEventId", default)]
+    pub next_page_event_id: Option<u64>,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct HubEvent {
+    pub id: u64,
+    #[serde(rename = "type")]
+    pub event_type: String,
+    #[serde(rename = "mergeMessageBody", default)]
+    pub merge_message_body: Option<MergeMessageBody>,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct MergeMessageBody {
+    pub message: serde_json::Value,
+}
+
+impl HubEvent {
+    // only newly merged cast adds matter to the live feed; removes, reactions etc. are skipped
+    pub fn merged_cast(&self) -> Option<Cast> {
+        if self.event_type != "HUB_EVENT_TYPE_MERGE_MESSAGE" {
+            return None;
+        }
+        let message = &self.merge_message_body.as_ref()?.message;
+        if message["data"]["type"] != "MESSAGE_TYPE_CAST_ADD" {
+            return None;
+        }
+        serde_json::from_value(message.clone()).ok()
+    }
+}
+
+// publishing
+
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+pub struct PublishedCast {
+    pub hash: String,
+    pub fid: u64,
+    pub text: String,
+    pub mentions: Vec<u64>,
+    pub mentions_positions: Vec<u32>,
+    pub embeds: Vec<String>,
+    pub parent_url: Option<String>,
+    /// `@names` that didn't resolve to an fid and were left in the text as typed
+    pub unresolved_mentions: Vec<String>,
+    /// signed but never sent to the hub
+    pub dry_run: bool,
+}
@@ -0,0 +1,115 @@
+use cfg_if::cfg_if;
+
+cfg_if! { if #[cfg(feature = "ssr")] {
+    use crate::models::farcaster::{Cast, Reaction, UserDataResponse};
+    use crate::schema::*;
+    use chrono::NaiveDateTime;
+    use diesel::prelude::*;
+
+    // casts, reactions and user data pulled from the hub by the indexer
+
+    #[derive(Debug, Queryable, Identifiable)]
+    #[diesel(table_name = casts, primary_key(hash))]
+    pub struct IndexedCast {
+        pub hash: String,
+        pub fid: i64,
+        pub channel_id: Option<String>,
+        pub parent_url: Option<String>,
+        pub parent_cast_hash: Option<String>,
+        pub text: Option<String>,
+        pub timestamp: i64,
+        pub raw: serde_json::Value,
+        pub indexed_at: NaiveDateTime,
+    }
+
+    impl TryFrom<IndexedCast> for Cast {
+        type Error = serde_json::Error;
+
+        fn try_from(cast: IndexedCast) -> Result<Self, Self::Error> {
+            serde_json::from_value(cast.raw)
+        }
+    }
+
+    #[derive(Debug, Insertable)]
+    #[diesel(table_name = casts)]
+    pub struct NewIndexedCast {
+        pub hash: String,
+        pub fid: i64,
+        pub channel_id: Option<String>,
+        pub parent_url: Option<String>,
+        pub parent_cast_hash: Option<String>,
+        pub text: Option<String>,
+        pub timestamp: i64,
+        pub raw: serde_json::Value,
+    }
+
+    impl NewIndexedCast {
+        pub fn from_cast(cast: &Cast, channel_id: Option<String>) -> Result<Self, serde_json::Error> {
+            let body = cast.data.castAddBody.as_ref();
+            Ok(NewIndexedCast {
+                hash: cast.hash.clone(),
+                fid: cast.data.fid as i64,
+                channel_id,
+                parent_url: body.and_then(|body| body.parentUrl.clone()),
+                parent_cast_hash: body.and_then(|body| body.parentCastId.as_ref().map(|parent| parent.hash.clone())),
+                text: body.and_then(|body| body.text.clone()),
+                timestamp: cast.data.timestamp as i64,
+                raw: serde_json::to_value(cast)?,
+            })
+        }
+    }
+
+    #[derive(Debug, Queryable, Insertable)]
+    #[diesel(table_name = reactions)]
+    pub struct IndexedReaction {
+        pub fid: i64,
+        pub reaction_type: String,
+        pub target_fid: i64,
+        pub target_hash: String,
+        pub timestamp: i64,
+    }
+
+    impl IndexedReaction {
+        // reactions on urls rather than casts have no target cast and are skipped
+        pub fn from_reaction(reaction: &Reaction) -> Option<Self> {
+            let target = reaction.data.reaction_body.target_cast_id.as_ref()?;
+            Some(IndexedReaction {
+                fid: reaction.data.fid as i64,
+                reaction_type: reaction.data.reaction_body.reaction_type.clone(),
+                target_fid: target.fid as i64,
+                target_hash: target.hash.clone(),
+                timestamp: reaction.data.timestamp,
+            })
+        }
+    }
+
+    #[derive(Debug, Queryable)]
+    #[diesel(table_name = user_data)]
+    pub struct IndexedUserData {
+        pub fid: i64,
+        pub data_type: String,
+        pub value: String,
+        pub timestamp: i64,
+        pub updated_at: NaiveDateTime,
+    }
+
+    #[derive(Debug, Insertable)]
+    #[diesel(table_name = user_data)]
+    pub struct NewIndexedUserData {
+        pub fid: i64,
+        pub data_type: String,
+        pub value: String,
+        pub timestamp: i64,
+    }
+
+    impl From<&UserDataResponse> for NewIndexedUserData {
+        fn from(message: &UserDataResponse) -> Self {
+            NewIndexedUserData {
+                fid: message.data.fid as i64,
+                data_type: message.data.user_data_body.data_type.clone(),
+                value: message.data.user_data_body.value.clone(),
+                timestamp: message.data.timestamp,
+            }
+        }
+    }
+}}
@@ -1,2 +1,7 @@
+pub mod auth;
+pub mod channels;
 pub mod conversations;
 pub mod farcaster;
+pub mod indexed;
+pub mod search;
+pub mod settings;
@@ -0,0 +1,16 @@
+use chrono::NaiveDate;
+use serde::{Deserialize, Serialize};
+
+/// Filters for searching indexed casts; anything left as `None` isn't filtered on.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct CastSearchFilters {
+    /// websearch-style text query: quoted phrases, `or`, `-excluded`
+    pub query: Option<String>,
+    pub channel_id: Option<String>,
+    pub fid: Option<u64>,
+    /// first day to include
+    pub from_date: Option<NaiveDate>,
+    /// last day to include
+    pub to_date: Option<NaiveDate>,
+    pub has_embed: Option<bool>,
+}
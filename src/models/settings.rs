@@ -0,0 +1,175 @@
+use cfg_if::cfg_if;
+use serde::{Deserialize, Serialize};
+
+/// Models the writers room can talk to, as (model id, label).
+pub const MODELS: &[(&str, &str)] = &[
+    ("claude-3-haiku-20240307", "claude-3-haiku"),
+    ("claude-3-sonnet-20240229", "claude-3-sonnet"),
+    ("claude-3-opus-20240229", "claude-3-opus"),
+    ("claude-3-5-sonnet-20240620", "claude-3-5-sonnet"),
+    ("gpt-4o-mini", "gpt-4o-mini"),
+    ("gpt-4o", "gpt-4o"),
+    ("gpt-4-turbo", "gpt-4-turbo"),
+];
+
+pub const LABS: &[&str] = &["openai", "anthropic"];
+
+/// Which lab serves a model, going by its name.
+pub fn lab_for_model(model: &str) -> &'static str {
+    if model.contains("claude") {
+        "anthropic"
+    } else {
+        "openai"
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+pub enum Theme {
+    /// follow the browser's `prefers-color-scheme`
+    #[default]
+    System,
+    Light,
+    Dark,
+}
+
+impl Theme {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Theme::System => "system",
+            Theme::Light => "light",
+            Theme::Dark => "dark",
+        }
+    }
+
+    pub fn parse(value: &str) -> Theme {
+        match value {
+            "light" => Theme::Light,
+            "dark" => Theme::Dark,
+            _ => Theme::System,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct UserSettingsView {
+    pub theme: Theme,
+    pub default_model: String,
+    pub default_lab: String,
+    pub default_channel: Option<String>,
+    pub notify_mentions: bool,
+    pub notify_replies: bool,
+    pub title_provider: String,
+}
+
+impl Default for UserSettingsView {
+    fn default() -> Self {
+        UserSettingsView {
+            theme: Theme::System,
+            default_model: "gpt-4o-mini".to_string(),
+            default_lab: "openai".to_string(),
+            default_channel: None,
+            notify_mentions: false,
+            notify_replies: false,
+            title_provider: "openai".to_string(),
+        }
+    }
+}
+
+impl UserSettingsView {
+    pub fn validate(&self) -> Result<(), String> {
+        if !MODELS.iter().any(|(model, _)| *model == self.default_model) {
+            return Err(format!("unknown model {}", self.default_model));
+        }
+        if lab_for_model(&self.default_model) != self.default_lab {
+            return Err(format!("{} isn't served by {}", self.default_model, self.default_lab));
+        }
+        if !LABS.contains(&self.title_provider.as_str()) {
+            return Err(format!("unknown title provider {}", self.title_provider));
+        }
+        Ok(())
+    }
+}
+
+cfg_if! { if #[cfg(feature = "ssr")] {
+    use chrono::NaiveDateTime;
+    use diesel::prelude::*;
+    use crate::schema::user_settings;
+
+    // `treat_none_as_null` so saving without a default channel clears it
+    #[derive(Debug, Queryable, Insertable, AsChangeset)]
+    #[diesel(table_name = user_settings, treat_none_as_null = true)]
+    pub struct UserSettings {
+        pub fid: i64,
+        pub theme: String,
+        pub default_model: String,
+        pub default_lab: String,
+        pub default_channel: Option<String>,
+        pub notify_mentions: bool,
+        pub notify_replies: bool,
+        pub title_provider: String,
+        pub updated_at: NaiveDateTime,
+    }
+
+    impl UserSettings {
+        pub fn from_view(fid: i64, view: UserSettingsView, updated_at: NaiveDateTime) -> Self {
+            UserSettings {
+                fid,
+                theme: view.theme.as_str().to_string(),
+                default_model: view.default_model,
+                default_lab: view.default_lab,
+                default_channel: view.default_channel,
+                notify_mentions: view.notify_mentions,
+                notify_replies: view.notify_replies,
+                title_provider: view.title_provider,
+                updated_at,
+            }
+        }
+    }
+
+    impl From<UserSettings> for UserSettingsView {
+        fn from(settings: UserSettings) -> Self {
+            UserSettingsView {
+                theme: Theme::parse(&settings.theme),
+                default_model: settings.default_model,
+                default_lab: settings.default_lab,
+                default_channel: settings.default_channel,
+                notify_mentions: settings.notify_mentions,
+                notify_replies: settings.notify_replies,
+                title_provider: settings.title_provider,
+            }
+        }
+    }
+}}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn labs_follow_model_names() {
+        assert_eq!(lab_for_model("claude-3-opus-20240229"), "anthropic");
+        assert_eq!(lab_for_model("gpt-4o"), "openai");
+    }
+
+    #[test]
+    fn defaults_are_valid() {
+        assert_eq!(UserSettingsView::default().validate(), Ok(()));
+    }
+
+    #[test]
+    fn rejects_mismatched_or_unknown_choices() {
+        let settings = UserSettingsView { default_lab: "anthropic".to_string(), ..Default::default() };
+        assert!(settings.validate().is_err());
+        let settings = UserSettingsView { default_model: "gpt-2".to_string(), ..Default::default() };
+        assert!(settings.validate().is_err());
+        let settings = UserSettingsView { title_provider: "mistral".to_string(), ..Default::default() };
+        assert!(settings.validate().is_err());
+    }
+
+    #[test]
+    fn unknown_themes_fall_back_to_system() {
+        assert_eq!(Theme::parse("dark"), Theme::Dark);
+        assert_eq!(Theme::parse(Theme::Light.as_str()), Theme::Light);
+        assert_eq!(Theme::parse("sepia"), Theme::System);
+    }
+}
@@ -2,15 +2,26 @@ use leptos::*;
 
 use crate::components::cast_list::CastList;
 use crate::components::channels::Channels;
+use crate::components::preferences::use_preferences;
+use crate::components::timeline::Timeline;
 
 #[component]
 pub fn Home() -> impl IntoView {
-    let (channel, set_channel) = create_signal("networktimes".to_string());
+    // no channel selected means the merged timeline across every followed channel
+    let (channel, set_channel) = create_signal(None::<String>);
+    let show_timeline = create_memo(move |_| channel.with(Option::is_none));
+    let active_channel = Signal::derive(move || channel.get().unwrap_or_default());
+
+    use_preferences().on_first_load(move |settings| set_channel(settings.default_channel.clone()));
 
     view! {
         <div class="home-feed-container flex flex-col md:flex-row justify-center pt-2 bg-gray-300 dark:bg-teal-900">
             <Channels set_active_channel=set_channel/>
-            <CastList active_channel=channel/>
+            {move || if show_timeline.get() {
+                view! { <Timeline set_active_channel=set_channel/> }.into_view()
+            } else {
+                view! { <CastList active_channel=active_channel/> }.into_view()
+            }}
         </div>
     }
 }
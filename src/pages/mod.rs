@@ -4,3 +4,4 @@ pub mod settings;
 pub mod writersroom;
 pub mod codedemo;
 pub mod mermaiddemo;
+pub mod share;
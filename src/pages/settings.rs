@@ -1,30 +1,204 @@
 use leptos::*;
 
+use crate::components::auth::RequireSignIn;
+use crate::components::channels::get_followed_channels;
+use crate::components::preferences::{update_user_settings, use_preferences};
+use crate::error_template::AppError;
+use crate::models::settings::{lab_for_model, Theme, UserSettingsView, LABS, MODELS};
+
+const SELECT_CLASS: &str = "w-full px-3 py-2 bg-white dark:bg-teal-700 text-gray-900 dark:text-gray-100 border border-gray-300 dark:border-teal-600 rounded-md focus:outline-none focus:ring-2 focus:ring-seafoam-500";
+
 #[component]
 pub fn Settings() -> impl IntoView {
     view! {
         <div class="flex flex-col justify-center pt-8 bg-gray-200 dark:bg-teal-900 min-h-screen">
             <h1 class="text-3xl font-bold text-seafoam-700 dark:text-mint-400 mb-6 text-center">"Settings"</h1>
-            <div class="max-w-2xl mx-auto bg-gray-100 dark:bg-teal-800 p-6 rounded-lg shadow-lg">
-                <h2 class="text-2xl font-semibold text-teal-700 dark:text-aqua-300 mb-4">"General Settings"</h2>
-                <div class="mb-4">
-                    <label for="username" class="block text-gray-700 dark:text-gray-300 mb-2">"Username"</label>
-                    <input type="text" id="username" class="w-full px-3 py-2 bg-white dark:bg-teal-700 text-gray-900 dark:text-gray-100 border border-gray-300 dark:border-teal-600 rounded-md focus:outline-none focus:ring-2 focus:ring-seafoam-500"/>
-                </div>
-                <div class="mb-4">
-                    <label for="email" class="block text-gray-700 dark:text-gray-300 mb-2">"Email"</label>
-                    <input type="email" id="email" class="w-full px-3 py-2 bg-white dark:bg-teal-700 text-gray-900 dark:text-gray-100 border border-gray-300 dark:border-teal-600 rounded-md focus:outline-none focus:ring-2 focus:ring-seafoam-500"/>
-                </div>
-                <div class="mb-6">
-                    <label class="flex items-center">
-                        <input type="checkbox" class="form-checkbox h-5 w-5 text-seafoam-600 dark:text-aqua-400"/>
-                        <span class="ml-2 text-gray-700 dark:text-gray-300">"Receive notifications"</span>
-                    </label>
-                </div>
-                <button class="w-full bg-seafoam-600 hover:bg-seafoam-700 dark:bg-aqua-600 dark:hover:bg-aqua-700 text-white font-bold py-2 px-4 rounded focus:outline-none focus:shadow-outline transition duration-300">
-                    "Save Changes"
-                </button>
+            <RequireSignIn>
+                <SettingsForm/>
+            </RequireSignIn>
+        </div>
+    }
+}
+
+#[component]
+fn SettingsForm() -> impl IntoView {
+    let preferences = use_preferences();
+    let channels = create_resource(|| (), |_| async move { get_followed_channels().await.unwrap_or_default() });
+    let (draft, set_draft) = create_signal(UserSettingsView::default());
+    let (status, set_status) = create_signal(None::<Result<(), String>>);
+
+    create_effect(move |_| {
+        if let Some(saved) = preferences.settings.get() {
+            set_draft(saved);
+        }
+    });
+
+    let save = create_action(move |settings: &UserSettingsView| {
+        let settings = settings.clone();
+        async move {
+            match update_user_settings(settings).await {
+                Ok(saved) => {
+                    preferences.set_theme(saved.theme);
+                    preferences.settings.set(saved);
+                    set_status(Some(Ok(())));
+                }
+                Err(e) => set_status(Some(Err(AppError::from(e).public_message()))),
+            }
+        }
+    });
+
+    let edit = move |change: &dyn Fn(&mut UserSettingsView)| {
+        set_status(None);
+        set_draft.update(|draft| change(draft));
+    };
+
+    view! {
+        <div class="max-w-2xl w-full mx-auto bg-gray-100 dark:bg-teal-800 p-6 rounded-lg shadow-lg">
+            <h2 class="text-2xl font-semibold text-teal-700 dark:text-aqua-300 mb-4">"General Settings"</h2>
+            <div class="mb-4">
+                <label for="theme" class="block text-gray-700 dark:text-gray-300 mb-2">"Theme"</label>
+                <select
+                    id="theme"
+                    class=SELECT_CLASS
+                    prop:value=move || draft.with(|draft| draft.theme.as_str())
+                    on:change=move |ev| {
+                        let theme = Theme::parse(&event_target_value(&ev));
+                        edit(&|draft| draft.theme = theme);
+                    }
+                >
+                    {[Theme::System, Theme::Light, Theme::Dark]
+                        .into_iter()
+                        .map(|theme| view! {
+                            <option
+                                value=theme.as_str()
+                                selected=move || draft.with(|draft| draft.theme == theme)
+                            >
+                                {theme.as_str()}
+                            </option>
+                        })
+                        .collect_view()}
+                </select>
+            </div>
+            <div class="mb-4">
+                <label for="default-model" class="block text-gray-700 dark:text-gray-300 mb-2">"Default model"</label>
+                <select
+                    id="default-model"
+                    class=SELECT_CLASS
+                    prop:value=move || draft.with(|draft| draft.default_model.clone())
+                    on:change=move |ev| {
+                        let model = event_target_value(&ev);
+                        edit(&|draft| {
+                            draft.default_lab = lab_for_model(&model).to_string();
+                            draft.default_model = model.clone();
+                        });
+                    }
+                >
+                    {MODELS
+                        .iter()
+                        .map(|(id, label)| view! {
+                            <option
+                                value=*id
+                                selected=move || draft.with(|draft| draft.default_model == *id)
+                            >
+                                {*label}
+                            </option>
+                        })
+                        .collect_view()}
+                </select>
+            </div>
+            <div class="mb-4">
+                <label for="default-channel" class="block text-gray-700 dark:text-gray-300 mb-2">"Default channel"</label>
+                <select
+                    id="default-channel"
+                    class=SELECT_CLASS
+                    prop:value=move || draft.with(|draft| draft.default_channel.clone().unwrap_or_default())
+                    on:change=move |ev| {
+                        let channel = Some(event_target_value(&ev)).filter(|channel| !channel.is_empty());
+                        edit(&|draft| draft.default_channel = channel.clone());
+                    }
+                >
+                    <option value="">"all channels"</option>
+                    <Transition fallback=|| ()>
+                        {move || channels.get().map(|channels| {
+                            channels
+                                .into_iter()
+                                .map(|channel| {
+                                    let id = channel.id.clone();
+                                    view! {
+                                        <option
+                                            value=channel.id
+                                            selected=move || draft.with(|draft| draft.default_channel.as_deref() == Some(id.as_str()))
+                                        >
+                                            {channel.name}
+                                        </option>
+                                    }
+                                })
+                                .collect_view()
+                        })}
+                    </Transition>
+                </select>
+            </div>
+            <div class="mb-4">
+                <label for="title-provider" class="block text-gray-700 dark:text-gray-300 mb-2">"Thread titles by"</label>
+                <select
+                    id="title-provider"
+                    class=SELECT_CLASS
+                    prop:value=move || draft.with(|draft| draft.title_provider.clone())
+                    on:change=move |ev| {
+                        let provider = event_target_value(&ev);
+                        edit(&|draft| draft.title_provider = provider.clone());
+                    }
+                >
+                    {LABS
+                        .iter()
+                        .map(|lab| view! {
+                            <option
+                                value=*lab
+                                selected=move || draft.with(|draft| draft.title_provider == *lab)
+                            >
+                                {*lab}
+                            </option>
+                        })
+                        .collect_view()}
+                </select>
+            </div>
+            <div class="mb-6 flex flex-col space-y-2">
+                <label class="flex items-center">
+                    <input
+                        type="checkbox"
+                        class="form-checkbox h-5 w-5 text-seafoam-600 dark:text-aqua-400"
+                        prop:checked=move || draft.with(|draft| draft.notify_mentions)
+                        on:change=move |ev| {
+                            let checked = event_target_checked(&ev);
+                            edit(&|draft| draft.notify_mentions = checked);
+                        }
+                    />
+                    <span class="ml-2 text-gray-700 dark:text-gray-300">"Notify me about mentions"</span>
+                </label>
+                <label class="flex items-center">
+                    <input
+                        type="checkbox"
+                        class="form-checkbox h-5 w-5 text-seafoam-600 dark:text-aqua-400"
+                        prop:checked=move || draft.with(|draft| draft.notify_replies)
+                        on:change=move |ev| {
+                            let checked = event_target_checked(&ev);
+                            edit(&|draft| draft.notify_replies = checked);
+                        }
+                    />
+                    <span class="ml-2 text-gray-700 dark:text-gray-300">"Notify me about replies"</span>
+                </label>
             </div>
+            <button
+                class="w-full bg-seafoam-600 hover:bg-seafoam-700 dark:bg-aqua-600 dark:hover:bg-aqua-700 text-white font-bold py-2 px-4 rounded focus:outline-none focus:shadow-outline transition duration-300 disabled:opacity-50"
+                disabled=move || save.pending().get()
+                on:click=move |_| save.dispatch(draft.get_untracked())
+            >
+                {move || if save.pending().get() { "Saving..." } else { "Save Changes" }}
+            </button>
+            {move || status.get().map(|status| match status {
+                Ok(()) => view! { <p class="pt-3 text-sm text-seafoam-600 dark:text-aqua-400">"saved"</p> },
+                Err(e) => view! { <p class="pt-3 text-sm text-salmon-600 dark:text-salmon-400">{e}</p> },
+            })}
         </div>
     }
 }
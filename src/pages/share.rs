@@ -0,0 +1,156 @@
+use leptos::*;
+use leptos_meta::*;
+use leptos_router::*;
+
+use crate::error_template::AppError;
+use crate::models::conversations::{MessageView, SharedThreadView};
+
+const DESCRIPTION_CHARS: usize = 200;
+
+/// Loads a thread through a share token. No session needed; the token is the permission.
+#[server(GetSharedThread, "/api")]
+pub async fn get_shared_thread(token: String) -> Result<SharedThreadView, ServerFnError> {
+    use chrono::Utc;
+    use crate::models::conversations::ThreadView;
+    use crate::state::AppState;
+
+    let app_state = use_context::<AppState>()
+        .expect("failed to get AppState from context");
+
+    // expired, revoked and made up tokens all come back as the same not found
+    let (thread, thread_messages) = app_state.threads().shared(&token, Utc::now().naive_utc()).await?;
+
+    let thread = ThreadView::from(thread);
+    Ok(SharedThreadView {
+        title: thread.title,
+        created_at: thread.created_at,
+        messages: thread_messages.into_iter().map(MessageView::from).collect(),
+    })
+}
+
+/// The opening message, cut down to something a link preview can show.
+fn share_description(thread: &SharedThreadView) -> String {
+    let opening = thread
+        .messages
+        .iter()
+        .find_map(|message| message.content.as_deref())
+        .unwrap_or_default()
+        .split_whitespace()
+        .collect::<Vec<_>>()
+        .join(" ");
+    if opening.chars().count() > DESCRIPTION_CHARS {
+        let cut: String = opening.chars().take(DESCRIPTION_CHARS - 1).collect();
+        format!("{}…", cut.trim_end())
+    } else {
+        opening
+    }
+}
+
+#[component]
+pub fn SharedThread() -> impl IntoView {
+    let params = use_params_map();
+    let token = create_memo(move |_| params.with(|params| params.get("token").cloned().unwrap_or_default()));
+    let thread = create_resource(token, get_shared_thread);
+
+    view! {
+        // share links are unlisted, keep them out of search results
+        <Meta name="robots" content="noindex"/>
+        <div class="shared-thread w-11/12 lg:w-8/12 xl:w-6/12 mx-auto pt-6 pb-12">
+            <Suspense fallback=|| view! { <p class="text-teal-500 dark:text-teal-400">"loading..."</p> }>
+                {move || thread.get().map(|result| match result {
+                    Ok(thread) => {
+                        let title = thread.title.clone().unwrap_or_else(|| "a conversation".to_string());
+                        let description = share_description(&thread);
+                        view! {
+                            <Title text=format!("{} · thenetworktimes", title)/>
+                            <Meta property="og:type" content="article"/>
+                            <Meta property="og:site_name" content="thenetworktimes"/>
+                            <Meta property="og:title" content=title.clone()/>
+                            <Meta property="og:description" content=description.clone()/>
+                            <Meta name="description" content=description/>
+                            <h1 class="text-2xl text-teal-700 dark:text-mint-400 pb-1">{title}</h1>
+                            <p class="text-xs text-gray-500 dark:text-gray-400 pb-6">
+                                {thread
+                                    .created_at
+                                    .map(|dt| dt.format("%b %d, %Y").to_string().to_lowercase())
+                                    .unwrap_or_default()}
+                                " · read only"
+                            </p>
+                            <div class="flex flex-col space-y-6">
+                                {thread.messages.into_iter().map(|message| view! { <SharedMessage message/> }).collect_view()}
+                            </div>
+                        }.into_view()
+                    }
+                    Err(e) => view! {
+                        <p class="text-salmon-600 dark:text-salmon-400">
+                            {match AppError::from(e) {
+                                AppError::NotFound(_) => "this link doesn't exist or has expired".to_string(),
+                                e => e.public_message(),
+                            }}
+                        </p>
+                    }.into_view(),
+                })}
+            </Suspense>
+        </div>
+    }
+}
+
+#[component]
+fn SharedMessage(message: MessageView) -> impl IntoView {
+    let is_assistant = message.role == "assistant";
+
+    view! {
+        <div class=format!("flex w-full {}", if is_assistant { "justify-start" } else { "justify-end" })>
+            <div class=format!(
+                "max-w-prose p-3 {}",
+                if is_assistant {
+                    "bg-gray-200 dark:bg-teal-800"
+                } else {
+                    "border-2 border-gray-700 dark:border-teal-700"
+                },
+            )>
+                <p class="ir text-base text-teal-600 dark:text-mint-400 whitespace-pre-wrap">
+                    {message.content.unwrap_or_default()}
+                </p>
+                {is_assistant.then(|| view! {
+                    <p class="ir text-xs text-gray-500 dark:text-gray-400 pt-2">{message.active_model}</p>
+                })}
+            </div>
+        </div>
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn message(content: &str) -> MessageView {
+        MessageView {
+            id: 1,
+            thread_id: "t".to_string(),
+            content: Some(content.to_string()),
+            role: "user".to_string(),
+            active_model: "gpt-4o-mini".to_string(),
+            active_lab: "openai".to_string(),
+            created_at: None,
+            updated_at: None,
+        }
+    }
+
+    fn thread(contents: &[&str]) -> SharedThreadView {
+        SharedThreadView { title: None, created_at: None, messages: contents.iter().map(|c| message(c)).collect() }
+    }
+
+    #[test]
+    fn describes_a_thread_by_its_opening_message() {
+        assert_eq!(share_description(&thread(&["what  is\na\tcast?", "a post"])), "what is a cast?");
+        assert_eq!(share_description(&thread(&[])), "");
+    }
+
+    #[test]
+    fn long_openings_are_cut_on_a_char_boundary() {
+        let description = share_description(&thread(&[&"é".repeat(500)]));
+        assert_eq!(description.chars().count(), DESCRIPTION_CHARS);
+        assert!(description.ends_with('…'));
+    }
+}
@@ -1,13 +1,18 @@
 use leptos::*;
 
+use crate::components::auth::RequireSignIn;
 use crate::components::chat::Chat;
 use crate::components::threadlist::{ThreadList, get_threads};
 use crate::components::messagelist::MessageList;
+use crate::components::preferences::use_preferences;
+use crate::components::publish::PublishCast;
 use crate::components::toast::Toast;
+use crate::models::settings::{lab_for_model, MODELS};
 
 #[component]
 pub fn WritersRoom() -> impl IntoView {
     let (show_threads, set_show_threads) = create_signal(false);
+    let (show_publish, set_show_publish) = create_signal(false);
     let (model, set_model) = create_signal("gpt-4o-mini".to_string());
     let (lab, set_lab) = create_signal("openai".to_string());
     let (thread_id, set_thread_id) = create_signal("0001".to_string());
@@ -19,16 +24,15 @@ pub fn WritersRoom() -> impl IntoView {
         |_| async move { get_threads().await }
     );
 
+    use_preferences().on_first_load(move |settings| {
+        set_model(settings.default_model.clone());
+        set_lab(settings.default_lab.clone());
+    });
+
     let handle_model_change = move |ev| {
         let value = event_target_value(&ev);
-        set_model(value.clone());
-    
-        let new_lab = if value.contains("claude") {
-            "anthropic"
-        } else {
-            "openai"
-        };
-        set_lab(new_lab.to_string());
+        set_lab(lab_for_model(&value).to_string());
+        set_model(value);
     };
 
     let create_new_thread = create_action(move |_: &()| {
@@ -69,158 +73,129 @@ pub fn WritersRoom() -> impl IntoView {
     });
 
     view! {
-        <div class="w-full flex flex-col bg-gray-300 dark:bg-teal-900 justify-start pt-2 pl-2 pr-2 h-full">
-            <div class="flex flex-row items-center justify-between">
-                <div class="flex flex-row items-center justify-center space-x-4">
-                    <button
-                        class="self-start ib text-xs md:text-sm text-gray-900 dark:text-gray-100 hover:text-gray-800 dark:hover:text-gray-200 p-2 border-2 bg-gray-300 dark:bg-teal-700 hover:bg-gray-400 dark:hover:bg-teal-600 border-gray-700 dark:border-gray-600 hover:border-gray-900 dark:hover:border-gray-400"
-                        on:click=move |_| set_show_threads.update(|v| *v = !*v)
-                    >
-                        {move || if show_threads.get() { "hide threads" } else { "show threads" }}
-                    </button>
-                    <button
-                        class="ib text-xs md:text-sm text-teal-700 dark:text-teal-100 hover:text-teal-600 dark:hover:text-teal-200 bg-gray-300 dark:bg-teal-700 hover:bg-gray-400 dark:hover:bg-teal-600 border-gray-700 dark:border-gray-600 hover:border-gray-900 dark:hover:border-gray-400"
-                        on:click=move |_| create_new_thread.dispatch(())
+        <RequireSignIn>
+            <div class="w-full flex flex-col bg-gray-300 dark:bg-teal-900 justify-start pt-2 pl-2 pr-2 h-full">
+                <div class="flex flex-row items-center justify-between">
+                    <div class="flex flex-row items-center justify-center space-x-4">
+                        <button
+                            class="self-start ib text-xs md:text-sm text-gray-900 dark:text-gray-100 hover:text-gray-800 dark:hover:text-gray-200 p-2 border-2 bg-gray-300 dark:bg-teal-700 hover:bg-gray-400 dark:hover:bg-teal-600 border-gray-700 dark:border-gray-600 hover:border-gray-900 dark:hover:border-gray-400"
+                            on:click=move |_| set_show_threads.update(|v| *v = !*v)
+                        >
+                            {move || if show_threads.get() { "hide threads" } else { "show threads" }}
+                        </button>
+                        <button
+                            class="ib text-xs md:text-sm text-teal-700 dark:text-teal-100 hover:text-teal-600 dark:hover:text-teal-200 bg-gray-300 dark:bg-teal-700 hover:bg-gray-400 dark:hover:bg-teal-600 border-gray-700 dark:border-gray-600 hover:border-gray-900 dark:hover:border-gray-400"
+                            on:click=move |_| create_new_thread.dispatch(())
+                        >
+                            "mew"
+                        </button>
+                        <button
+                            class="ib text-xs md:text-sm text-teal-700 dark:text-teal-100 hover:text-teal-600 dark:hover:text-teal-200 bg-gray-300 dark:bg-teal-700 hover:bg-gray-400 dark:hover:bg-teal-600 border-gray-700 dark:border-gray-600 hover:border-gray-900 dark:hover:border-gray-400"
+                            on:click=move |_| set_show_publish.update(|v| *v = !*v)
+                        >
+                            {move || if show_publish.get() { "hide cast" } else { "cast" }}
+                        </button>
+                    </div>
+                    <select
+                        class="self-start ib text-xs md:text-sm 
+                        text-gray-900 dark:text-gray-100 hover:text-gray-800 dark:hover:text-gray-200 p-2 border-2 
+                        bg-gray-300 dark:bg-teal-700 hover:bg-gray-400 dark:hover:bg-teal-600 
+                        border-gray-700 dark:border-gray-600 hover:border-gray-900 dark:hover:border-gray-400"
+                        on:change=handle_model_change
+                        prop:value=model
                     >
-                        "mew"
-                    </button>
+                        {MODELS
+                            .iter()
+                            .map(|(id, label)| view! {
+                                <option value=*id selected=move || model.get() == *id>{*label}</option>
+                            })
+                            .collect_view()}
+                    </select>
                 </div>
-                <select
-                    class="self-start ib text-xs md:text-sm 
-                    text-gray-900 dark:text-gray-100 hover:text-gray-800 dark:hover:text-gray-200 p-2 border-2 
-                    bg-gray-300 dark:bg-teal-700 hover:bg-gray-400 dark:hover:bg-teal-600 
-                    border-gray-700 dark:border-gray-600 hover:border-gray-900 dark:hover:border-gray-400"
-                    on:change=handle_model_change
-                >
-                    <option value="claude-3-haiku-20240307">"claude-3-haiku"</option>
-                    <option value="claude-3-sonnet-20240229">"claude-3-sonnet"</option>
-                    <option value="claude-3-opus-20240229">"claude-3-opus"</option>
-                    <option value="claude-3-5-sonnet-20240620">"claude-3-5-sonnet"</option>
-                    <option value="gpt-4o-mini" selected="selected">
-                        "gpt-4o-mini"
-                    </option>
-                    <option value="gpt-4o">"gpt-4o"</option>
-                    <option value="gpt-4-turbo">"gpt-4-turbo"</option>
-                </select>
-            </div>
-            <div class="flex flex-row items-start justify-between">
-                <div class=move || {
-                    let base_class = "transition-all duration-300 ease-in-out overflow-hidden";
-                    if show_threads.get() {
-                        format!("{} max-w-xs w-full opacity-100", base_class)
-                    } else {
-                        format!("{} max-w-0 w-0 opacity-0", base_class)
-                    }
-                }>
-                    <Suspense fallback=move || {
-                        view! { <p>"loading threads..."</p> }
+                <Show when=move || show_publish.get()>
+                    <div class="pt-2">
+                        <PublishCast/>
+                    </div>
+                </Show>
+                <div class="flex flex-row items-start justify-between">
+                    <div class=move || {
+                        let base_class = "transition-all duration-300 ease-in-out overflow-hidden";
+                        if show_threads.get() {
+                            format!("{} max-w-xs w-full opacity-100", base_class)
+                        } else {
+                            format!("{} max-w-0 w-0 opacity-0", base_class)
+                        }
                     }>
-                        {move || {
-                            threads
-                                .get()
-                                .map(|thread_list| {
-                                    match thread_list {
-                                        Ok(_threads) => {
-                                            view! {
-                                                <div>
-                                                    <ThreadList
-                                                        current_thread_id=thread_id
-                                                        set_current_thread_id=set_thread_id
-                                                        // will use for filtering later
-                                                        _lab=lab
-                                                    />
-                                                </div>
+                        <Suspense fallback=move || {
+                            view! { <p>"loading threads..."</p> }
+                        }>
+                            {move || {
+                                threads
+                                    .get()
+                                    .map(|thread_list| {
+                                        match thread_list {
+                                            Ok(_threads) => {
+                                                view! {
+                                                    <div>
+                                                        <ThreadList
+                                                            current_thread_id=thread_id
+                                                            set_current_thread_id=set_thread_id
+                                                            // will use for filtering later
+                                                            _lab=lab
+                                                        />
+                                                    </div>
+                                                }
                                             }
-                                        }
-                                        Err(_) => {
-                                            view! {
-                                                // will use for filtering later
+                                            Err(_) => {
+                                                view! {
+                                                    // will use for filtering later
 
-                                                // will use for filtering later
-                                                <div>"error loading threads: {e}"</div>
+                                                    // will use for filtering later
+                                                    <div>"error loading threads: {e}"</div>
+                                                }
                                             }
                                         }
-                                    }
-                                })
-                        }}
+                                    })
+                            }}
 
-                    </Suspense>
-                </div>
-                <div class="w-full flex flex-col content-end justify-between h-[calc(80vh-10px)]">
-                    <MessageList current_thread_id=thread_id/>
-                    <div class="relative text-gray-900 dark:text-gray-100">
-                        <Toast
-                            message=toast_message
-                            visible=toast_visible
-                            on_close=move |_| set_toast_visible(false)
-                        />
-                        <Chat thread_id=thread_id model=model lab=lab/>
+                        </Suspense>
+                    </div>
+                    <div class="w-full flex flex-col content-end justify-between h-[calc(80vh-10px)]">
+                        <MessageList current_thread_id=thread_id/>
+                        <div class="relative text-gray-900 dark:text-gray-100">
+                            <Toast
+                                message=toast_message
+                                visible=toast_visible
+                                on_close=move |_| set_toast_visible(false)
+                            />
+                            <Chat thread_id=thread_id model=model lab=lab/>
+                        </div>
                     </div>
                 </div>
             </div>
-        </div>
+        </RequireSignIn>
     }
 }
 
 #[server(CreateThread, "/api")]
 pub async fn create_thread() -> Result<String, ServerFnError> {
-    use diesel::prelude::*;
-    use crate::schema::threads;
     use chrono::Utc;
-    use std::fmt;
     use crate::state::AppState;
     use crate::models::conversations::Thread;
 
-    #[derive(Debug)]
-    enum ThreadError {
-        Pool(String),
-        Database(diesel::result::Error),
-        Interaction(String),
-    }
-
-    impl fmt::Display for ThreadError {
-        fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
-            match self {
-                ThreadError::Pool(e) => write!(f, "pool error: {}", e),
-                ThreadError::Database(e) => write!(f, "database error: {}", e),
-                ThreadError::Interaction(e) => write!(f, "interaction error: {}", e),
-            }
-        }
-    }
-
-    fn to_server_error(e: ThreadError) -> ServerFnError {
-        ServerFnError::ServerError(e.to_string())
-    }
+    let user = crate::services::auth::require_user().await?;
 
     let app_state = use_context::<AppState>()
         .expect("failed to get AppState from context");
-    let pool = app_state.pool;
-
-    let conn = pool
-        .get()
-        .await
-        .map_err(|e| ThreadError::Pool(e.to_string()))
-        .map_err(to_server_error)?;
-
-    let new_thread_id = conn
-        .interact(|conn| {
-            let new_thread = Thread {
-                id: uuid::Uuid::new_v4().to_string(),
-                created_at: Some(Utc::now().naive_utc()),
-                updated_at: Some(Utc::now().naive_utc()),
-                title: None, 
-            };
-
-            diesel::insert_into(threads::table)
-                .values(&new_thread)
-                .execute(conn)
-                .map(|_| new_thread.id)
-        })
-        .await
-        .map_err(|e| ThreadError::Interaction(e.to_string()))
-        .map_err(to_server_error)?
-        .map_err(ThreadError::Database)
-        .map_err(to_server_error)?;
-
-    Ok(new_thread_id)
+
+    let new_thread = Thread {
+        id: uuid::Uuid::new_v4().to_string(),
+        created_at: Some(Utc::now().naive_utc()),
+        updated_at: Some(Utc::now().naive_utc()),
+        title: None,
+        owner: user.fid as i64,
+    };
+    let thread = app_state.threads().create(new_thread).await?;
+
+    Ok(thread.id)
 }
@@ -3,6 +3,33 @@ use cfg_if::cfg_if;
 cfg_if! {
 if #[cfg(feature = "ssr")] {
 
+diesel::table! {
+    casts (hash) {
+        #[max_length = 255]
+        hash -> Varchar,
+        fid -> Int8,
+        #[max_length = 255]
+        channel_id -> Nullable<Varchar>,
+        parent_url -> Nullable<Text>,
+        #[max_length = 255]
+        parent_cast_hash -> Nullable<Varchar>,
+        text -> Nullable<Text>,
+        timestamp -> Int8,
+        raw -> Jsonb,
+        indexed_at -> Timestamp,
+    }
+}
+
+diesel::table! {
+    followed_channels (channel_id) {
+        #[max_length = 255]
+        channel_id -> Varchar,
+        #[max_length = 255]
+        url -> Varchar,
+        created_at -> Nullable<Timestamp>,
+    }
+}
+
 diesel::table! {
     messages (id) {
         id -> Int4,
@@ -17,6 +44,30 @@ diesel::table! {
     }
 }
 
+diesel::table! {
+    reactions (fid, target_hash, reaction_type) {
+        fid -> Int8,
+        #[max_length = 64]
+        reaction_type -> Varchar,
+        target_fid -> Int8,
+        #[max_length = 255]
+        target_hash -> Varchar,
+        timestamp -> Int8,
+    }
+}
+
+diesel::table! {
+    thread_shares (token) {
+        #[max_length = 64]
+        token -> Varchar,
+        #[max_length = 255]
+        thread_id -> Varchar,
+        created_at -> Timestamp,
+        expires_at -> Nullable<Timestamp>,
+        revoked_at -> Nullable<Timestamp>,
+    }
+}
+
 diesel::table! {
     threads (id) {
         #[max_length = 255]
@@ -25,13 +76,51 @@ diesel::table! {
         updated_at -> Nullable<Timestamp>,
         #[max_length = 255]
         title -> Nullable<Varchar>,
+        owner -> Int8,
+    }
+}
+
+diesel::table! {
+    user_settings (fid) {
+        fid -> Int8,
+        #[max_length = 16]
+        theme -> Varchar,
+        #[max_length = 64]
+        default_model -> Varchar,
+        #[max_length = 32]
+        default_lab -> Varchar,
+        #[max_length = 255]
+        default_channel -> Nullable<Varchar>,
+        notify_mentions -> Bool,
+        notify_replies -> Bool,
+        #[max_length = 32]
+        title_provider -> Varchar,
+        updated_at -> Timestamp,
+    }
+}
+
+diesel::table! {
+    user_data (fid, data_type) {
+        fid -> Int8,
+        #[max_length = 64]
+        data_type -> Varchar,
+        value -> Text,
+        timestamp -> Int8,
+        updated_at -> Timestamp,
     }
 }
 
 diesel::joinable!(messages -> threads (thread_id));
+diesel::joinable!(thread_shares -> threads (thread_id));
 
 diesel::allow_tables_to_appear_in_same_query!(
+    casts,
+    followed_channels,
     messages,
+    reactions,
+    thread_shares,
     threads,
+    user_data,
+    user_settings,
 );
 }}
@@ -0,0 +1,462 @@
+use cfg_if::cfg_if;
+cfg_if! {
+    if #[cfg(feature = "ssr")] {
+        use anyhow::{anyhow, Error};
+        use chrono::{DateTime, Duration, SecondsFormat, Utc};
+        use http::header::{HeaderMap, COOKIE};
+        use k256::ecdsa::{RecoveryId, Signature, VerifyingKey};
+        use leptos::{use_context, ServerFnError};
+        use reqwest::Client;
+        use serde::Deserialize;
+        use sha3::{Digest, Keccak256};
+        use crate::config::Config;
+        use crate::services::cache::CacheStore;
+        use crate::error_template::AppError;
+
+        use crate::models::auth::SessionUser;
+        use crate::state::AppState;
+
+        pub const SESSION_COOKIE: &str = "nwt_session";
+        const SESSION_TTL_SECS: u64 = 60 * 60 * 24 * 7;
+        const NONCE_TTL_SECS: u64 = 60 * 5;
+        // siwf messages are always for optimism, where the id registry lives
+        const SIWF_CHAIN_ID: u64 = 10;
+        const SIWF_STATEMENT: &str = "Sign in to thenetworktimes with your Farcaster account.";
+
+        /// Where sign in messages are allowed to come from. `SIWF_DOMAIN` and
+        /// `SIWF_URI` default to the address the server listens on.
+        #[derive(Debug, Clone)]
+        pub struct SiwfSettings {
+            pub domain: String,
+            pub uri: String,
+        }
+
+        impl SiwfSettings {
+            pub fn from_config(config: &Config, site_addr: &str) -> Self {
+                let domain = config.siwf_domain.clone().unwrap_or_else(|| site_addr.to_string());
+                let uri = config.siwf_uri.clone().unwrap_or_else(|| format!("http://{}", domain));
+                SiwfSettings { domain, uri }
+            }
+        }
+
+        /// An EIP-4361 (sign in with ethereum) message carrying a
+        /// `farcaster://fid/<fid>` resource, which is all SIWF adds on top.
+        #[derive(Debug, Clone, PartialEq)]
+        pub struct SiwfMessage {
+            pub domain: String,
+            pub address: String,
+            pub statement: Option<String>,
+            pub uri: String,
+            pub version: String,
+            pub chain_id: u64,
+            pub nonce: String,
+            pub issued_at: DateTime<Utc>,
+            pub expiration_time: Option<DateTime<Utc>>,
+            pub not_before: Option<DateTime<Utc>>,
+            pub resources: Vec<String>,
+        }
+
+        impl SiwfMessage {
+            pub fn new(settings: &SiwfSettings, address: &str, fid: u64, nonce: &str, now: DateTime<Utc>) -> Self {
+                SiwfMessage {
+                    domain: settings.domain.clone(),
+                    address: address.to_string(),
+                    statement: Some(SIWF_STATEMENT.to_string()),
+                    uri: settings.uri.clone(),
+                    version: "1".to_string(),
+                    chain_id: SIWF_CHAIN_ID,
+                    nonce: nonce.to_string(),
+                    issued_at: now,
+                    expiration_time: Some(now + Duration::seconds(NONCE_TTL_SECS as i64)),
+                    not_before: None,
+                    resources: vec![format!("farcaster://fid/{}", fid)],
+                }
+            }
+
+            pub fn parse(text: &str) -> Result<Self, Error> {
+                let mut lines = text.lines().peekable();
+
+                let domain = lines
+                    .next()
+                    .and_then(|line| line.strip_suffix(" wants you to sign in with your Ethereum account:"))
+                    .ok_or_else(|| anyhow!("not a sign in with ethereum message"))?
+                    .to_string();
+                let address = lines.next().ok_or_else(|| anyhow!("missing address"))?.trim().to_string();
+
+                let mut statement = None;
+                while lines.peek().is_some_and(|line| line.is_empty()) {
+                    lines.next();
+                }
+                if lines.peek().is_some_and(|line| !line.starts_with("URI: ")) {
+                    statement = lines.next().map(str::to_string);
+                }
+
+                let mut uri = None;
+                let mut version = None;
+                let mut chain_id = None;
+                let mut nonce = None;
+                let mut issued_at = None;
+                let mut expiration_time = None;
+                let mut not_before = None;
+                let mut resources = Vec::new();
+
+                for line in lines {
+                    if line.is_empty() || line == "Resources:" {
+                        continue;
+                    }
+                    if let Some(resource) = line.strip_prefix("- ") {
+                        resources.push(resource.to_string());
+                        continue;
+                    }
+                    let (key, value) = line.split_once(": ").ok_or_else(|| anyhow!("malformed line: {}", line))?;
+                    match key {
+                        "URI" => uri = Some(value.to_string()),
+                        "Version" => version = Some(value.to_string()),
+                        "Chain ID" => chain_id = Some(value.parse::<u64>()?),
+                        "Nonce" => nonce = Some(value.to_string()),
+                        "Issued At" => issued_at = Some(parse_time(value)?),
+                        "Expiration Time" => expiration_time = Some(parse_time(value)?),
+                        "Not Before" => not_before = Some(parse_time(value)?),
+                        // request id and anything newer don't matter here
+                        _ => {}
+                    }
+                }
+
+                Ok(SiwfMessage {
+                    domain,
+                    address,
+                    statement,
+                    uri: uri.ok_or_else(|| anyhow!("missing URI"))?,
+                    version: version.ok_or_else(|| anyhow!("missing Version"))?,
+                    chain_id: chain_id.ok_or_else(|| anyhow!("missing Chain ID"))?,
+                    nonce: nonce.ok_or_else(|| anyhow!("missing Nonce"))?,
+                    issued_at: issued_at.ok_or_else(|| anyhow!("missing Issued At"))?,
+                    expiration_time,
+                    not_before,
+                    resources,
+                })
+            }
+
+            pub fn fid(&self) -> Option<u64> {
+                self.resources
+                    .iter()
+                    .find_map(|resource| resource.strip_prefix("farcaster://fid/"))
+                    .and_then(|fid| fid.parse().ok())
+            }
+
+            /// Checks everything that doesn't need the outside world: where the
+            /// message is for, when it's valid, and who signed it.
+            pub fn verify(&self, signature: &str, domain: &str, now: DateTime<Utc>) -> Result<(), Error> {
+                if self.domain != domain {
+                    return Err(anyhow!("message is for {}, not {}", self.domain, domain));
+                }
+                if self.version != "1" || self.chain_id != SIWF_CHAIN_ID {
+                    return Err(anyhow!("unsupported version {} on chain {}", self.version, self.chain_id));
+                }
+                if self.expiration_time.is_some_and(|expires| expires <= now) {
+                    return Err(anyhow!("message has expired"));
+                }
+                if self.not_before.is_some_and(|not_before| not_before > now) {
+                    return Err(anyhow!("message is not valid yet"));
+                }
+                if self.fid().is_none() {
+                    return Err(anyhow!("message has no farcaster fid resource"));
+                }
+                let signer = recover_address(&self.to_string(), signature)?;
+                if !signer.eq_ignore_ascii_case(&self.address) {
+                    return Err(anyhow!("signed by {}, not {}", signer, self.address));
+                }
+                Ok(())
+            }
+        }
+
+        impl std::fmt::Display for SiwfMessage {
+            fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+                writeln!(f, "{} wants you to sign in with your Ethereum account:", self.domain)?;
+                writeln!(f, "{}", self.address)?;
+                writeln!(f)?;
+                if let Some(statement) = &self.statement {
+                    writeln!(f, "{}", statement)?;
+                    writeln!(f)?;
+                }
+                writeln!(f, "URI: {}", self.uri)?;
+                writeln!(f, "Version: {}", self.version)?;
+                writeln!(f, "Chain ID: {}", self.chain_id)?;
+                writeln!(f, "Nonce: {}", self.nonce)?;
+                write!(f, "Issued At: {}", format_time(self.issued_at))?;
+                if let Some(expiration_time) = self.expiration_time {
+                    write!(f, "\nExpiration Time: {}", format_time(expiration_time))?;
+                }
+                if let Some(not_before) = self.not_before {
+                    write!(f, "\nNot Before: {}", format_time(not_before))?;
+                }
+                if !self.resources.is_empty() {
+                    write!(f, "\nResources:")?;
+                    for resource in &self.resources {
+                        write!(f, "\n- {}", resource)?;
+                    }
+                }
+                Ok(())
+            }
+        }
+
+        fn parse_time(value: &str) -> Result<DateTime<Utc>, Error> {
+            Ok(DateTime::parse_from_rfc3339(value)?.with_timezone(&Utc))
+        }
+
+        fn format_time(time: DateTime<Utc>) -> String {
+            time.to_rfc3339_opts(SecondsFormat::Millis, true)
+        }
+
+        /// Recovers the address behind an EIP-191 `personal_sign` signature.
+        /// Smart contract wallets (ERC-1271) aren't supported; custody
+        /// addresses are plain accounts.
+        pub fn recover_address(message: &str, signature: &str) -> Result<String, Error> {
+            let bytes = hex::decode(signature.trim().trim_start_matches("0x"))
+                .map_err(|e| anyhow!("signature is not valid hex: {}", e))?;
+            if bytes.len() != 65 {
+                return Err(anyhow!("signature should be 65 bytes, got {}", bytes.len()));
+            }
+            let signature = Signature::from_slice(&bytes[..64])?;
+            let v = bytes[64];
+            let recovery_id = RecoveryId::from_byte(if v >= 27 { v - 27 } else { v })
+                .ok_or_else(|| anyhow!("bad recovery id {}", v))?;
+
+            let key = VerifyingKey::recover_from_digest(personal_message_digest(message), &signature, recovery_id)?;
+            Ok(address_of(&key))
+        }
+
+        fn personal_message_digest(message: &str) -> Keccak256 {
+            let mut digest = Keccak256::new();
+            digest.update(format!("\x19Ethereum Signed Message:\n{}", message.len()));
+            digest.update(message);
+            digest
+        }
+
+        fn address_of(key: &VerifyingKey) -> String {
+            let point = key.to_encoded_point(false);
+            let hash = Keccak256::digest(&point.as_bytes()[1..]);
+            format!("0x{}", hex::encode(&hash[12..]))
+        }
+
+        #[derive(Deserialize)]
+        #[serde(rename_all = "camelCase")]
+        struct IdRegistryEvent {
+            fid: u64,
+            id_register_event_body: IdRegisterEventBody,
+        }
+
+        #[derive(Deserialize)]
+        struct IdRegisterEventBody {
+            to: String,
+        }
+
+        /// The fid `address` is currently custody of, according to the hub.
+        pub async fn custody_fid(client: &Client, hub_url: &str, address: &str) -> Result<Option<u64>, Error> {
+            let response = client
+                .get(format!("{}/v1/onChainIdRegistryEventByAddress", hub_url))
+                .query(&[("address", address)])
+                .send()
+                .await?;
+            if response.status() == reqwest::StatusCode::NOT_FOUND {
+                return Ok(None);
+            }
+            if !response.status().is_success() {
+                return Err(anyhow!("hub returned {} looking up {}", response.status(), address));
+            }
+            let event = response.json::<IdRegistryEvent>().await?;
+            // a transfer away still shows up under the old address, with someone else as `to`
+            Ok(event.id_register_event_body.to.eq_ignore_ascii_case(address).then_some(event.fid))
+        }
+
+        /// 244 random bits, hex encoded; for anything that must not be guessable
+        pub fn random_token() -> String {
+            format!("{}{}", uuid::Uuid::new_v4().simple(), uuid::Uuid::new_v4().simple())
+        }
+
+        pub async fn issue_nonce(cache: &CacheStore) -> Result<String, Error> {
+            let nonce = uuid::Uuid::new_v4().simple().to_string();
+            let ttl = std::time::Duration::from_secs(NONCE_TTL_SECS);
+            if !cache.set_durable(&format!("siwf:nonce:{}", nonce), "1".to_string(), ttl).await {
+                return Err(anyhow!("the session store is unavailable"));
+            }
+            Ok(nonce)
+        }
+
+        /// True the first time a nonce we issued comes back, false ever after.
+        pub async fn consume_nonce(cache: &CacheStore, nonce: &str) -> bool {
+            cache.take_durable(&format!("siwf:nonce:{}", nonce)).await.is_some()
+        }
+
+        pub async fn create_session(cache: &CacheStore, user: &SessionUser) -> Result<String, Error> {
+            let token = random_token();
+            let serialized = serde_json::to_string(user)?;
+            let ttl = std::time::Duration::from_secs(SESSION_TTL_SECS);
+            if !cache.set_durable(&format!("session:{}", token), serialized, ttl).await {
+                return Err(anyhow!("the session store is unavailable"));
+            }
+            Ok(token)
+        }
+
+        pub async fn load_session(cache: &CacheStore, token: &str) -> Option<SessionUser> {
+            let session = cache.get_durable(&format!("session:{}", token)).await?;
+            serde_json::from_str(&session).ok()
+        }
+
+        pub async fn delete_session(cache: &CacheStore, token: &str) {
+            cache.delete_durable(&format!("session:{}", token), std::time::Duration::from_secs(SESSION_TTL_SECS)).await
+        }
+
+        pub fn session_token(headers: &HeaderMap) -> Option<String> {
+            headers
+                .get_all(COOKIE)
+                .iter()
+                .filter_map(|value| value.to_str().ok())
+                .flat_map(|value| value.split(';'))
+                .filter_map(|pair| pair.trim().split_once('='))
+                .find(|(name, _)| *name == SESSION_COOKIE)
+                .map(|(_, token)| token.to_string())
+                .filter(|token| !token.is_empty())
+        }
+
+        pub fn session_cookie(token: &str) -> String {
+            format!("{}={}; Path=/; Max-Age={}; HttpOnly; Secure; SameSite=Lax", SESSION_COOKIE, token, SESSION_TTL_SECS)
+        }
+
+        pub fn cleared_session_cookie() -> String {
+            format!("{}=; Path=/; Max-Age=0; HttpOnly; Secure; SameSite=Lax", SESSION_COOKIE)
+        }
+
+        /// The user behind a request's session cookie, for plain axum handlers.
+        pub async fn session_user(cache: &CacheStore, headers: &HeaderMap) -> Option<SessionUser> {
+            let token = session_token(headers)?;
+            load_session(cache, &token).await
+        }
+
+        /// The signed in user for the request being handled, if any. Only
+        /// works inside server fns and SSR, where the request parts are in context.
+        pub async fn current_user() -> Option<SessionUser> {
+            let parts = use_context::<http::request::Parts>()?;
+            let app_state = use_context::<AppState>()?;
+            session_user(&app_state.cache, &parts.headers).await
+        }
+
+        pub async fn require_user() -> Result<SessionUser, ServerFnError> {
+            current_user()
+                .await
+                .ok_or_else(|| AppError::Unauthorized.into())
+        }
+
+        /// The signed in user, if they're the app's signer or listed in `EDITOR_FIDS`.
+        pub async fn require_editor() -> Result<SessionUser, ServerFnError> {
+            let user = require_user().await?;
+            let app_state = use_context::<AppState>().ok_or(AppError::Unauthorized)?;
+            if !app_state.config.is_editor(user.fid) {
+                return Err(AppError::Unauthorized.into());
+            }
+            Ok(user)
+        }
+
+        #[cfg(test)]
+        mod tests {
+            use super::*;
+            use chrono::TimeZone;
+            use http::HeaderValue;
+            use k256::ecdsa::SigningKey;
+
+            fn settings() -> SiwfSettings {
+                SiwfSettings { domain: "thenetworktimes.xyz".to_string(), uri: "https://thenetworktimes.xyz".to_string() }
+            }
+
+            fn now() -> DateTime<Utc> {
+                Utc.with_ymd_and_hms(2024, 6, 1, 12, 0, 0).unwrap()
+            }
+
+            fn wallet() -> SigningKey {
+                SigningKey::from_slice(&[1u8; 32]).unwrap()
+            }
+
+            fn personal_sign(key: &SigningKey, message: &str) -> String {
+                let (signature, recovery_id) = key.sign_digest_recoverable(personal_message_digest(message)).unwrap();
+                let mut bytes = signature.to_bytes().to_vec();
+                bytes.push(recovery_id.to_byte() + 27);
+                format!("0x{}", hex::encode(bytes))
+            }
+
+            fn signed_message() -> (SiwfMessage, String) {
+                let key = wallet();
+                let address = address_of(key.verifying_key());
+                let message = SiwfMessage::new(&settings(), &address, 3, "abc123", now());
+                let signature = personal_sign(&key, &message.to_string());
+                (message, signature)
+            }
+
+            #[test]
+            fn derives_ethereum_addresses() {
+                // the well known address for private key 0x00..01
+                let mut secret = [0u8; 32];
+                secret[31] = 1;
+                let key = SigningKey::from_slice(&secret).unwrap();
+                assert_eq!(address_of(key.verifying_key()), "0x7e5f4552091a69125d5dfcb7b8c2659029395bdf");
+            }
+
+            #[test]
+            fn round_trips_the_message_text() {
+                let (message, _) = signed_message();
+                let text = message.to_string();
+                assert!(text.starts_with("thenetworktimes.xyz wants you to sign in with your Ethereum account:\n0x"));
+                assert!(text.contains("\nChain ID: 10\nNonce: abc123\nIssued At: 2024-06-01T12:00:00.000Z\n"));
+                assert!(text.ends_with("Resources:\n- farcaster://fid/3"));
+                assert_eq!(SiwfMessage::parse(&text).unwrap(), message);
+            }
+
+            #[test]
+            fn parses_messages_without_a_statement() {
+                let text = "example.com wants you to sign in with your Ethereum account:\n0xabc\n\nURI: https://example.com\nVersion: 1\nChain ID: 10\nNonce: n\nIssued At: 2024-06-01T12:00:00Z\nResources:\n- farcaster://fid/42";
+                let message = SiwfMessage::parse(text).unwrap();
+                assert_eq!(message.statement, None);
+                assert_eq!(message.fid(), Some(42));
+                assert_eq!(message.issued_at, now());
+            }
+
+            #[test]
+            fn accepts_a_valid_signature() {
+                let (message, signature) = signed_message();
+                assert!(message.verify(&signature, "thenetworktimes.xyz", now()).is_ok());
+            }
+
+            #[test]
+            fn rejects_someone_elses_signature() {
+                let (message, _) = signed_message();
+                let other = SigningKey::from_slice(&[2u8; 32]).unwrap();
+                let signature = personal_sign(&other, &message.to_string());
+                assert!(message.verify(&signature, "thenetworktimes.xyz", now()).is_err());
+            }
+
+            #[test]
+            fn rejects_tampered_messages() {
+                let (mut message, signature) = signed_message();
+                message.resources = vec!["farcaster://fid/1".to_string()];
+                assert!(message.verify(&signature, "thenetworktimes.xyz", now()).is_err());
+            }
+
+            #[test]
+            fn rejects_other_domains_and_expired_messages() {
+                let (message, signature) = signed_message();
+                assert!(message.verify(&signature, "evil.example", now()).is_err());
+                assert!(message.verify(&signature, "thenetworktimes.xyz", now() + Duration::minutes(10)).is_err());
+            }
+
+            #[test]
+            fn finds_the_session_cookie() {
+                let mut headers = HeaderMap::new();
+                headers.insert(COOKIE, HeaderValue::from_static("theme=dark; nwt_session=tok123; other=1"));
+                assert_eq!(session_token(&headers), Some("tok123".to_string()));
+
+                headers.insert(COOKIE, HeaderValue::from_static("nwt_session="));
+                assert_eq!(session_token(&headers), None);
+                assert_eq!(session_token(&HeaderMap::new()), None);
+            }
+        }
+    }
+}
@@ -0,0 +1,668 @@
+use cfg_if::cfg_if;
+
+cfg_if! {
+    if #[cfg(feature = "ssr")] {
+        use lru::LruCache;
+        use redis::aio::MultiplexedConnection;
+        use redis::{AsyncCommands, Client, RedisError, RedisResult};
+        use serde::de::DeserializeOwned;
+        use serde::{Deserialize, Serialize};
+        use std::collections::HashMap;
+        use std::fmt::Display;
+        use std::future::Future;
+        use std::marker::PhantomData;
+        use std::num::NonZeroUsize;
+        use std::sync::{Arc, Mutex};
+        use std::time::{Duration, Instant};
+
+        use crate::models::farcaster::{Cast, CastResponse, ChannelsResponse, LinkPreview, ProfileData, ReactionsResponse, UserDataResponse};
+
+        /// how many entries the in-process fallback keeps before evicting
+        const LOCAL_CAPACITY: usize = 10_000;
+        /// a cache that takes longer than this is slower than going without
+        const COMMAND_TIMEOUT: Duration = Duration::from_millis(500);
+        const CONNECT_TIMEOUT: Duration = Duration::from_secs(2);
+        /// how long to stay on the local cache after redis goes away
+        const RETRY_AFTER: Duration = Duration::from_secs(30);
+
+        struct Entry {
+            value: String,
+            expires_at: Instant,
+        }
+
+        /// Sessions and sign in nonces, kept apart from the LRU so cache churn
+        /// can't evict a login.
+        #[derive(Default)]
+        struct Durable {
+            /// only used without redis; with it, redis holds the one copy
+            entries: HashMap<String, Entry>,
+            /// deletes redis missed, honoured until they're replayed or would have expired
+            tombstones: HashMap<String, Instant>,
+        }
+
+        #[derive(Default)]
+        struct Connection {
+            conn: Option<MultiplexedConnection>,
+            retry_at: Option<Instant>,
+        }
+
+        struct RedisBackend {
+            client: Client,
+            state: tokio::sync::Mutex<Connection>,
+        }
+
+        impl RedisBackend {
+            /// the live connection, or a fresh one if it's time to try again
+            async fn connection(&self) -> Option<MultiplexedConnection> {
+                let mut state = self.state.lock().await;
+                if let Some(conn) = &state.conn {
+                    return Some(conn.clone());
+                }
+                if state.retry_at.is_some_and(|retry_at| Instant::now() < retry_at) {
+                    return None;
+                }
+                match tokio::time::timeout(CONNECT_TIMEOUT, self.client.get_multiplexed_async_connection()).await {
+                    Ok(Ok(conn)) => {
+                        if state.retry_at.is_some() {
+                            crate::log_info!("redis is back, leaving the local cache");
+                        }
+                        *state = Connection { conn: Some(conn.clone()), retry_at: None };
+                        Some(conn)
+                    }
+                    Ok(Err(e)) => {
+                        crate::log_warn!("redis unavailable, using the local cache for {:?}: {}", RETRY_AFTER, e);
+                        state.retry_at = Some(Instant::now() + RETRY_AFTER);
+                        None
+                    }
+                    Err(_) => {
+                        crate::log_warn!("redis didn't answer, using the local cache for {:?}", RETRY_AFTER);
+                        state.retry_at = Some(Instant::now() + RETRY_AFTER);
+                        None
+                    }
+                }
+            }
+
+            async fn disconnect(&self) {
+                *self.state.lock().await = Connection { conn: None, retry_at: Some(Instant::now() + RETRY_AFTER) };
+            }
+        }
+
+        /// Key/value storage with expiry for caches, sessions and nonces. Backed
+        /// by redis when there is one and it's answering; otherwise, or while it's
+        /// down, by an LRU in this process. Nothing here fails: a broken backend
+        /// is logged and stepped around.
+        ///
+        /// The `*_durable` methods are for auth state, which has to agree with
+        /// redis once it's back: while it's down they refuse writes, read nothing,
+        /// and remember deletes to replay later.
+        #[derive(Clone)]
+        pub struct CacheStore {
+            redis: Option<Arc<RedisBackend>>,
+            local: Arc<Mutex<LruCache<String, Entry>>>,
+            durable: Arc<Mutex<Durable>>,
+        }
+
+        impl CacheStore {
+            /// Doesn't connect yet; the first command does.
+            pub fn new(redis_url: Option<&str>) -> RedisResult<Self> {
+                let redis = match redis_url {
+                    Some(url) => Some(Arc::new(RedisBackend {
+                        client: Client::open(url)?,
+                        state: tokio::sync::Mutex::new(Connection::default()),
+                    })),
+                    None => None,
+                };
+                Ok(CacheStore { redis, local: local(LOCAL_CAPACITY), durable: Arc::default() })
+            }
+
+            /// no redis at all, for development and tests
+            pub fn in_memory(capacity: usize) -> Self {
+                CacheStore { redis: None, local: local(capacity), durable: Arc::default() }
+            }
+
+            pub async fn get(&self, key: &str) -> Option<String> {
+                let owned = key.to_string();
+                match self.redis("get", key, |mut conn| async move { conn.get(owned).await }).await {
+                    Some(value) => value,
+                    None => self.local_get(key, false),
+                }
+            }
+
+            /// one `MGET` for the lot, in the order asked for
+            pub async fn get_many(&self, keys: &[String]) -> Vec<Option<String>> {
+                if keys.is_empty() {
+                    return Vec::new();
+                }
+                let owned = keys.to_vec();
+                let fetched = self
+                    .redis("mget", &keys[0], |mut conn| async move {
+                        // a single key makes MGET answer like GET, so always ask with a list
+                        redis::cmd("MGET").arg(owned).query_async(&mut conn).await
+                    })
+                    .await;
+                match fetched {
+                    Some(values) => values,
+                    None => keys.iter().map(|key| self.local_get(key, false)).collect(),
+                }
+            }
+
+            pub async fn set(&self, key: &str, value: String, ttl: Duration) {
+                let (owned, redis_value) = (key.to_string(), value.clone());
+                let secs = ttl.as_secs().max(1);
+                let stored = self
+                    .redis("set", key, |mut conn| async move { conn.set_ex::<_, _, ()>(owned, redis_value, secs).await })
+                    .await;
+                if stored.is_none() {
+                    self.local_set(key, value, ttl);
+                }
+            }
+
+            /// Adds `by` to a counter and returns the new total. The counter expires
+            /// `ttl` after the last bump.
+            pub async fn incr_by(&self, key: &str, by: u64, ttl: Duration) -> u64 {
+                let owned = key.to_string();
+                let secs = ttl.as_secs().max(1) as i64;
+                let counted = self
+                    .redis("incr", key, |mut conn| async move {
+                        let (total,): (u64,) = redis::pipe()
+                            .atomic()
+                            .incr(&owned, by)
+                            .expire(&owned, secs)
+                            .ignore()
+                            .query_async(&mut conn)
+                            .await?;
+                        Ok(total)
+                    })
+                    .await;
+                if let Some(total) = counted {
+                    return total;
+                }
+                let mut local = self.local.lock().expect("local cache poisoned");
+                let now = Instant::now();
+                let current = local
+                    .get(key)
+                    .filter(|entry| entry.expires_at > now)
+                    .and_then(|entry| entry.value.parse::<u64>().ok())
+                    .unwrap_or(0);
+                let total = current + by;
+                local.put(key.to_string(), Entry { value: total.to_string(), expires_at: now + ttl });
+                total
+            }
+
+            pub async fn delete(&self, key: &str) {
+                self.redis_delete(key).await;
+                // whatever was written locally during an outage has to go too
+                self.local.lock().expect("local cache poisoned").pop(key);
+            }
+
+            /// Stores auth state, returning whether it was stored. With redis
+            /// configured that's only if redis took it.
+            pub async fn set_durable(&self, key: &str, value: String, ttl: Duration) -> bool {
+                if self.redis.is_none() {
+                    let mut durable = self.durable.lock().expect("durable store poisoned");
+                    let now = Instant::now();
+                    durable.entries.retain(|_, entry| entry.expires_at > now);
+                    durable.entries.insert(key.to_string(), Entry { value, expires_at: now + ttl });
+                    return true;
+                }
+                self.replay_deletes().await;
+                let owned = key.to_string();
+                let secs = ttl.as_secs().max(1);
+                self.redis("set", key, |mut conn| async move { conn.set_ex::<_, _, ()>(owned, value, secs).await })
+                    .await
+                    .is_some()
+            }
+
+            pub async fn get_durable(&self, key: &str) -> Option<String> {
+                self.read_durable(key, false).await
+            }
+
+            /// reads and removes in one go, so only one caller ever sees the value
+            pub async fn take_durable(&self, key: &str) -> Option<String> {
+                self.read_durable(key, true).await
+            }
+
+            /// Deletes auth state. A delete redis misses is held locally for `ttl`,
+            /// as long as the value could have lived, and retried until it lands.
+            pub async fn delete_durable(&self, key: &str, ttl: Duration) {
+                if self.redis.is_none() {
+                    self.durable.lock().expect("durable store poisoned").entries.remove(key);
+                    return;
+                }
+                self.replay_deletes().await;
+                if !self.redis_delete(key).await {
+                    crate::log_warn!("couldn't delete {} from redis, holding it as deleted until it can", key);
+                    let mut durable = self.durable.lock().expect("durable store poisoned");
+                    durable.tombstones.insert(key.to_string(), Instant::now() + ttl);
+                }
+            }
+
+            async fn read_durable(&self, key: &str, remove: bool) -> Option<String> {
+                if self.redis.is_none() {
+                    let mut durable = self.durable.lock().expect("durable store poisoned");
+                    let now = Instant::now();
+                    let entry = if remove {
+                        durable.entries.remove(key)
+                    } else {
+                        durable.entries.get(key).map(|entry| Entry { value: entry.value.clone(), expires_at: entry.expires_at })
+                    };
+                    return entry.filter(|entry| entry.expires_at > now).map(|entry| entry.value);
+                }
+                self.replay_deletes().await;
+                if self.is_tombstoned(key) {
+                    return None;
+                }
+                let owned = key.to_string();
+                let value = if remove {
+                    self.redis("take", key, |mut conn| async move { conn.get_del(owned).await }).await
+                } else {
+                    self.redis("get", key, |mut conn| async move { conn.get(owned).await }).await
+                };
+                value.flatten()
+            }
+
+            fn is_tombstoned(&self, key: &str) -> bool {
+                let durable = self.durable.lock().expect("durable store poisoned");
+                durable.tombstones.get(key).is_some_and(|until| *until > Instant::now())
+            }
+
+            /// sends redis the deletes it missed, stopping at the first it still can't take
+            async fn replay_deletes(&self) {
+                let pending: Vec<String> = {
+                    let mut durable = self.durable.lock().expect("durable store poisoned");
+                    let now = Instant::now();
+                    durable.tombstones.retain(|_, until| *until > now);
+                    durable.tombstones.keys().cloned().collect()
+                };
+                for key in pending {
+                    if !self.redis_delete(&key).await {
+                        break;
+                    }
+                    self.durable.lock().expect("durable store poisoned").tombstones.remove(&key);
+                }
+            }
+
+            async fn redis_delete(&self, key: &str) -> bool {
+                let owned = key.to_string();
+                self.redis("delete", key, |mut conn| async move { conn.del::<_, ()>(owned).await }).await.is_some()
+            }
+
+            /// `None` when redis isn't configured, isn't reachable or just failed
+            async fn redis<T, F, Fut>(&self, op: &str, key: &str, command: F) -> Option<T>
+            where
+                F: FnOnce(MultiplexedConnection) -> Fut,
+                Fut: Future<Output = RedisResult<T>>,
+            {
+                let backend = self.redis.as_ref()?;
+                let conn = backend.connection().await?;
+                match tokio::time::timeout(COMMAND_TIMEOUT, command(conn)).await {
+                    Ok(Ok(value)) => Some(value),
+                    Ok(Err(e)) => {
+                        crate::log_warn!("redis {} {} failed, using the local cache: {}", op, key, e);
+                        if is_connection_error(&e) {
+                            backend.disconnect().await;
+                        }
+                        None
+                    }
+                    Err(_) => {
+                        crate::log_warn!("redis {} {} timed out, using the local cache", op, key);
+                        backend.disconnect().await;
+                        None
+                    }
+                }
+            }
+
+            fn local_get(&self, key: &str, remove: bool) -> Option<String> {
+                let mut local = self.local.lock().expect("local cache poisoned");
+                let live = local.get(key).is_some_and(|entry| entry.expires_at > Instant::now());
+                if !live || remove {
+                    return local.pop(key).filter(|_| live).map(|entry| entry.value);
+                }
+                local.get(key).map(|entry| entry.value.clone())
+            }
+
+            fn local_set(&self, key: &str, value: String, ttl: Duration) {
+                let entry = Entry { value, expires_at: Instant::now() + ttl };
+                self.local.lock().expect("local cache poisoned").put(key.to_string(), entry);
+            }
+        }
+
+        /// Where a kind of value lives in the store and how long it stays there.
+        #[derive(Clone, Copy, Debug)]
+        pub struct Namespace {
+            pub name: &'static str,
+            /// bump when the cached type changes shape, so old entries are never read
+            pub version: u32,
+            pub ttl: Duration,
+            /// how long "doesn't exist" is remembered
+            pub missing_ttl: Duration,
+        }
+
+        #[derive(Serialize, Deserialize)]
+        enum Cached<V> {
+            Found(V),
+            Missing,
+        }
+
+        type Flights = Arc<Mutex<HashMap<String, Arc<tokio::sync::Mutex<()>>>>>;
+
+        /// A typed view over one namespace of the [`CacheStore`]. Misses for the
+        /// same key are loaded once, with everyone else waiting on that load, and
+        /// things the source says don't exist are cached as such.
+        pub struct Cache<K, V> {
+            store: CacheStore,
+            namespace: Namespace,
+            flights: Flights,
+            _types: PhantomData<fn(&K) -> V>,
+        }
+
+        impl<K, V> Clone for Cache<K, V> {
+            fn clone(&self) -> Self {
+                Cache {
+                    store: self.store.clone(),
+                    namespace: self.namespace,
+                    flights: self.flights.clone(),
+                    _types: PhantomData,
+                }
+            }
+        }
+
+        impl<K: Display, V: Serialize + DeserializeOwned> Cache<K, V> {
+            pub fn new(store: CacheStore, namespace: Namespace) -> Self {
+                Cache { store, namespace, flights: Flights::default(), _types: PhantomData }
+            }
+
+            fn key(&self, key: &K) -> String {
+                format!("{}:v{}:{}", self.namespace.name, self.namespace.version, key)
+            }
+
+            /// `None` on a miss, `Some(None)` when the value is known not to exist
+            pub async fn get(&self, key: &K) -> Option<Option<V>> {
+                self.read(&self.key(key)).await
+            }
+
+            /// like [`Cache::get`] for each key, read from the store in one round trip
+            pub async fn get_many(&self, keys: &[K]) -> Vec<Option<Option<V>>> {
+                let keys: Vec<String> = keys.iter().map(|key| self.key(key)).collect();
+                self.store
+                    .get_many(&keys)
+                    .await
+                    .into_iter()
+                    .zip(&keys)
+                    .map(|(raw, key)| raw.and_then(|raw| self.decode(key, &raw)))
+                    .collect()
+            }
+
+            pub async fn set(&self, key: &K, value: Option<&V>) {
+                self.write(&self.key(key), value).await
+            }
+
+            pub async fn invalidate(&self, key: &K) {
+                self.store.delete(&self.key(key)).await
+            }
+
+            /// The cached value, or whatever `load` comes back with. `Ok(None)` from
+            /// `load` is remembered for the namespace's `missing_ttl`; errors aren't
+            /// remembered at all.
+            pub async fn get_or_load<E, F, Fut>(&self, key: &K, load: F) -> Result<Option<V>, E>
+            where
+                F: FnOnce() -> Fut,
+                Fut: Future<Output = Result<Option<V>, E>>,
+            {
+                let key = self.key(key);
+                if let Some(cached) = self.read(&key).await {
+                    return Ok(cached);
+                }
+
+                let flight = self.flights.lock().expect("cache flights poisoned").entry(key.clone()).or_default().clone();
+                let result = {
+                    let _loading = flight.lock().await;
+                    // whoever held the lock before us has probably filled it in
+                    match self.read(&key).await {
+                        Some(cached) => Ok(cached),
+                        None => {
+                            crate::log_debug!("cache miss for {}", key);
+                            let loaded = load().await;
+                            if let Ok(value) = &loaded {
+                                self.write(&key, value.as_ref()).await;
+                            }
+                            loaded
+                        }
+                    }
+                };
+
+                let mut flights = self.flights.lock().expect("cache flights poisoned");
+                // the map and us; anyone else still holding it is waiting to read
+                if Arc::strong_count(&flight) == 2 {
+                    flights.remove(&key);
+                }
+                result
+            }
+
+            async fn read(&self, key: &str) -> Option<Option<V>> {
+                let raw = self.store.get(key).await?;
+                self.decode(key, &raw)
+            }
+
+            fn decode(&self, key: &str, raw: &str) -> Option<Option<V>> {
+                match serde_json::from_str::<Cached<V>>(raw) {
+                    Ok(Cached::Found(value)) => Some(Some(value)),
+                    Ok(Cached::Missing) => Some(None),
+                    Err(e) => {
+                        crate::log_warn!("unreadable cache entry {}, treating it as a miss: {}", key, e);
+                        None
+                    }
+                }
+            }
+
+            async fn write(&self, key: &str, value: Option<&V>) {
+                let (entry, ttl) = match value {
+                    Some(value) => (serde_json::to_string(&Cached::Found(value)), self.namespace.ttl),
+                    None => (serde_json::to_string(&Cached::<&V>::Missing), self.namespace.missing_ttl),
+                };
+                match entry {
+                    Ok(entry) => self.store.set(key, entry, ttl).await,
+                    Err(e) => {
+                        crate::log_warn!("failed to serialize {} for the cache: {}", key, e);
+                    }
+                }
+            }
+        }
+
+        const MINUTE: Duration = Duration::from_secs(60);
+        const HOUR: Duration = Duration::from_secs(60 * 60);
+
+        /// Every typed cache the app reads through, built once so concurrent
+        /// requests share their in-flight loads.
+        #[derive(Clone)]
+        pub struct Caches {
+            /// the all-channels catalog, keyed by a fixed name
+            pub channels: Cache<&'static str, ChannelsResponse>,
+            /// single casts by `fid:hash`; a cast never changes once it exists
+            pub casts: Cache<String, Cast>,
+            /// pages of casts straight from the hub
+            pub cast_pages: Cache<String, CastResponse>,
+            /// reactions on a cast by `fid:hash`
+            pub reactions: Cache<String, ReactionsResponse>,
+            pub profiles: Cache<u64, ProfileData>,
+            /// one user data type for a fid by `fid:type`
+            pub user_data: Cache<String, UserDataResponse>,
+            pub link_previews: Cache<String, LinkPreview>,
+        }
+
+        impl Caches {
+            pub fn new(store: &CacheStore) -> Self {
+                let namespace = |name, ttl, missing_ttl| Namespace { name, version: 1, ttl, missing_ttl };
+                Caches {
+                    channels: Cache::new(store.clone(), namespace("channels", 6 * HOUR, 5 * MINUTE)),
+                    casts: Cache::new(store.clone(), namespace("cast", 24 * HOUR, 10 * MINUTE)),
+                    cast_pages: Cache::new(store.clone(), namespace("cast_page", MINUTE, MINUTE)),
+                    reactions: Cache::new(store.clone(), namespace("reactions", 2 * MINUTE, 2 * MINUTE)),
+                    profiles: Cache::new(store.clone(), namespace("profile", 10 * MINUTE, 5 * MINUTE)),
+                    user_data: Cache::new(store.clone(), namespace("user_data", HOUR, 10 * MINUTE)),
+                    link_previews: Cache::new(store.clone(), namespace("link_preview", 24 * HOUR, HOUR)),
+                }
+            }
+        }
+
+        fn local(capacity: usize) -> Arc<Mutex<LruCache<String, Entry>>> {
+            let capacity = NonZeroUsize::new(capacity).unwrap_or(NonZeroUsize::MIN);
+            Arc::new(Mutex::new(LruCache::new(capacity)))
+        }
+
+        fn is_connection_error(e: &RedisError) -> bool {
+            e.is_io_error() || e.is_connection_dropped() || e.is_connection_refusal() || e.is_timeout()
+        }
+
+        #[cfg(test)]
+        mod tests {
+            use super::*;
+            use std::sync::atomic::{AtomicUsize, Ordering};
+
+            fn namespace(version: u32) -> Namespace {
+                Namespace { name: "test", version, ttl: HOUR, missing_ttl: HOUR }
+            }
+
+            #[tokio::test]
+            async fn expired_entries_read_as_missing() {
+                let cache = CacheStore::in_memory(8);
+                cache.set("fresh", "1".to_string(), HOUR).await;
+                cache.set("stale", "2".to_string(), Duration::ZERO).await;
+
+                assert_eq!(cache.get("fresh").await.as_deref(), Some("1"));
+                assert_eq!(cache.get("stale").await, None);
+            }
+
+            #[tokio::test]
+            async fn take_hands_a_value_out_once() {
+                let cache = CacheStore::in_memory(8);
+                assert!(cache.set_durable("nonce", "1".to_string(), HOUR).await);
+
+                assert_eq!(cache.take_durable("nonce").await.as_deref(), Some("1"));
+                assert_eq!(cache.take_durable("nonce").await, None);
+            }
+
+            #[tokio::test]
+            async fn durable_entries_are_never_evicted() {
+                let cache = CacheStore::in_memory(1);
+                cache.set_durable("session:abc", "user".to_string(), HOUR).await;
+                cache.set_durable("session:old", "user".to_string(), Duration::ZERO).await;
+                cache.set("a", "1".to_string(), HOUR).await;
+                cache.set("b", "2".to_string(), HOUR).await;
+
+                assert_eq!(cache.get_durable("session:abc").await.as_deref(), Some("user"));
+                assert_eq!(cache.get_durable("session:old").await, None);
+                cache.delete_durable("session:abc", HOUR).await;
+                assert_eq!(cache.get_durable("session:abc").await, None);
+            }
+
+            #[tokio::test]
+            async fn durable_state_is_refused_while_redis_is_down() {
+                let cache = CacheStore::new(Some("redis://127.0.0.1:1")).unwrap();
+
+                assert!(!cache.set_durable("session:abc", "user".to_string(), HOUR).await);
+                assert_eq!(cache.get_durable("session:abc").await, None);
+                cache.delete_durable("session:abc", HOUR).await;
+                assert!(cache.is_tombstoned("session:abc"));
+                cache.delete_durable("session:old", Duration::ZERO).await;
+                assert!(!cache.is_tombstoned("session:old"));
+            }
+
+            #[tokio::test]
+            async fn least_recently_used_goes_first() {
+                let cache = CacheStore::in_memory(2);
+                cache.set("a", "1".to_string(), HOUR).await;
+                cache.set("b", "2".to_string(), HOUR).await;
+                cache.get("a").await;
+                cache.set("c", "3".to_string(), HOUR).await;
+
+                assert_eq!(cache.get("b").await, None);
+                assert_eq!(cache.get("a").await.as_deref(), Some("1"));
+                cache.delete("a").await;
+                assert_eq!(cache.get("a").await, None);
+            }
+
+            #[tokio::test]
+            async fn counters_add_up_and_expire() {
+                let cache = CacheStore::in_memory(8);
+                assert_eq!(cache.incr_by("tokens", 5, HOUR).await, 5);
+                assert_eq!(cache.incr_by("tokens", 7, HOUR).await, 12);
+                assert_eq!(cache.get("tokens").await.as_deref(), Some("12"));
+
+                cache.incr_by("stale", 5, Duration::ZERO).await;
+                assert_eq!(cache.incr_by("stale", 1, HOUR).await, 1);
+            }
+
+            #[tokio::test]
+            async fn unreachable_redis_falls_back_to_local() {
+                // nothing listens on port 1
+                let cache = CacheStore::new(Some("redis://127.0.0.1:1")).unwrap();
+                cache.set("cast:abc", "cast".to_string(), HOUR).await;
+
+                assert_eq!(cache.get("cast:abc").await.as_deref(), Some("cast"));
+            }
+
+            #[tokio::test]
+            async fn concurrent_misses_load_once() {
+                let cache: Cache<u64, String> = Cache::new(CacheStore::in_memory(8), namespace(1));
+                let loads = AtomicUsize::new(0);
+                let load = || async {
+                    loads.fetch_add(1, Ordering::SeqCst);
+                    tokio::time::sleep(Duration::from_millis(20)).await;
+                    Ok::<_, ()>(Some("dwr".to_string()))
+                };
+
+                let (a, b, c) = tokio::join!(cache.get_or_load(&3, load), cache.get_or_load(&3, load), cache.get_or_load(&3, load));
+
+                assert_eq!(loads.load(Ordering::SeqCst), 1);
+                for result in [a, b, c] {
+                    assert_eq!(result, Ok(Some("dwr".to_string())));
+                }
+                assert!(cache.flights.lock().unwrap().is_empty());
+            }
+
+            #[tokio::test]
+            async fn missing_values_are_remembered_but_errors_are_not() {
+                let cache: Cache<u64, String> = Cache::new(CacheStore::in_memory(8), namespace(1));
+                let loads = AtomicUsize::new(0);
+
+                assert_eq!(cache.get_or_load(&1, || async { Err::<Option<String>, _>("hub down") }).await, Err("hub down"));
+                for _ in 0..2 {
+                    let result = cache
+                        .get_or_load(&1, || async {
+                            loads.fetch_add(1, Ordering::SeqCst);
+                            Ok::<_, ()>(None)
+                        })
+                        .await;
+                    assert_eq!(result, Ok(None));
+                }
+
+                assert_eq!(loads.load(Ordering::SeqCst), 1);
+                assert_eq!(cache.get(&1).await, Some(None));
+            }
+
+            #[tokio::test]
+            async fn get_many_keeps_the_order_asked_for() {
+                let cache: Cache<u64, String> = Cache::new(CacheStore::in_memory(8), namespace(1));
+                cache.set(&1, Some(&"one".to_string())).await;
+                cache.set(&3, None).await;
+
+                assert_eq!(cache.get_many(&[3, 2, 1]).await, vec![Some(None), None, Some(Some("one".to_string()))]);
+                assert!(cache.get_many(&[]).await.is_empty());
+            }
+
+            #[tokio::test]
+            async fn a_new_version_ignores_old_entries() {
+                let store = CacheStore::in_memory(8);
+                let old: Cache<u64, String> = Cache::new(store.clone(), namespace(1));
+                let new: Cache<u64, u64> = Cache::new(store, namespace(2));
+                old.set(&1, Some(&"v1".to_string())).await;
+
+                assert_eq!(new.get(&1).await, None);
+                assert_eq!(old.get(&1).await, Some(Some("v1".to_string())));
+                old.invalidate(&1).await;
+                assert_eq!(old.get(&1).await, None);
+            }
+        }
+    }
+}
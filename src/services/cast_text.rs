@@ -0,0 +1,487 @@
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+
+/// A piece of rendered cast text.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub enum CastTextSegment {
+    Text(String),
+    Url { href: String, text: String },
+    Mention { fid: u64, username: Option<String> },
+    /// A channel reference like `/networktimes`, stored without the slash.
+    Channel(String),
+    /// A cashtag like `$DEGEN`, stored without the dollar sign.
+    Cashtag(String),
+}
+
+/// Splits cast text into typed segments: mentions come from Hubble's `mentions`
+/// and `mentionsPositions`, named from `usernames` where the server resolved
+/// them, and everything else is picked out of the text itself.
+pub fn tokenize_cast(text: &str, mentions: &[u64], positions: &[u32], usernames: &HashMap<u64, String>) -> Vec<CastTextSegment> {
+    splice_mentions(text, mentions, positions, usernames)
+        .into_iter()
+        .flat_map(|segment| match segment {
+            CastTextSegment::Text(text) => tokenize_text(&text),
+            other => vec![other],
+        })
+        .collect()
+}
+
+/// Picks urls, channel references and cashtags out of plain text.
+pub fn tokenize_text(text: &str) -> Vec<CastTextSegment> {
+    let mut segments = Vec::new();
+    let mut plain_start = 0;
+    let mut index = 0;
+    let mut prev = None;
+
+    while let Some(c) = text[index..].chars().next() {
+        if starts_token(prev) {
+            let rest = &text[index..];
+            if let Some((len, segment)) = match_url(rest)
+                .or_else(|| match_channel(rest))
+                .or_else(|| match_cashtag(rest))
+            {
+                if plain_start < index {
+                    segments.push(CastTextSegment::Text(text[plain_start..index].to_string()));
+                }
+                segments.push(segment);
+                index += len;
+                plain_start = index;
+                prev = text[..index].chars().next_back();
+                continue;
+            }
+        }
+        prev = Some(c);
+        index += c.len_utf8();
+    }
+
+    if plain_start < text.len() {
+        segments.push(CastTextSegment::Text(text[plain_start..].to_string()));
+    }
+
+    segments
+}
+
+fn starts_token(prev: Option<char>) -> bool {
+    match prev {
+        None => true,
+        Some(c) => c.is_whitespace() || matches!(c, '(' | '[' | '{' | '"' | '\'' | '“' | '‘'),
+    }
+}
+
+fn match_url(rest: &str) -> Option<(usize, CastTextSegment)> {
+    let lower = rest.get(..8).unwrap_or(rest).to_ascii_lowercase();
+    let (prefix_len, needs_scheme) = if lower.starts_with("https://") {
+        (8, false)
+    } else if lower.starts_with("http://") {
+        (7, false)
+    } else if lower.starts_with("www.") {
+        (4, true)
+    } else {
+        return None;
+    };
+
+    let end = rest.find(char::is_whitespace).unwrap_or(rest.len());
+    let candidate = trim_url_end(&rest[..end]);
+    // trimming can leave less than the prefix, as in `www.` or `www...`
+    let host = candidate.get(prefix_len..).filter(|host| !host.is_empty())?;
+    if needs_scheme && !host.contains('.') {
+        return None;
+    }
+
+    let href = if needs_scheme {
+        format!("https://{}", candidate)
+    } else {
+        candidate.to_string()
+    };
+    Some((candidate.len(), CastTextSegment::Url { href, text: candidate.to_string() }))
+}
+
+// sentence punctuation after a url belongs to the sentence, and so does a closing
+// paren or bracket that the url never opened
+fn trim_url_end(url: &str) -> &str {
+    let mut url = url;
+    while let Some(last) = url.chars().next_back() {
+        let unopened = match last {
+            ')' => url.matches('(').count() < url.matches(')').count(),
+            ']' => url.matches('[').count() < url.matches(']').count(),
+            _ => false,
+        };
+        let punctuation = matches!(last, '.' | ',' | '!' | '?' | ';' | ':' | '\'' | '"' | '*' | '>' | '”' | '’');
+        if !(unopened || punctuation) {
+            break;
+        }
+        url = &url[..url.len() - last.len_utf8()];
+    }
+    url
+}
+
+const MAX_CHANNEL_ID_LEN: usize = 16;
+const MAX_CASHTAG_LEN: usize = 10;
+
+fn match_channel(rest: &str) -> Option<(usize, CastTextSegment)> {
+    let name = rest.strip_prefix('/')?;
+    let len = name
+        .find(|c: char| !(c.is_ascii_lowercase() || c.is_ascii_digit() || c == '-'))
+        .unwrap_or(name.len());
+    let id = &name[..len];
+    if id.is_empty() || id.len() > MAX_CHANNEL_ID_LEN || id.starts_with('-') || id.ends_with('-') {
+        return None;
+    }
+
+    // `/usr/bin`, `/Users` and `/index.html` are paths, not channels
+    let mut after = name[len..].chars();
+    match after.next() {
+        Some(c) if c.is_alphanumeric() || c == '/' || c == '_' => return None,
+        Some('.') if after.next().is_some_and(|c| c.is_alphanumeric()) => return None,
+        _ => {}
+    }
+
+    Some((len + 1, CastTextSegment::Channel(id.to_string())))
+}
+
+fn match_cashtag(rest: &str) -> Option<(usize, CastTextSegment)> {
+    let ticker = rest.strip_prefix('$')?;
+    if !ticker.starts_with(|c: char| c.is_ascii_alphabetic()) {
+        return None;
+    }
+    let len = ticker
+        .find(|c: char| !c.is_ascii_alphanumeric())
+        .unwrap_or(ticker.len());
+    if len > MAX_CASHTAG_LEN || ticker[len..].starts_with(|c: char| c.is_alphanumeric() || c == '_') {
+        return None;
+    }
+
+    Some((len + 1, CastTextSegment::Cashtag(ticker[..len].to_string())))
+}
+
+/// Splices mentions back into cast text.
+///
+/// Hubble strips `@username` out of `text` and reports each mentioned fid with the
+/// byte offset (into the stripped UTF-8 text) where it used to be. Offsets past the
+/// end of the text are clamped to it, and offsets that land inside a multi-byte
+/// character are moved back to the start of that character.
+pub fn splice_mentions(
+    text: &str,
+    mentions: &[u64],
+    positions: &[u32],
+    usernames: &HashMap<u64, String>,
+) -> Vec<CastTextSegment> {
+    let mut mentions: Vec<(usize, u64)> = positions
+        .iter()
+        .zip(mentions)
+        .map(|(&position, &fid)| (position as usize, fid))
+        .collect();
+    // stable, so mentions sharing a position keep their original order
+    mentions.sort_by_key(|(position, _)| *position);
+
+    let mut segments = Vec::new();
+    let mut cursor = 0;
+
+    for (position, fid) in mentions {
+        let position = floor_char_boundary(text, position).max(cursor);
+        if position > cursor {
+            segments.push(CastTextSegment::Text(text[cursor..position].to_string()));
+        }
+        segments.push(CastTextSegment::Mention {
+            fid,
+            username: usernames.get(&fid).cloned(),
+        });
+        cursor = position;
+    }
+
+    if cursor < text.len() {
+        segments.push(CastTextSegment::Text(text[cursor..].to_string()));
+    }
+
+    segments
+}
+
+fn floor_char_boundary(text: &str, index: usize) -> usize {
+    if index >= text.len() {
+        return text.len();
+    }
+    (0..=index).rev().find(|&i| text.is_char_boundary(i)).unwrap_or(0)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use CastTextSegment::{Cashtag, Channel, Mention, Text, Url};
+
+    fn usernames() -> HashMap<u64, String> {
+        HashMap::from([(3, "dwr".to_string()), (2, "v".to_string())])
+    }
+
+    fn mention(fid: u64, username: &str) -> CastTextSegment {
+        Mention { fid, username: Some(username.to_string()) }
+    }
+
+    fn text(s: &str) -> CastTextSegment {
+        Text(s.to_string())
+    }
+
+    #[test]
+    fn no_mentions_is_one_text_segment() {
+        assert_eq!(splice_mentions("gm", &[], &[], &usernames()), vec![text("gm")]);
+    }
+
+    #[test]
+    fn empty_text_with_no_mentions_is_empty() {
+        assert!(splice_mentions("", &[], &[], &usernames()).is_empty());
+    }
+
+    #[test]
+    fn splices_mentions_at_byte_positions() {
+        // "hello @dwr and @v" as hubble stores it
+        let segments = splice_mentions("hello  and ", &[3, 2], &[6, 11], &usernames());
+        assert_eq!(
+            segments,
+            vec![text("hello "), mention(3, "dwr"), text(" and "), mention(2, "v")],
+        );
+    }
+
+    #[test]
+    fn mention_at_start() {
+        let segments = splice_mentions(" gm", &[3], &[0], &usernames());
+        assert_eq!(segments, vec![mention(3, "dwr"), text(" gm")]);
+    }
+
+    #[test]
+    fn adjacent_mentions_keep_their_order() {
+        let segments = splice_mentions("cc ", &[2, 3], &[3, 3], &usernames());
+        assert_eq!(segments, vec![text("cc "), mention(2, "v"), mention(3, "dwr")]);
+    }
+
+    #[test]
+    fn unsorted_positions_are_sorted() {
+        let segments = splice_mentions("a  b ", &[2, 3], &[5, 2], &usernames());
+        assert_eq!(
+            segments,
+            vec![text("a "), mention(3, "dwr"), text(" b "), mention(2, "v")],
+        );
+    }
+
+    #[test]
+    fn multi_byte_text_before_mention() {
+        // 🚀 is four bytes, so "gm " ends at byte 8
+        let segments = splice_mentions("🚀 gm ", &[3], &[8], &usernames());
+        assert_eq!(segments, vec![text("🚀 gm "), mention(3, "dwr")]);
+
+        let segments = splice_mentions("héllo  wörld", &[2], &[7], &usernames());
+        assert_eq!(segments, vec![text("héllo "), mention(2, "v"), text(" wörld")]);
+    }
+
+    #[test]
+    fn position_inside_a_character_moves_back() {
+        // byte 2 is in the middle of 🚀
+        let segments = splice_mentions("🚀!", &[3], &[2], &usernames());
+        assert_eq!(segments, vec![mention(3, "dwr"), text("🚀!")]);
+    }
+
+    #[test]
+    fn position_past_the_end_is_clamped() {
+        let segments = splice_mentions("gm ", &[3], &[99], &usernames());
+        assert_eq!(segments, vec![text("gm "), mention(3, "dwr")]);
+    }
+
+    #[test]
+    fn unresolved_username_is_kept_as_fid() {
+        let segments = splice_mentions("hi ", &[42], &[3], &usernames());
+        assert_eq!(segments, vec![text("hi "), Mention { fid: 42, username: None }]);
+    }
+
+    #[test]
+    fn mismatched_lengths_use_the_shorter_list() {
+        let segments = splice_mentions("hi ", &[3, 2], &[3], &usernames());
+        assert_eq!(segments, vec![text("hi "), mention(3, "dwr")]);
+    }
+
+    fn url(href: &str) -> CastTextSegment {
+        Url { href: href.to_string(), text: href.to_string() }
+    }
+
+    fn channel(id: &str) -> CastTextSegment {
+        Channel(id.to_string())
+    }
+
+    fn cashtag(ticker: &str) -> CastTextSegment {
+        Cashtag(ticker.to_string())
+    }
+
+    #[test]
+    fn plain_text_is_untouched() {
+        assert_eq!(tokenize_text("gm frens"), vec![text("gm frens")]);
+    }
+
+    #[test]
+    fn two_links_are_both_kept() {
+        assert_eq!(
+            tokenize_text("read https://a.com/one and https://b.com/two"),
+            vec![text("read "), url("https://a.com/one"), text(" and "), url("https://b.com/two")],
+        );
+    }
+
+    #[test]
+    fn the_same_link_twice() {
+        assert_eq!(
+            tokenize_text("https://a.com https://a.com"),
+            vec![url("https://a.com"), text(" "), url("https://a.com")],
+        );
+    }
+
+    #[test]
+    fn trailing_punctuation_is_not_part_of_the_link() {
+        assert_eq!(
+            tokenize_text("new post: https://paragraph.xyz/@nwt/issue-4. go read it!"),
+            vec![text("new post: "), url("https://paragraph.xyz/@nwt/issue-4"), text(". go read it!")],
+        );
+        assert_eq!(
+            tokenize_text("is this real? https://x.com/a/status/1?s=20!!"),
+            vec![text("is this real? "), url("https://x.com/a/status/1?s=20"), text("!!")],
+        );
+    }
+
+    #[test]
+    fn parens_around_and_inside_links() {
+        assert_eq!(
+            tokenize_text("(https://en.wikipedia.org/wiki/Rust_(programming_language))"),
+            vec![text("("), url("https://en.wikipedia.org/wiki/Rust_(programming_language)"), text(")")],
+        );
+    }
+
+    #[test]
+    fn links_across_newlines() {
+        assert_eq!(
+            tokenize_text("links:\nhttps://a.com\nhttps://b.com"),
+            vec![text("links:\n"), url("https://a.com"), text("\n"), url("https://b.com")],
+        );
+    }
+
+    #[test]
+    fn scheme_is_case_insensitive_and_www_gets_one() {
+        assert_eq!(tokenize_text("HTTPS://X.COM"), vec![url("HTTPS://X.COM")]);
+        assert_eq!(
+            tokenize_text("try www.thenetworktimes.xyz"),
+            vec![
+                text("try "),
+                Url { href: "https://www.thenetworktimes.xyz".to_string(), text: "www.thenetworktimes.xyz".to_string() },
+            ],
+        );
+    }
+
+    #[test]
+    fn a_bare_scheme_is_not_a_link() {
+        assert_eq!(tokenize_text("https:// lol"), vec![text("https:// lol")]);
+        assert_eq!(tokenize_text("https://"), vec![text("https://")]);
+    }
+
+    #[test]
+    fn www_with_nothing_after_it_is_not_a_link() {
+        for cast in ["www.", "www...", "see www... later", "(www.)"] {
+            assert_eq!(tokenize_text(cast), vec![text(cast)], "{}", cast);
+        }
+    }
+
+    #[test]
+    fn links_inside_words_are_left_alone() {
+        assert_eq!(tokenize_text("foohttps://a.com"), vec![text("foohttps://a.com")]);
+    }
+
+    #[test]
+    fn channel_references() {
+        assert_eq!(
+            tokenize_text("cross-posting to /networktimes and /all-in!"),
+            vec![text("cross-posting to "), channel("networktimes"), text(" and "), channel("all-in"), text("!")],
+        );
+        assert_eq!(tokenize_text("/farcaster."), vec![channel("farcaster"), text(".")]);
+    }
+
+    #[test]
+    fn things_that_look_like_channels_but_are_not() {
+        for cast in ["1/2 the time", "and/or", "cat /usr/bin/env", "see /Users/me", "open /index.html", "/this-name-is-way-too-long", "/-dash"] {
+            assert_eq!(tokenize_text(cast), vec![text(cast)], "{}", cast);
+        }
+    }
+
+    #[test]
+    fn cashtags() {
+        assert_eq!(
+            tokenize_text("tipping 420 $DEGEN, and $higher."),
+            vec![text("tipping 420 "), cashtag("DEGEN"), text(", and "), cashtag("higher"), text(".")],
+        );
+    }
+
+    #[test]
+    fn prices_are_not_cashtags() {
+        for cast in ["coffee was $5", "$5.00 flat", "US$100", "$TOOLONGTICKER1", "$a_b"] {
+            assert_eq!(tokenize_text(cast), vec![text(cast)], "{}", cast);
+        }
+    }
+
+    #[test]
+    fn multi_byte_text_around_tokens() {
+        assert_eq!(
+            tokenize_text("🎩 $HAT /degen 🎩 https://degen.tips 🎩"),
+            vec![
+                text("🎩 "),
+                cashtag("HAT"),
+                text(" "),
+                channel("degen"),
+                text(" 🎩 "),
+                url("https://degen.tips"),
+                text(" 🎩"),
+            ],
+        );
+    }
+
+    #[test]
+    fn tokens_in_quotes() {
+        assert_eq!(
+            tokenize_text("“https://a.com” and '/memes'"),
+            vec![text("“"), url("https://a.com"), text("” and '"), channel("memes"), text("'")],
+        );
+    }
+
+    #[test]
+    fn mentions_links_and_channels_together() {
+        // "@dwr shared https://a.com in /farcaster" as hubble stores it
+        let segments = tokenize_cast(" shared https://a.com in /farcaster", &[3], &[0], &HashMap::new());
+        assert_eq!(
+            segments,
+            vec![
+                Mention { fid: 3, username: None },
+                text(" shared "),
+                url("https://a.com"),
+                text(" in "),
+                channel("farcaster"),
+            ],
+        );
+    }
+
+    #[test]
+    fn a_mention_right_before_a_channel_still_splits_it() {
+        let segments = tokenize_cast("gm /networktimes", &[3], &[3], &HashMap::new());
+        assert_eq!(
+            segments,
+            vec![text("gm "), Mention { fid: 3, username: None }, channel("networktimes")],
+        );
+    }
+
+    #[test]
+    fn resolved_usernames_are_spliced_in_and_the_rest_tokenized() {
+        // "gm @dwr and @v, see /farcaster" as hubble stores it, with only dwr resolved
+        let usernames = HashMap::from([(3, "dwr".to_string())]);
+        let segments = tokenize_cast("gm  and , see /farcaster", &[3, 2], &[3, 8], &usernames);
+        assert_eq!(
+            segments,
+            vec![
+                text("gm "),
+                mention(3, "dwr"),
+                text(" and "),
+                Mention { fid: 2, username: None },
+                text(", see "),
+                channel("farcaster"),
+            ],
+        );
+    }
+}
@@ -0,0 +1,353 @@
+use cfg_if::cfg_if;
+cfg_if! {
+    if #[cfg(feature = "ssr")] {
+        use anyhow::{anyhow, Error};
+        use axum::extract::{Query, State};
+        use axum::response::sse::{Event, KeepAlive, Sse};
+        use diesel::prelude::*;
+        use reqwest::Client;
+        use serde::Deserialize;
+        use std::collections::HashMap;
+        use std::time::Duration;
+        use tokio::sync::broadcast::{self, error::RecvError};
+        use tokio::sync::mpsc;
+        use tokio::task::JoinHandle;
+
+        use crate::components::chat::SseStream;
+        use crate::config::Config;
+        use crate::database::casts::upsert_casts;
+        use crate::database::db::{interact, DbPool};
+        use crate::models::channels::FollowedChannel;
+        use crate::models::farcaster::{Cast, HubEventsResponse};
+        use crate::models::indexed::NewIndexedCast;
+        use crate::schema::followed_channels;
+        use crate::time::FARCASTER_EPOCH_MILLIS;
+
+        // hub event ids are (ms since the farcaster epoch) << SEQUENCE_BITS | sequence
+        const SEQUENCE_BITS: u64 = 12;
+
+        #[derive(Debug, Clone)]
+        pub struct ChannelCast {
+            pub channel_id: String,
+            pub cast: Cast,
+        }
+
+        /// fans casts merged on the hub out to every open feed stream
+        #[derive(Clone)]
+        pub struct CastFeed {
+            sender: broadcast::Sender<ChannelCast>,
+        }
+
+        impl CastFeed {
+            pub fn new(capacity: usize) -> Self {
+                let (sender, _) = broadcast::channel(capacity);
+                CastFeed { sender }
+            }
+
+            pub fn publish(&self, cast: ChannelCast) {
+                // nobody listening just means nobody has a feed open
+                let _ = self.sender.send(cast);
+            }
+
+            pub fn subscribe(&self) -> broadcast::Receiver<ChannelCast> {
+                self.sender.subscribe()
+            }
+        }
+
+        #[derive(Clone, Debug)]
+        pub struct HubEventSettings {
+            pub events_url: String,
+            /// how long to wait before asking again once we've caught up
+            pub poll_interval: Duration,
+        }
+
+        impl HubEventSettings {
+            /// `None` without a hub to follow
+            pub fn from_config(config: &Config) -> Option<Self> {
+                let hubble_url = config.hubble_url.as_ref()?;
+                Some(HubEventSettings {
+                    events_url: format!("{}:2281/v1/events", hubble_url),
+                    poll_interval: config.hub_events_poll,
+                })
+            }
+        }
+
+        pub fn event_id_at(timestamp_ms: u64) -> u64 {
+            timestamp_ms.saturating_sub(FARCASTER_EPOCH_MILLIS as u64) << SEQUENCE_BITS
+        }
+
+        pub async fn fetch_events(client: &Client, events_url: &str, from_event_id: u64) -> Result<HubEventsResponse, Error> {
+            let response = client
+                .get(events_url)
+                .query(&[("from_event_id", from_event_id)])
+                .send()
+                .await?;
+            if !response.status().is_success() {
+                return Err(anyhow!("hub returned {} for events", response.status()));
+            }
+            Ok(response.json::<HubEventsResponse>().await?)
+        }
+
+        /// casts out of a page of events posted to one of `channels` (parent url -> channel id)
+        pub fn channel_casts(page: &HubEventsResponse, channels: &HashMap<String, String>) -> Vec<ChannelCast> {
+            page.events
+                .iter()
+                .filter_map(|event| {
+                    let cast = event.merged_cast()?;
+                    let parent_url = cast.data.castAddBody.as_ref()?.parentUrl.as_ref()?;
+                    let channel_id = channels.get(parent_url)?.clone();
+                    Some(ChannelCast { channel_id, cast })
+                })
+                .collect()
+        }
+
+        pub fn spawn_hub_event_stream(pool: DbPool, feed: CastFeed, settings: HubEventSettings) -> JoinHandle<()> {
+            tokio::spawn(async move {
+                crate::log_info!("following hub events at {}", settings.events_url);
+                let client = Client::new();
+                // start from now rather than replaying everything the hub still holds
+                let mut cursor = event_id_at(chrono::Utc::now().timestamp_millis() as u64);
+                loop {
+                    match poll_events(&client, &pool, &feed, &settings.events_url, cursor).await {
+                        Ok((next_cursor, caught_up)) => {
+                            cursor = next_cursor;
+                            if caught_up {
+                                tokio::time::sleep(settings.poll_interval).await;
+                            }
+                        }
+                        Err(e) => {
+                            crate::log_warn!("failed to poll hub events: {}", e);
+                            tokio::time::sleep(settings.poll_interval).await;
+                        }
+                    }
+                }
+            })
+        }
+
+        async fn poll_events(
+            client: &Client,
+            pool: &DbPool,
+            feed: &CastFeed,
+            events_url: &str,
+            cursor: u64,
+        ) -> Result<(u64, bool), Error> {
+            let page = fetch_events(client, events_url, cursor).await?;
+            let next_cursor = page
+                .next_page_event_id
+                .or_else(|| page.events.iter().map(|event| event.id + 1).max())
+                .unwrap_or(cursor)
+                .max(cursor);
+
+            if page.events.is_empty() {
+                return Ok((next_cursor, true));
+            }
+
+            let channels: HashMap<String, String> = interact(pool, |conn| followed_channels::table.load::<FollowedChannel>(conn))
+                .await?
+                .into_iter()
+                .map(|channel| (channel.url, channel.channel_id))
+                .collect();
+
+            let casts = channel_casts(&page, &channels);
+            if !casts.is_empty() {
+                let new_casts = casts
+                    .iter()
+                    .map(|channel_cast| NewIndexedCast::from_cast(&channel_cast.cast, Some(channel_cast.channel_id.clone())))
+                    .collect::<Result<Vec<_>, _>>()?;
+                interact(pool, move |conn| upsert_casts(conn, &new_casts)).await?;
+
+                crate::log_debug!("{} new casts from hub events", casts.len());
+                for cast in casts {
+                    feed.publish(cast);
+                }
+            }
+
+            Ok((next_cursor, false))
+        }
+
+        #[derive(Deserialize)]
+        pub struct CastStreamParams {
+            pub channel: String,
+        }
+
+        pub async fn stream_channel_casts(
+            State(feed): State<CastFeed>,
+            Query(params): Query<CastStreamParams>,
+        ) -> Sse<SseStream> {
+            let (tx, rx) = mpsc::channel(16);
+            let mut casts = feed.subscribe();
+
+            tokio::spawn(async move {
+                loop {
+                    tokio::select! {
+                        // the browser went away
+                        _ = tx.closed() => break,
+                        received = casts.recv() => match received {
+                            Ok(channel_cast) if channel_cast.channel_id == params.channel => {
+                                let event = Event::default()
+                                    .json_data(&channel_cast.cast)
+                                    .map_err(|e| anyhow!("failed to serialize cast: {}", e));
+                                if tx.send(event).await.is_err() {
+                                    break;
+                                }
+                            }
+                            Ok(_) => {}
+                            Err(RecvError::Lagged(skipped)) => {
+                                crate::log_warn!("cast stream for /{} fell behind by {} casts", params.channel, skipped);
+                            }
+                            Err(RecvError::Closed) => break,
+                        },
+                    }
+                }
+            });
+
+            Sse::new(SseStream { receiver: rx }).keep_alive(KeepAlive::default())
+        }
+
+        #[cfg(test)]
+        mod tests {
+            use super::*;
+            use axum::routing::get;
+            use axum::{Json, Router};
+            use serde_json::{json, Value};
+
+            const FOLLOWED_URL: &str = "https://warpcast.com/~/channel/networktimes";
+
+            fn cast_add(id: u64, hash: &str, parent_url: &str) -> Value {
+                json!({
+                    "type": "HUB_EVENT_TYPE_MERGE_MESSAGE",
+                    "id": id,
+                    "mergeMessageBody": {
+                        "message": {
+                            "data": {
+                                "type": "MESSAGE_TYPE_CAST_ADD",
+                                "fid": 3,
+                                "timestamp": 110000000,
+                                "network": "FARCASTER_NETWORK_MAINNET",
+                                "castAddBody": {
+                                    "embedsDeprecated": [],
+                                    "mentions": [],
+                                    "parentUrl": parent_url,
+                                    "text": "gm",
+                                    "mentionsPositions": [],
+                                    "embeds": []
+                                }
+                            },
+                            "hash": hash
+                        },
+                        "deletedMessages": []
+                    }
+                })
+            }
+
+            fn reaction_add(id: u64) -> Value {
+                json!({
+                    "type": "HUB_EVENT_TYPE_MERGE_MESSAGE",
+                    "id": id,
+                    "mergeMessageBody": {
+                        "message": {
+                            "data": {
+                                "type": "MESSAGE_TYPE_REACTION_ADD",
+                                "fid": 3,
+                                "timestamp": 110000000,
+                                "network": "FARCASTER_NETWORK_MAINNET",
+                                "reactionBody": { "type": "REACTION_TYPE_LIKE", "targetCastId": { "fid": 2, "hash": "0xaa" } }
+                            },
+                            "hash": "0xbb"
+                        },
+                        "deletedMessages": []
+                    }
+                })
+            }
+
+            fn followed() -> HashMap<String, String> {
+                HashMap::from([(FOLLOWED_URL.to_string(), "networktimes".to_string())])
+            }
+
+            // a stand-in for hubble's /v1/events that only has events after id 100
+            async fn mock_hub() -> String {
+                let app = Router::new().route(
+                    "/v1/events",
+                    get(|Query(params): Query<HashMap<String, u64>>| async move {
+                        if params.get("from_event_id").copied().unwrap_or(0) > 100 {
+                            return Json(json!({ "events": [], "nextPageEventId": 104 }));
+                        }
+                        Json(json!({
+                            "events": [
+                                cast_add(101, "0x01", FOLLOWED_URL),
+                                reaction_add(102),
+                                cast_add(103, "0x03", "https://warpcast.com/~/channel/elsewhere"),
+                            ],
+                            "nextPageEventId": 104
+                        }))
+                    }),
+                );
+                let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+                let addr = listener.local_addr().unwrap();
+                tokio::spawn(async move { axum::serve(listener, app).await.unwrap() });
+                format!("http://{}/v1/events", addr)
+            }
+
+            #[test]
+            fn event_ids_start_at_the_farcaster_epoch() {
+                assert_eq!(event_id_at(FARCASTER_EPOCH_MILLIS as u64), 0);
+                assert_eq!(event_id_at(FARCASTER_EPOCH_MILLIS as u64 + 1), 1 << SEQUENCE_BITS);
+                assert_eq!(event_id_at(0), 0);
+            }
+
+            #[tokio::test]
+            async fn picks_followed_channel_casts_out_of_hub_events() {
+                let events_url = mock_hub().await;
+                let client = Client::new();
+
+                let page = fetch_events(&client, &events_url, 100).await.unwrap();
+                assert_eq!(page.events.len(), 3);
+                assert_eq!(page.next_page_event_id, Some(104));
+
+                let casts = channel_casts(&page, &followed());
+                assert_eq!(casts.len(), 1);
+                assert_eq!(casts[0].channel_id, "networktimes");
+                assert_eq!(casts[0].cast.hash, "0x01");
+
+                let caught_up = fetch_events(&client, &events_url, 104).await.unwrap();
+                assert!(caught_up.events.is_empty());
+            }
+
+            #[tokio::test]
+            async fn streams_only_the_requested_channel() {
+                let feed = CastFeed::new(16);
+                let app = Router::new()
+                    .route("/api/cast_stream", get(stream_channel_casts))
+                    .with_state(feed.clone());
+                let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+                let addr = listener.local_addr().unwrap();
+                tokio::spawn(async move { axum::serve(listener, app).await.unwrap() });
+
+                let mut response = Client::new()
+                    .get(format!("http://{}/api/cast_stream?channel=networktimes", addr))
+                    .send()
+                    .await
+                    .unwrap();
+
+                let page: HubEventsResponse = serde_json::from_value(json!({
+                    "events": [cast_add(1, "0x0e", FOLLOWED_URL), cast_add(2, "0x0f", FOLLOWED_URL)]
+                }))
+                .unwrap();
+                let mut casts = channel_casts(&page, &followed()).into_iter();
+                let mut elsewhere = casts.next().unwrap();
+                elsewhere.channel_id = "elsewhere".to_string();
+                feed.publish(elsewhere);
+                feed.publish(casts.next().unwrap());
+
+                let mut body = String::new();
+                while !body.contains("\n\n") {
+                    let chunk = response.chunk().await.unwrap().expect("stream ended early");
+                    body.push_str(&String::from_utf8_lossy(&chunk));
+                }
+                let data = body.lines().find_map(|line| line.strip_prefix("data:")).unwrap();
+                let cast: Cast = serde_json::from_str(data.trim()).unwrap();
+                assert_eq!(cast.hash, "0x0f");
+            }
+        }
+    }
+}
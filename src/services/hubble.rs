@@ -2,16 +2,16 @@ use cfg_if::cfg_if;
 cfg_if! {
     if #[cfg(feature = "ssr")] {
         use axum::{
-            extract::{Path, Query},
-            http::StatusCode,
+            extract::{Path, Query, State},
             response::Json,
         };
-        use reqwest::Client;
+        use reqwest::{Client, StatusCode};
         use serde::Deserialize;
         use serde_json::Value;
-        use std::collections::HashMap;
-        use std::env;
+        use std::sync::Arc;
         use tracing::log::info;
+        use crate::config::Config;
+        use crate::error_template::AppError;
         
         #[derive(Deserialize)]
         pub struct UserDataParams {
@@ -19,6 +19,27 @@ cfg_if! {
             pub user_data_type: Option<String>,
         }
         
+        #[derive(Deserialize, Default)]
+        pub struct PageParams {
+            pub page_size: Option<u32>,
+            pub page_token: Option<String>,
+            pub reverse: Option<bool>,
+        }
+
+        impl PageParams {
+            fn push_to(&self, url: &mut String) {
+                if let Some(page_size) = self.page_size {
+                    url.push_str(&format!("&pageSize={}", page_size));
+                }
+                if let Some(ref page_token) = self.page_token {
+                    url.push_str(&format!("&pageToken={}", urlencoding::encode(page_token)));
+                }
+                if let Some(reverse) = self.reverse {
+                    url.push_str(&format!("&reverse={}", reverse));
+                }
+            }
+        }
+
         #[derive(Deserialize)]
         pub struct ReactionsByCastParams {
             pub target_fid: u64,
@@ -26,14 +47,14 @@ cfg_if! {
             pub reaction_type: Option<String>,
         }
         
-        pub async fn get_username_proofs_by_fid(Path(fid): Path<u64>) -> Result<Json<Value>, StatusCode> {
-            let hubble_url = env::var("HUBBLE_URL").map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+        pub async fn get_username_proofs_by_fid(State(config): State<Arc<Config>>, Path(fid): Path<u64>) -> Result<Json<Value>, AppError> {
+            let hubble_url = hubble_url(&config)?;
             let url = format!("{}:2281/v1/userNameProofsByFid?fid={}", hubble_url, fid);
             fetch_and_respond(url).await
         }
         
-        pub async fn get_user_data_by_fid(Query(params): Query<UserDataParams>) -> Result<Json<Value>, StatusCode> {
-            let hubble_url = env::var("HUBBLE_URL").map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+        pub async fn get_user_data_by_fid(State(config): State<Arc<Config>>, Query(params): Query<UserDataParams>) -> Result<Json<Value>, AppError> {
+            let hubble_url = hubble_url(&config)?;
             let mut url = format!("{}/userDataByFid?fid={}", hubble_url, params.fid);
             if let Some(ref data_type) = params.user_data_type {
                 url.push_str(&format!("&user_data_type={}", data_type));
@@ -43,52 +64,58 @@ cfg_if! {
             fetch_and_respond(url).await
         }
         
-        pub async fn get_cast_by_id(Path((fid, hash)): Path<(u64, String)>) -> Result<Json<Value>, StatusCode> {
-            let hubble_url = env::var("HUBBLE_URL").map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+        pub async fn get_cast_by_id(State(config): State<Arc<Config>>, Path((fid, hash)): Path<(u64, String)>) -> Result<Json<Value>, AppError> {
+            let hubble_url = hubble_url(&config)?;
             let url = format!("{}:2281/v1/castById?fid={}&hash={}", hubble_url, fid, hash);
             fetch_and_respond(url).await
         }
         
-        pub async fn get_casts_by_fid(Path(fid): Path<u64>) -> Result<Json<Value>, StatusCode> {
-            let hubble_url = env::var("HUBBLE_URL").map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
-            let url = format!("{}:2281/v1/castsByFid?fid={}", hubble_url, fid);
+        pub async fn get_casts_by_fid(State(config): State<Arc<Config>>, Path(fid): Path<u64>, Query(page): Query<PageParams>) -> Result<Json<Value>, AppError> {
+            let hubble_url = hubble_url(&config)?;
+            let mut url = format!("{}:2281/v1/castsByFid?fid={}", hubble_url, fid);
+            page.push_to(&mut url);
             fetch_and_respond(url).await
         }
         
-        pub async fn get_channels() -> Result<Json<Value>, StatusCode> {
-            let warpcast_url = env::var("WARPCAST_URL").map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+        pub async fn get_channels(State(config): State<Arc<Config>>) -> Result<Json<Value>, AppError> {
+            let warpcast_url = config
+                .warpcast_url
+                .as_deref()
+                .ok_or_else(|| AppError::Upstream("WARPCAST_URL isn't set, the channel catalog is off".to_string()))?;
             let url = format!("{}all-channels", warpcast_url);
             fetch_and_respond(url).await
         }
         
         pub async fn get_casts_by_parent(
+            State(config): State<Arc<Config>>,
             Path(encoded_url): Path<String>,
-            Query(query): Query<HashMap<String, u64>>,
-        ) -> Result<Json<Value>, StatusCode> {
+            Query(page): Query<PageParams>,
+        ) -> Result<Json<Value>, AppError> {
             info!("Fetching Casts by Channel");
-            let hubble_url = env::var("HUBBLE_URL").map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
-            let page = query.get("page").cloned().unwrap_or(1);
-            let limit = query.get("limit").cloned().unwrap_or(40);
-            let _offset = (page - 1) * limit;
+            let hubble_url = hubble_url(&config)?;
 
-            let url = format!(
-                "{}/castsByChannel/{}",
-                hubble_url, encoded_url 
-            );
+            let mut url = format!("{}/castsByChannel/{}", hubble_url, encoded_url);
+            let mut params = String::new();
+            page.push_to(&mut params);
+            if let Some(params) = params.strip_prefix('&') {
+                url.push('?');
+                url.push_str(params);
+            }
 
             info!("Final URL: {}", url);
         
             fetch_and_respond(url).await
         }
         
-        pub async fn get_casts_by_mention(Path(fid): Path<u64>) -> Result<Json<Value>, StatusCode> {
-            let hubble_url = env::var("HUBBLE_URL").map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
-            let url = format!("{}:2281/v1/castsByMention?fid={}", hubble_url, fid);
+        pub async fn get_casts_by_mention(State(config): State<Arc<Config>>, Path(fid): Path<u64>, Query(page): Query<PageParams>) -> Result<Json<Value>, AppError> {
+            let hubble_url = hubble_url(&config)?;
+            let mut url = format!("{}:2281/v1/castsByMention?fid={}", hubble_url, fid);
+            page.push_to(&mut url);
             fetch_and_respond(url).await
         }
         
-        pub async fn get_reactions_by_cast(Query(params): Query<ReactionsByCastParams>) -> Result<Json<Value>, StatusCode> {
-            let hubble_url = env::var("HUBBLE_URL").map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+        pub async fn get_reactions_by_cast(State(config): State<Arc<Config>>, Query(params): Query<ReactionsByCastParams>) -> Result<Json<Value>, AppError> {
+            let hubble_url = hubble_url(&config)?;
             let mut url = format!("{}:2281/v1/reactionsByCast?target_fid={}&target_hash={}",
                                   hubble_url, params.target_fid, params.target_hash);
         
@@ -99,16 +126,46 @@ cfg_if! {
             fetch_and_respond(url).await
         }
         
-        async fn fetch_and_respond(url: String) -> Result<Json<Value>, StatusCode> {
+        fn hubble_url(config: &Config) -> Result<&str, AppError> {
+            config
+                .hubble_url
+                .as_deref()
+                .ok_or_else(|| AppError::Upstream("HUBBLE_URL isn't set, hub lookups are off".to_string()))
+        }
+
+        // the path is enough to tell requests apart without the hub's address in every line
+        #[tracing::instrument(
+            name = "hub_request",
+            skip_all,
+            fields(path = %reqwest::Url::parse(&url).map(|url| url.path().to_string()).unwrap_or_default(), status = tracing::field::Empty),
+        )]
+        async fn fetch_and_respond(url: String) -> Result<Json<Value>, AppError> {
             let client = Client::new();
-            match client.get(&url).send().await {
-                Ok(response) if response.status().is_success() => {
-                    match response.json::<Value>().await {
-                        Ok(json) => Ok(Json(json)),
-                        Err(_) => Err(StatusCode::INTERNAL_SERVER_ERROR),
-                    }
-                }
-                _ => Err(StatusCode::INTERNAL_SERVER_ERROR),
+            let response = client
+                .get(&url)
+                .send()
+                .await
+                .map_err(|e| AppError::Upstream(format!("request failed: {}", e)))?;
+            tracing::Span::current().record("status", response.status().as_u16());
+            if response.status() == StatusCode::NOT_FOUND {
+                return Err(AppError::NotFound("hub resource".to_string()));
+            }
+            if !response.status().is_success() {
+                return Err(AppError::Upstream(format!("hub returned {}", response.status())));
+            }
+            response
+                .json::<Value>()
+                .await
+                .map(Json)
+                .map_err(|e| AppError::Upstream(format!("unreadable response: {}", e)))
+        }
+
+        /// `Ok(None)` for things the hub doesn't have, so caches can remember that
+        pub fn found<T>(result: Result<T, AppError>) -> Result<Option<T>, AppError> {
+            match result {
+                Ok(value) => Ok(Some(value)),
+                Err(AppError::NotFound(_)) => Ok(None),
+                Err(e) => Err(e),
             }
         }
 }}
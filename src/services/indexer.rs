@@ -0,0 +1,338 @@
+use cfg_if::cfg_if;
+cfg_if! {
+    if #[cfg(feature = "ssr")] {
+        use anyhow::{anyhow, Error};
+        use axum::extract::{Path, Query, State};
+        use chrono::Utc;
+        use diesel::prelude::*;
+        use std::collections::{HashMap, HashSet};
+        use std::future::Future;
+        use std::sync::Arc;
+        use std::time::Duration;
+        use tokio::task::JoinHandle;
+
+        use crate::config::Config;
+        use crate::database::casts::{
+            get_casts_by_channel, get_stale_user_data_fids, newest_cast_timestamp, upsert_casts, upsert_reactions, upsert_user_data,
+        };
+        use crate::database::db::{interact, DbPool};
+        use crate::error_template::AppError;
+        use crate::models::channels::FollowedChannel;
+        use crate::models::farcaster::{Cast, CastResponse, ReactionsResponse, UserDataListResponse};
+        use crate::models::indexed::{IndexedReaction, NewIndexedCast, NewIndexedUserData};
+        use crate::schema::followed_channels;
+        use crate::services::cache::Caches;
+        use crate::services::hubble::{
+            found, get_casts_by_parent, get_reactions_by_cast, get_user_data_by_fid, PageParams, ReactionsByCastParams,
+            UserDataParams,
+        };
+
+        #[derive(Clone, Debug)]
+        pub struct IndexerSettings {
+            /// how long to wait between passes over the followed channels
+            pub interval: Duration,
+            /// how many of the newest casts per channel get their reactions refreshed
+            pub reaction_window: usize,
+            /// how old indexed user data can get before it is fetched again
+            pub user_data_max_age: chrono::Duration,
+            /// how many casts to ask the hub for at a time
+            pub page_size: u32,
+            /// how many pages one pass walks back through a channel before giving up on the rest
+            pub max_pages: usize,
+        }
+
+        impl Default for IndexerSettings {
+            fn default() -> Self {
+                IndexerSettings {
+                    interval: Duration::from_secs(60),
+                    reaction_window: 20,
+                    user_data_max_age: chrono::Duration::hours(6),
+                    page_size: 100,
+                    max_pages: 10,
+                }
+            }
+        }
+
+        impl IndexerSettings {
+            pub fn from_config(config: &Config) -> Self {
+                IndexerSettings { interval: config.indexer_interval, ..IndexerSettings::default() }
+            }
+        }
+
+        #[derive(Debug, Default)]
+        struct IndexStats {
+            casts: usize,
+            reactions: usize,
+            users: usize,
+        }
+
+        /// the newest cast timestamp indexed per channel, so each pass only walks
+        /// back as far as the last one got
+        type HighWaterMarks = HashMap<String, u64>;
+
+        pub fn spawn_indexer(pool: DbPool, config: Arc<Config>, caches: Caches, settings: IndexerSettings) -> JoinHandle<()> {
+            tokio::spawn(async move {
+                crate::log_info!("cast indexer running every {:?}", settings.interval);
+                let mut marks = HighWaterMarks::new();
+                let mut ticker = tokio::time::interval(settings.interval);
+                ticker.set_missed_tick_behavior(tokio::time::MissedTickBehavior::Delay);
+                loop {
+                    ticker.tick().await;
+                    if let Err(e) = index_followed_channels(&pool, &config, &caches, &settings, &mut marks).await {
+                        crate::log_error!("cast indexer pass failed: {}", e);
+                    }
+                }
+            })
+        }
+
+        async fn index_followed_channels(
+            pool: &DbPool,
+            config: &Arc<Config>,
+            caches: &Caches,
+            settings: &IndexerSettings,
+            marks: &mut HighWaterMarks,
+        ) -> Result<(), Error> {
+            let channels = interact(pool, |conn| followed_channels::table.load::<FollowedChannel>(conn)).await?;
+
+            for channel in channels {
+                match index_channel(pool, config, caches, &channel, settings, marks).await {
+                    Ok(stats) => {
+                        crate::log_debug!(
+                            "indexed /{}: {} new casts, {} new reactions, {} users refreshed",
+                            channel.channel_id, stats.casts, stats.reactions, stats.users
+                        );
+                    }
+                    // one bad channel shouldn't stop the rest from being indexed
+                    Err(e) => {
+                        crate::log_warn!("failed to index /{}: {}", channel.channel_id, e);
+                    }
+                }
+            }
+
+            Ok(())
+        }
+
+        async fn index_channel(
+            pool: &DbPool,
+            config: &Arc<Config>,
+            caches: &Caches,
+            channel: &FollowedChannel,
+            settings: &IndexerSettings,
+            marks: &mut HighWaterMarks,
+        ) -> Result<IndexStats, Error> {
+            let mut stats = IndexStats::default();
+
+            let since = match marks.get(&channel.channel_id) {
+                Some(mark) => Some(*mark),
+                // first pass since startup, so carry on from whatever an earlier run stored
+                None => {
+                    let channel_id = channel.channel_id.clone();
+                    interact(pool, move |conn| newest_cast_timestamp(conn, &channel_id))
+                        .await?
+                        .map(|timestamp| timestamp as u64)
+                }
+            };
+
+            let encoded_url = urlencoding::encode(&channel.url).to_string();
+            let (casts, complete) = fetch_casts_since(since, settings.max_pages, |page_token| {
+                let params = PageParams {
+                    page_size: Some(settings.page_size),
+                    page_token,
+                    reverse: Some(true),
+                };
+                let json = get_casts_by_parent(State(config.clone()), Path(encoded_url.clone()), Query(params));
+                async move {
+                    let json = json.await.map_err(|status| anyhow!("hub returned {} for casts", status))?;
+                    Ok(serde_json::from_value::<CastResponse>(json.0)?)
+                }
+            })
+            .await?;
+            if !complete && since.is_some() {
+                crate::log_warn!(
+                    "/{} has more than {} pages of new casts, older ones past that weren't indexed",
+                    channel.channel_id, settings.max_pages
+                );
+            }
+
+            let new_casts = casts
+                .iter()
+                .map(|cast| NewIndexedCast::from_cast(cast, Some(channel.channel_id.clone())))
+                .collect::<Result<Vec<_>, _>>()?;
+            stats.casts = interact(pool, move |conn| upsert_casts(conn, &new_casts)).await?;
+            if let Some(newest) = casts.iter().map(|cast| cast.data.timestamp).chain(since).max() {
+                marks.insert(channel.channel_id.clone(), newest);
+            }
+
+            // reactions keep changing after a cast is stored, so the newest ones are refreshed every pass
+            let recent = {
+                let channel_id = channel.channel_id.clone();
+                let window = settings.reaction_window as i64;
+                interact(pool, move |conn| get_casts_by_channel(conn, &channel_id, 0, window))
+                    .await?
+                    .into_iter()
+                    .map(Cast::try_from)
+                    .collect::<Result<Vec<_>, _>>()?
+            };
+
+            // reactions come from the cache when they're fresh enough, so the hub isn't asked every pass
+            let mut new_reactions = Vec::new();
+            for cast in &recent {
+                let reactions = caches
+                    .reactions
+                    .get_or_load(&format!("{}:{}", cast.data.fid, cast.hash), || async {
+                        let params = ReactionsByCastParams {
+                            target_fid: cast.data.fid,
+                            target_hash: cast.hash.clone(),
+                            reaction_type: None,
+                        };
+                        found(get_reactions_by_cast(State(config.clone()), Query(params)).await)?
+                            .map(|json| serde_json::from_value::<ReactionsResponse>(json.0))
+                            .transpose()
+                            .map_err(|e| AppError::Upstream(format!("unreadable reactions: {}", e)))
+                    })
+                    .await;
+                match reactions {
+                    Ok(reactions) => {
+                        let reactions = reactions.map(|response| response.messages).unwrap_or_default();
+                        new_reactions.extend(reactions.iter().filter_map(IndexedReaction::from_reaction));
+                    }
+                    Err(e) => {
+                        crate::log_warn!("failed to load reactions on {}: {}", cast.hash, e);
+                    }
+                }
+            }
+            stats.reactions = interact(pool, move |conn| upsert_reactions(conn, &new_reactions)).await?;
+
+            let authors: Vec<i64> = casts
+                .iter()
+                .chain(&recent)
+                .map(|cast| cast.data.fid as i64)
+                .collect::<HashSet<_>>()
+                .into_iter()
+                .collect();
+            let since = Utc::now().naive_utc() - settings.user_data_max_age;
+            let stale = interact(pool, move |conn| get_stale_user_data_fids(conn, &authors, since)).await?;
+
+            let mut new_user_data = Vec::new();
+            for fid in stale {
+                let params = UserDataParams {
+                    fid: fid as u64,
+                    user_data_type: None,
+                };
+                match get_user_data_by_fid(State(config.clone()), Query(params)).await {
+                    Ok(json) => {
+                        let messages = serde_json::from_value::<UserDataListResponse>(json.0)?.messages;
+                        new_user_data.extend(messages.iter().map(NewIndexedUserData::from));
+                        stats.users += 1;
+                    }
+                    Err(status) => {
+                        crate::log_warn!("hub returned {} for user data of fid {}", status, fid);
+                    }
+                }
+            }
+            interact(pool, move |conn| upsert_user_data(conn, &new_user_data)).await?;
+
+            Ok(stats)
+        }
+
+        /// walks a channel newest first, a page at a time, until it gets past
+        /// `since` or `max_pages` run out; the flag says whether it got past
+        async fn fetch_casts_since<F, Fut>(since: Option<u64>, max_pages: usize, mut fetch_page: F) -> Result<(Vec<Cast>, bool), Error>
+        where
+            F: FnMut(Option<String>) -> Fut,
+            Fut: Future<Output = Result<CastResponse, Error>>,
+        {
+            let mut casts = Vec::new();
+            let mut page_token = None;
+            for _ in 0..max_pages {
+                let page = fetch_page(page_token.take()).await?;
+                for cast in page.messages {
+                    // casts sharing the mark's second may have landed after it was taken,
+                    // so only older ones count as stored; upserting the rest again is a no-op
+                    if since.is_some_and(|since| cast.data.timestamp < since) {
+                        return Ok((casts, true));
+                    }
+                    casts.push(cast);
+                }
+                match page.next_page_token.filter(|token| !token.is_empty()) {
+                    Some(token) => page_token = Some(token),
+                    None => return Ok((casts, true)),
+                }
+            }
+            Ok((casts, false))
+        }
+
+        #[cfg(test)]
+        mod tests {
+            use super::*;
+            use serde_json::json;
+            use std::sync::Mutex;
+
+            fn cast(timestamp: u64) -> Cast {
+                serde_json::from_value(json!({
+                    "data": {
+                        "type": "MESSAGE_TYPE_CAST_ADD",
+                        "fid": 3,
+                        "timestamp": timestamp,
+                        "network": "FARCASTER_NETWORK_MAINNET",
+                        "castAddBody": {
+                            "embedsDeprecated": [],
+                            "mentions": [],
+                            "parentUrl": "https://warpcast.com/~/channel/networktimes",
+                            "text": "gm",
+                            "mentionsPositions": [],
+                            "embeds": []
+                        }
+                    },
+                    "hash": format!("0x{:02x}", timestamp)
+                }))
+                .unwrap()
+            }
+
+            /// a channel of casts at timestamps 10 down to 1, two to a page; records
+            /// the tokens it was asked for
+            fn channel(requested: &Mutex<Vec<Option<String>>>) -> impl FnMut(Option<String>) -> std::future::Ready<Result<CastResponse, Error>> + '_ {
+                move |page_token: Option<String>| {
+                    requested.lock().unwrap().push(page_token.clone());
+                    let start = page_token.map(|token| token.parse::<u64>().unwrap()).unwrap_or(0);
+                    let messages = (start..start + 2).map(|i| cast(10 - i)).collect();
+                    let next_page_token = (start + 2 < 10).then(|| (start + 2).to_string());
+                    std::future::ready(Ok(CastResponse { messages, next_page_token }))
+                }
+            }
+
+            fn timestamps(casts: &[Cast]) -> Vec<u64> {
+                casts.iter().map(|cast| cast.data.timestamp).collect()
+            }
+
+            #[tokio::test]
+            async fn pages_back_to_the_high_water_mark() {
+                let requested = Mutex::new(Vec::new());
+                let (casts, complete) = fetch_casts_since(Some(6), 10, channel(&requested)).await.unwrap();
+
+                assert!(complete);
+                assert_eq!(timestamps(&casts), vec![10, 9, 8, 7, 6]);
+                assert_eq!(*requested.lock().unwrap(), vec![None, Some("2".to_string()), Some("4".to_string())]);
+            }
+
+            #[tokio::test]
+            async fn walks_the_whole_channel_without_a_mark() {
+                let requested = Mutex::new(Vec::new());
+                let (casts, complete) = fetch_casts_since(None, 10, channel(&requested)).await.unwrap();
+
+                assert!(complete);
+                assert_eq!(timestamps(&casts), (1..=10).rev().collect::<Vec<_>>());
+                assert_eq!(requested.lock().unwrap().len(), 5);
+            }
+
+            #[tokio::test]
+            async fn stops_after_max_pages() {
+                let requested = Mutex::new(Vec::new());
+                let (casts, complete) = fetch_casts_since(Some(1), 2, channel(&requested)).await.unwrap();
+
+                assert!(!complete);
+                assert_eq!(timestamps(&casts), vec![10, 9, 8, 7]);
+            }
+        }
+    }
+}
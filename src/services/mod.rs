@@ -1,2 +1,10 @@
+pub mod auth;
+pub mod cache;
+pub mod cast_text;
+pub mod hub_events;
 pub mod hubble;
-pub mod redis;
+pub mod indexer;
+pub mod opengraph;
+pub mod rate_limit;
+pub mod signer;
+pub mod timeline;
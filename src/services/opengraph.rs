@@ -0,0 +1,330 @@
+use cfg_if::cfg_if;
+cfg_if! {
+    if #[cfg(feature = "ssr")] {
+        use anyhow::{anyhow, Error};
+        use regex::Regex;
+        use reqwest::header::{CONTENT_TYPE, LOCATION};
+        use reqwest::redirect::Policy;
+        use reqwest::{Client, StatusCode};
+        use std::net::{IpAddr, Ipv4Addr, SocketAddr};
+        use std::time::Duration;
+        use tokio::net::lookup_host;
+        use url::{Host, Url};
+
+        use crate::models::farcaster::{EmbedKind, LinkPreview};
+
+        // enough to get past the <head> of any sane page
+        const MAX_HTML_BYTES: usize = 256 * 1024;
+        const MAX_REDIRECTS: usize = 5;
+
+        /// `Ok(None)` when the page is gone
+        pub async fn fetch_link_preview(url: &str) -> Result<Option<LinkPreview>, Error> {
+            fetch_preview(url, is_public_ip).await
+        }
+
+        // `allowed` is only swapped out by tests, which have to fetch from loopback
+        async fn fetch_preview(url: &str, allowed: fn(IpAddr) -> bool) -> Result<Option<LinkPreview>, Error> {
+            let parsed = Url::parse(url).map_err(|e| anyhow!("invalid url {}: {}", url, e))?;
+
+            // redirects are followed by hand so every hop goes through the same checks
+            let mut hop = parsed.clone();
+            let mut redirects = 0;
+            let mut response = loop {
+                let response = pinned_client(&hop, allowed).await?.get(hop.clone()).send().await?;
+                if !response.status().is_redirection() {
+                    break response;
+                }
+                if redirects == MAX_REDIRECTS {
+                    return Err(anyhow!("{} redirected more than {} times", url, MAX_REDIRECTS));
+                }
+                let location = response
+                    .headers()
+                    .get(LOCATION)
+                    .and_then(|value| value.to_str().ok())
+                    .ok_or_else(|| anyhow!("{} redirected without a location", hop))?;
+                hop = hop.join(location).map_err(|e| anyhow!("{} redirected to an invalid url: {}", hop, e))?;
+                redirects += 1;
+            };
+
+            if matches!(response.status(), StatusCode::NOT_FOUND | StatusCode::GONE) {
+                return Ok(None);
+            }
+            if !response.status().is_success() {
+                return Err(anyhow!("{} returned {}", url, response.status()));
+            }
+
+            let content_type = response
+                .headers()
+                .get(CONTENT_TYPE)
+                .and_then(|value| value.to_str().ok())
+                .unwrap_or_default()
+                .to_lowercase();
+
+            match embed_kind(&content_type) {
+                EmbedKind::Link if content_type.contains("html") => {
+                    let mut body = Vec::new();
+                    while let Some(chunk) = response.chunk().await? {
+                        body.extend_from_slice(&chunk);
+                        if body.len() >= MAX_HTML_BYTES {
+                            break;
+                        }
+                    }
+                    let html = String::from_utf8_lossy(&body);
+                    Ok(Some(parse_opengraph(&parsed, &html)))
+                }
+                kind => Ok(Some(LinkPreview::bare(url.to_string(), kind))),
+            }
+        }
+
+        fn embed_kind(content_type: &str) -> EmbedKind {
+            if content_type.starts_with("image/") {
+                EmbedKind::Image
+            } else if content_type.starts_with("video/") || content_type.contains("mpegurl") {
+                EmbedKind::Video
+            } else {
+                EmbedKind::Link
+            }
+        }
+
+        /// A client for one request to `url`, which can only connect to addresses
+        /// that passed `allowed`. A host name is resolved here and the client pinned
+        /// to what came back, so a second lookup can't point it somewhere else.
+        async fn pinned_client(url: &Url, allowed: fn(IpAddr) -> bool) -> Result<Client, Error> {
+            let refused = || anyhow!("refusing to fetch {}", url);
+            if !matches!(url.scheme(), "http" | "https") {
+                return Err(refused());
+            }
+
+            let builder = Client::builder()
+                .timeout(Duration::from_secs(5))
+                .user_agent("thenetworktimes-linkpreview/0.1")
+                .redirect(Policy::none());
+            let builder = match url.host() {
+                Some(Host::Domain(domain)) => {
+                    let port = url.port_or_known_default().unwrap_or(80);
+                    let addrs: Vec<SocketAddr> = lookup_host((domain, port)).await?.collect();
+                    if addrs.is_empty() || !addrs.iter().all(|addr| allowed(addr.ip())) {
+                        return Err(refused());
+                    }
+                    builder.resolve_to_addrs(domain, &addrs)
+                }
+                Some(Host::Ipv4(ip)) if allowed(IpAddr::V4(ip)) => builder,
+                Some(Host::Ipv6(ip)) if allowed(IpAddr::V6(ip)) => builder,
+                _ => return Err(refused()),
+            };
+
+            Ok(builder.build()?)
+        }
+
+        // keeps the preview fetcher from being pointed at our own network
+        fn is_public_ip(ip: IpAddr) -> bool {
+            match ip {
+                IpAddr::V4(ip) => {
+                    let [a, b, c, _] = ip.octets();
+                    !(ip.is_loopback()
+                        || ip.is_private()
+                        || ip.is_link_local()
+                        || ip.is_unspecified()
+                        || ip.is_documentation()
+                        || ip.is_multicast()
+                        // this network, carrier-grade nat, protocol assignments, benchmarking
+                        || a == 0
+                        || (a == 100 && (64..128).contains(&b))
+                        || (a == 192 && b == 0 && c == 0)
+                        || (a == 198 && (b == 18 || b == 19))
+                        // reserved, including broadcast
+                        || a >= 240)
+                }
+                IpAddr::V6(ip) => {
+                    let segments = ip.segments();
+                    // ::ffff:a.b.c.d and 64:ff9b::a.b.c.d reach ipv4 hosts, so they get the ipv4 rules
+                    let [.., a, b, c, d] = ip.octets();
+                    if ip.to_ipv4_mapped().is_some() || segments[..6] == [0x64, 0xff9b, 0, 0, 0, 0] {
+                        return is_public_ip(IpAddr::V4(Ipv4Addr::new(a, b, c, d)));
+                    }
+                    !(ip.is_multicast()
+                        // unspecified, loopback and the deprecated ::a.b.c.d
+                        || segments[..6] == [0; 6]
+                        // unique local fc00::/7, link local fe80::/10, site local fec0::/10
+                        || segments[0] & 0xfe00 == 0xfc00
+                        || segments[0] & 0xffc0 == 0xfe80
+                        || segments[0] & 0xffc0 == 0xfec0
+                        // documentation
+                        || segments[..2] == [0x2001, 0x0db8])
+                }
+            }
+        }
+
+        pub fn parse_opengraph(page_url: &Url, html: &str) -> LinkPreview {
+            let meta_re = Regex::new(r"(?is)<meta\s[^>]*>").unwrap();
+            let attr_re = Regex::new(r#"(?is)([a-z:_-]+)\s*=\s*(?:"([^"]*)"|'([^']*)')"#).unwrap();
+            let title_re = Regex::new(r"(?is)<title[^>]*>(.*?)</title>").unwrap();
+
+            let mut preview = LinkPreview::bare(page_url.to_string(), EmbedKind::Link);
+            let mut fallback_description = None;
+
+            for tag in meta_re.find_iter(html) {
+                let mut key = None;
+                let mut content = None;
+                for cap in attr_re.captures_iter(tag.as_str()) {
+                    let value = cap.get(2).or_else(|| cap.get(3)).map(|m| m.as_str());
+                    match cap[1].to_lowercase().as_str() {
+                        "property" | "name" => key = value.map(str::to_lowercase),
+                        "content" => content = value.map(decode_entities),
+                        _ => {}
+                    }
+                }
+
+                let (Some(key), Some(content)) = (key, content) else { continue };
+                if content.is_empty() {
+                    continue;
+                }
+
+                match key.as_str() {
+                    "og:title" | "twitter:title" => { preview.title.get_or_insert(content); }
+                    "og:description" | "twitter:description" => { preview.description.get_or_insert(content); }
+                    "description" => { fallback_description.get_or_insert(content); }
+                    "og:image" | "og:image:url" | "twitter:image" => {
+                        if preview.image.is_none() {
+                            preview.image = page_url.join(&content).ok().map(|url| url.to_string());
+                        }
+                    }
+                    "og:site_name" => { preview.site_name.get_or_insert(content); }
+                    _ => {}
+                }
+            }
+
+            if preview.title.is_none() {
+                preview.title = title_re
+                    .captures(html)
+                    .map(|cap| decode_entities(cap[1].trim()))
+                    .filter(|title| !title.is_empty());
+            }
+            if preview.description.is_none() {
+                preview.description = fallback_description;
+            }
+            if preview.site_name.is_none() {
+                preview.site_name = page_url.host_str().map(|host| host.trim_start_matches("www.").to_string());
+            }
+
+            preview
+        }
+
+        fn decode_entities(text: &str) -> String {
+            text.replace("&quot;", "\"")
+                .replace("&#39;", "'")
+                .replace("&#x27;", "'")
+                .replace("&apos;", "'")
+                .replace("&lt;", "<")
+                .replace("&gt;", ">")
+                .replace("&nbsp;", " ")
+                .replace("&amp;", "&")
+        }
+
+        #[cfg(test)]
+        mod tests {
+            use super::*;
+            use axum::http::{header, StatusCode};
+            use axum::response::{Html, IntoResponse};
+            use axum::routing::get;
+            use axum::Router;
+
+            fn page() -> Url {
+                Url::parse("https://www.example.com/posts/1").unwrap()
+            }
+
+            #[test]
+            fn open_graph_tags_fill_the_preview() {
+                let html = r#"<html><head>
+                    <title>ignored</title>
+                    <meta property="og:title" content="Issue #4 &amp; more">
+                    <meta content='the &quot;fourth&quot; one' property='og:description'/>
+                    <meta property="og:image" content="/img/cover.png">
+                    <meta property="og:site_name" content="The Network Times">
+                </head></html>"#;
+                let preview = parse_opengraph(&page(), html);
+
+                assert_eq!(preview.url, "https://www.example.com/posts/1");
+                assert_eq!(preview.title.as_deref(), Some("Issue #4 & more"));
+                assert_eq!(preview.description.as_deref(), Some("the \"fourth\" one"));
+                assert_eq!(preview.image.as_deref(), Some("https://www.example.com/img/cover.png"));
+                assert_eq!(preview.site_name.as_deref(), Some("The Network Times"));
+            }
+
+            #[test]
+            fn falls_back_to_the_title_tag_and_plain_description() {
+                let html = r#"<TITLE> gm &lt;3 </TITLE><meta name="description" content="plain"><meta name="og:title" content="">"#;
+                let preview = parse_opengraph(&page(), html);
+
+                assert_eq!(preview.title.as_deref(), Some("gm <3"));
+                assert_eq!(preview.description.as_deref(), Some("plain"));
+                assert_eq!(preview.site_name.as_deref(), Some("example.com"));
+                assert!(preview.image.is_none());
+            }
+
+            #[test]
+            fn the_first_of_each_tag_wins() {
+                let html = r#"<meta property="og:title" content="first"><meta name="twitter:title" content="second">"#;
+                assert_eq!(parse_opengraph(&page(), html).title.as_deref(), Some("first"));
+            }
+
+            #[test]
+            fn internal_addresses_are_not_public() {
+                for ip in [
+                    "127.0.0.1", "10.1.2.3", "172.16.0.1", "192.168.1.1", "169.254.169.254", "0.0.0.0", "100.64.0.1",
+                    "255.255.255.255", "::1", "::", "fc00::1", "fd12:3456::1", "fe80::1", "::ffff:127.0.0.1",
+                    "::ffff:169.254.169.254", "64:ff9b::a00:1", "::127.0.0.1",
+                ] {
+                    assert!(!is_public_ip(ip.parse().unwrap()), "{}", ip);
+                }
+                for ip in ["1.1.1.1", "104.16.0.1", "2606:4700::1111", "::ffff:8.8.8.8"] {
+                    assert!(is_public_ip(ip.parse().unwrap()), "{}", ip);
+                }
+            }
+
+            #[tokio::test]
+            async fn hosts_are_checked_by_what_they_resolve_to() {
+                for url in ["http://localhost:1/", "http://127.0.0.1/", "http://[::ffff:10.0.0.1]/", "file:///etc/passwd", "ftp://1.1.1.1/"] {
+                    let err = pinned_client(&Url::parse(url).unwrap(), is_public_ip).await.unwrap_err();
+                    assert!(err.to_string().starts_with("refusing"), "{}: {}", url, err);
+                }
+                assert!(pinned_client(&Url::parse("https://1.1.1.1/").unwrap(), is_public_ip).await.is_ok());
+            }
+
+            // only the mock server on 127.0.0.1 is allowed, standing in for the public internet
+            fn mock_allowed(ip: IpAddr) -> bool {
+                ip == IpAddr::V4(Ipv4Addr::LOCALHOST)
+            }
+
+            async fn mock_site() -> String {
+                let app = Router::new()
+                    .route("/page", get(|| async { Html(r#"<meta property="og:title" content="landed">"#) }))
+                    .route("/hop", get(|| async { (StatusCode::FOUND, [(header::LOCATION, "/page")]).into_response() }))
+                    .route("/loop", get(|| async { (StatusCode::FOUND, [(header::LOCATION, "/loop")]).into_response() }))
+                    .route(
+                        "/escape",
+                        get(|| async { (StatusCode::FOUND, [(header::LOCATION, "http://127.0.0.2/admin")]).into_response() }),
+                    );
+                let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+                let addr = listener.local_addr().unwrap();
+                tokio::spawn(async move { axum::serve(listener, app).await.unwrap() });
+                format!("http://{}", addr)
+            }
+
+            #[tokio::test]
+            async fn redirects_are_followed_through_the_same_checks() {
+                let site = mock_site().await;
+
+                let preview = fetch_preview(&format!("{}/hop", site), mock_allowed).await.unwrap().unwrap();
+                assert_eq!(preview.title.as_deref(), Some("landed"));
+                assert_eq!(preview.url, format!("{}/hop", site));
+
+                let err = fetch_preview(&format!("{}/escape", site), mock_allowed).await.unwrap_err();
+                assert_eq!(err.to_string(), "refusing to fetch http://127.0.0.2/admin");
+
+                let err = fetch_preview(&format!("{}/loop", site), mock_allowed).await.unwrap_err();
+                assert!(err.to_string().contains("redirected more than"), "{}", err);
+            }
+        }
+    }
+}
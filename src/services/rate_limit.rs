@@ -0,0 +1,315 @@
+use cfg_if::cfg_if;
+
+cfg_if! {
+    if #[cfg(feature = "ssr")] {
+        use axum::extract::{ConnectInfo, Request, State};
+        use axum::middleware::Next;
+        use axum::response::{IntoResponse, Response};
+        use chrono::{DateTime, Days, Utc};
+        use http::HeaderMap;
+        use leptos::server_fn::ServerFn;
+        use serde::{Deserialize, Serialize};
+        use std::fmt;
+        use std::net::{IpAddr, SocketAddr};
+        use std::time::Duration;
+
+        use crate::components::cast_list::GetCastsByChannel;
+        use crate::components::embed::{GetLinkPreview, GetQuotedCast};
+        use crate::components::profile::{GetProfile, GetProfileCasts};
+        use crate::components::threadlist::GenerateThreadTitle;
+        use crate::components::users::GetUsers;
+        use crate::error_template::AppError;
+        use crate::services::auth::session_user;
+        use crate::services::cache::{Cache, CacheStore, Namespace};
+        use crate::state::AppState;
+        use crate::wogging::ReportClientError;
+
+        /// A token bucket: up to `burst` requests back to back, refilled at
+        /// `per_minute` after that.
+        #[derive(Clone, Copy, Debug, PartialEq)]
+        pub struct Policy {
+            pub name: &'static str,
+            pub burst: u32,
+            pub per_minute: u32,
+            /// also held to the daily llm token quota
+            pub llm: bool,
+        }
+
+        impl Policy {
+            fn per_ms(&self) -> f64 {
+                self.per_minute as f64 / 60_000.0
+            }
+
+            /// how long an untouched bucket takes to fill back up, after which it can be forgotten
+            fn refill_time(&self) -> Duration {
+                Duration::from_secs((self.burst as u64 * 60).div_ceil(self.per_minute.max(1) as u64).max(1))
+            }
+        }
+
+        pub const LLM_STREAM: Policy = Policy { name: "llm_stream", burst: 5, per_minute: 6, llm: true };
+        pub const THREAD_TITLES: Policy = Policy { name: "thread_titles", burst: 3, per_minute: 4, llm: true };
+        pub const HUB_PROXY: Policy = Policy { name: "hub_proxy", burst: 60, per_minute: 120, llm: false };
+        /// browsers forwarding their errors; one bad page shouldn't flood the log
+        pub const CLIENT_ERRORS: Policy = Policy { name: "client_errors", burst: 10, per_minute: 20, llm: false };
+
+        /// the routes that pass our upstream hub straight through
+        const HUB_PROXY_PREFIXES: [&str; 8] = [
+            "/api/castsByFid/",
+            "/api/castsByChannel/",
+            "/api/castsByMention/",
+            "/api/castById/",
+            "/api/userDataByFid",
+            "/api/userNameProofsByFid/",
+            "/api/reactionsByCast",
+            "/api/channels",
+        ];
+
+        /// the server functions that go to the hub, or out to the web, on the caller's behalf
+        const HUB_PROXY_FNS: [&str; 6] = [
+            GetCastsByChannel::PATH,
+            GetUsers::PATH,
+            GetProfile::PATH,
+            GetProfileCasts::PATH,
+            GetQuotedCast::PATH,
+            GetLinkPreview::PATH,
+        ];
+
+        pub fn policy_for(path: &str) -> Option<Policy> {
+            if path == "/api/send_message_stream" {
+                Some(LLM_STREAM)
+            } else if path == GenerateThreadTitle::PATH {
+                Some(THREAD_TITLES)
+            } else if path == ReportClientError::PATH {
+                Some(CLIENT_ERRORS)
+            } else if HUB_PROXY_FNS.contains(&path) || HUB_PROXY_PREFIXES.iter().any(|prefix| path.starts_with(prefix)) {
+                Some(HUB_PROXY)
+            } else {
+                None
+            }
+        }
+
+        /// Who a bucket belongs to: the signed in user where there is one, so
+        /// they keep their allowance across networks, otherwise their address.
+        #[derive(Clone, Debug, PartialEq)]
+        pub enum Client {
+            User(u64),
+            Ip(IpAddr),
+            /// no address to go on; everyone like that shares one bucket
+            Unknown,
+        }
+
+        impl fmt::Display for Client {
+            fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+                match self {
+                    Client::User(fid) => write!(f, "fid:{}", fid),
+                    Client::Ip(ip) => write!(f, "ip:{}", ip),
+                    Client::Unknown => write!(f, "unknown"),
+                }
+            }
+        }
+
+        impl Client {
+            pub async fn identify(cache: &CacheStore, headers: &HeaderMap, peer: Option<SocketAddr>, trust_proxy: bool) -> Self {
+                if let Some(user) = session_user(cache, headers).await {
+                    return Client::User(user.fid);
+                }
+                client_ip(headers, peer, trust_proxy).map(Client::Ip).unwrap_or(Client::Unknown)
+            }
+        }
+
+        fn client_ip(headers: &HeaderMap, peer: Option<SocketAddr>, trust_proxy: bool) -> Option<IpAddr> {
+            let forwarded = trust_proxy
+                .then(|| headers.get("x-forwarded-for"))
+                .flatten()
+                .and_then(|value| value.to_str().ok())
+                // the proxy appends the address it saw, so the first one is the client's
+                .and_then(|value| value.split(',').next())
+                .and_then(|ip| ip.trim().parse().ok());
+            forwarded.or(peer.map(|peer| peer.ip()))
+        }
+
+        #[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+        struct Bucket {
+            tokens: f64,
+            updated_ms: i64,
+        }
+
+        /// Spends one token from `bucket`, or says how many seconds until there's one to spend.
+        fn take(bucket: Option<Bucket>, policy: &Policy, now_ms: i64) -> Result<Bucket, u64> {
+            let burst = policy.burst as f64;
+            let tokens = match bucket {
+                Some(bucket) => (bucket.tokens + (now_ms - bucket.updated_ms).max(0) as f64 * policy.per_ms()).min(burst),
+                None => burst,
+            };
+            if tokens >= 1.0 {
+                Ok(Bucket { tokens: tokens - 1.0, updated_ms: now_ms })
+            } else {
+                let wait_ms = (1.0 - tokens) / policy.per_ms();
+                Err(((wait_ms / 1000.0).ceil() as u64).max(1))
+            }
+        }
+
+        /// Charges `client` one request against `policy`. Buckets live in the
+        /// shared cache, read and written back without a lock, so a burst of
+        /// simultaneous requests can slip a few past; that's fine for keeping
+        /// costs sane.
+        pub async fn check(cache: &CacheStore, policy: &Policy, client: &Client) -> Result<(), AppError> {
+            let buckets: Cache<String, Bucket> = Cache::new(
+                cache.clone(),
+                Namespace { name: "ratelimit", version: 1, ttl: policy.refill_time(), missing_ttl: policy.refill_time() },
+            );
+            let key = format!("{}:{}", policy.name, client);
+            let bucket = buckets.get(&key).await.flatten();
+            match take(bucket, policy, Utc::now().timestamp_millis()) {
+                Ok(bucket) => {
+                    buckets.set(&key, Some(&bucket)).await;
+                    Ok(())
+                }
+                Err(retry_after) => {
+                    crate::log_info!("{} is over the {} limit", client, policy.name);
+                    Err(AppError::RateLimited { retry_after })
+                }
+            }
+        }
+
+        fn quota_key(fid: u64, now: DateTime<Utc>) -> String {
+            format!("llm_tokens:{}:{}", fid, now.format("%Y-%m-%d"))
+        }
+
+        fn secs_until_tomorrow(now: DateTime<Utc>) -> u64 {
+            let tomorrow = now
+                .date_naive()
+                .checked_add_days(Days::new(1))
+                .and_then(|day| day.and_hms_opt(0, 0, 0))
+                .map(|midnight| midnight.and_utc());
+            tomorrow.map(|midnight| (midnight - now).num_seconds().max(1) as u64).unwrap_or(1)
+        }
+
+        /// Turns `fid` away once they've used up today's llm tokens.
+        pub async fn check_llm_quota(cache: &CacheStore, daily_tokens: u64, fid: u64) -> Result<(), AppError> {
+            if daily_tokens == 0 {
+                return Ok(());
+            }
+            let now = Utc::now();
+            let used = cache
+                .get(&quota_key(fid, now))
+                .await
+                .and_then(|used| used.parse::<u64>().ok())
+                .unwrap_or(0);
+            if used >= daily_tokens {
+                crate::log_info!("fid {} is out of llm tokens for today", fid);
+                return Err(AppError::RateLimited { retry_after: secs_until_tomorrow(now) });
+            }
+            Ok(())
+        }
+
+        pub async fn record_llm_usage(cache: &CacheStore, fid: u64, tokens: u64) {
+            if tokens == 0 {
+                return;
+            }
+            // a day's counter has to outlive the day in every timezone it's read from
+            cache.incr_by(&quota_key(fid, Utc::now()), tokens, Duration::from_secs(2 * 24 * 60 * 60)).await;
+        }
+
+        /// Neither provider's stream reports usage, so go by the usual four characters a token.
+        pub fn estimate_tokens(text: &str) -> u64 {
+            (text.chars().count() as u64).div_ceil(4)
+        }
+
+        /// Applies the policy for the request's route, if it has one.
+        pub async fn rate_limit(State(app_state): State<AppState>, request: Request, next: Next) -> Response {
+            let Some(policy) = policy_for(request.uri().path()) else {
+                return next.run(request).await;
+            };
+
+            let peer = request.extensions().get::<ConnectInfo<SocketAddr>>().map(|info| info.0);
+            let client = Client::identify(&app_state.cache, request.headers(), peer, app_state.config.trust_proxy).await;
+
+            if let Err(e) = check(&app_state.cache, &policy, &client).await {
+                return e.into_response();
+            }
+            if let (true, Client::User(fid)) = (policy.llm, &client) {
+                if let Err(e) = check_llm_quota(&app_state.cache, app_state.config.llm_daily_tokens, *fid).await {
+                    return e.into_response();
+                }
+            }
+            next.run(request).await
+        }
+
+        #[cfg(test)]
+        mod tests {
+            use super::*;
+            use chrono::TimeZone;
+
+            #[test]
+            fn buckets_allow_a_burst_then_refill() {
+                let mut bucket = None;
+                for _ in 0..LLM_STREAM.burst {
+                    bucket = Some(take(bucket, &LLM_STREAM, 0).unwrap());
+                }
+                // six a minute is one every ten seconds
+                assert_eq!(take(bucket, &LLM_STREAM, 0), Err(10));
+                assert_eq!(take(bucket, &LLM_STREAM, 4_000), Err(6));
+                assert!(take(bucket, &LLM_STREAM, 10_000).is_ok());
+                // an idle bucket never holds more than the burst
+                assert_eq!(take(bucket, &LLM_STREAM, 3_600_000).unwrap().tokens, LLM_STREAM.burst as f64 - 1.0);
+            }
+
+            #[test]
+            fn routes_get_their_policies() {
+                assert_eq!(policy_for("/api/send_message_stream"), Some(LLM_STREAM));
+                assert_eq!(policy_for(GenerateThreadTitle::PATH), Some(THREAD_TITLES));
+                assert_eq!(policy_for("/api/castsByChannel/memes"), Some(HUB_PROXY));
+                assert_eq!(policy_for("/api/castsByFid/3"), Some(HUB_PROXY));
+                assert_eq!(policy_for(GetUsers::PATH), Some(HUB_PROXY));
+                assert_eq!(policy_for(GetLinkPreview::PATH), Some(HUB_PROXY));
+                assert_eq!(policy_for(ReportClientError::PATH), Some(CLIENT_ERRORS));
+                assert_eq!(policy_for("/api/create_message"), None);
+                assert_eq!(policy_for("/profile/3"), None);
+            }
+
+            #[test]
+            fn forwarded_for_is_only_believed_behind_a_proxy() {
+                let mut headers = HeaderMap::new();
+                headers.insert("x-forwarded-for", "203.0.113.9, 10.0.0.2".parse().unwrap());
+                let peer: SocketAddr = "10.0.0.2:4000".parse().unwrap();
+
+                assert_eq!(client_ip(&headers, Some(peer), true), Some("203.0.113.9".parse().unwrap()));
+                assert_eq!(client_ip(&headers, Some(peer), false), Some(peer.ip()));
+                assert_eq!(client_ip(&HeaderMap::new(), None, true), None);
+            }
+
+            #[test]
+            fn quotas_reset_at_utc_midnight() {
+                let now = Utc.with_ymd_and_hms(2024, 5, 1, 23, 59, 0).unwrap();
+                assert_eq!(secs_until_tomorrow(now), 60);
+                assert_eq!(quota_key(3, now), "llm_tokens:3:2024-05-01");
+            }
+
+            #[tokio::test]
+            async fn the_limit_is_per_client() {
+                let cache = CacheStore::in_memory(16);
+                for _ in 0..THREAD_TITLES.burst {
+                    check(&cache, &THREAD_TITLES, &Client::User(3)).await.unwrap();
+                }
+
+                assert!(matches!(
+                    check(&cache, &THREAD_TITLES, &Client::User(3)).await,
+                    Err(AppError::RateLimited { retry_after: 15 })
+                ));
+                assert!(check(&cache, &THREAD_TITLES, &Client::User(4)).await.is_ok());
+            }
+
+            #[tokio::test]
+            async fn quota_runs_out_after_enough_usage() {
+                let cache = CacheStore::in_memory(16);
+                assert!(check_llm_quota(&cache, 100, 3).await.is_ok());
+                record_llm_usage(&cache, 3, 100).await;
+
+                assert!(matches!(check_llm_quota(&cache, 100, 3).await, Err(AppError::RateLimited { .. })));
+                assert!(check_llm_quota(&cache, 0, 3).await.is_ok());
+                assert!(check_llm_quota(&cache, 100, 4).await.is_ok());
+            }
+        }
+    }
+}
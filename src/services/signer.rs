@@ -0,0 +1,532 @@
+use cfg_if::cfg_if;
+cfg_if! {
+    if #[cfg(feature = "ssr")] {
+        use anyhow::{anyhow, Error};
+        use chrono::Utc;
+        use ed25519_dalek::{Signer as _, SigningKey};
+        use regex::Regex;
+        use reqwest::{header::CONTENT_TYPE, Client};
+        use std::collections::HashMap;
+        use std::time::Duration;
+
+        use crate::config::Config;
+        use crate::models::farcaster::{PublishedCast, UserNameProof};
+        use crate::time::FarcasterTimestamp;
+
+        // protocol limits for a regular (not long-form) cast
+        pub const MAX_CAST_BYTES: usize = 320;
+        pub const MAX_EMBEDS: usize = 2;
+        pub const MAX_MENTIONS: usize = 10;
+
+        const MESSAGE_TYPE_CAST_ADD: u64 = 1;
+        const FARCASTER_NETWORK_MAINNET: u64 = 1;
+        const HASH_SCHEME_BLAKE3: u64 = 1;
+        const SIGNATURE_SCHEME_ED25519: u64 = 1;
+        const HASH_LENGTH: usize = 20;
+
+        /// Just enough protobuf to write farcaster's `MessageData` and `Message`.
+        /// Fields go out in field-number order and proto3 defaults are skipped.
+        #[derive(Default)]
+        struct ProtoWriter {
+            buf: Vec<u8>,
+        }
+
+        impl ProtoWriter {
+            fn varint(&mut self, mut value: u64) {
+                while value >= 0x80 {
+                    self.buf.push((value as u8) | 0x80);
+                    value >>= 7;
+                }
+                self.buf.push(value as u8);
+            }
+
+            fn key(&mut self, field: u32, wire_type: u8) {
+                self.varint(((field as u64) << 3) | wire_type as u64);
+            }
+
+            fn uint(&mut self, field: u32, value: u64) {
+                if value != 0 {
+                    self.key(field, 0);
+                    self.varint(value);
+                }
+            }
+
+            fn bytes(&mut self, field: u32, value: &[u8]) {
+                if !value.is_empty() {
+                    self.len_delimited(field, value);
+                }
+            }
+
+            // for oneof members and sub-messages, which are written even when empty
+            fn len_delimited(&mut self, field: u32, value: &[u8]) {
+                self.key(field, 2);
+                self.varint(value.len() as u64);
+                self.buf.extend_from_slice(value);
+            }
+
+            fn packed(&mut self, field: u32, values: impl IntoIterator<Item = u64>) {
+                let mut inner = ProtoWriter::default();
+                for value in values {
+                    inner.varint(value);
+                }
+                self.bytes(field, &inner.buf);
+            }
+        }
+
+        /// What gets signed: the cast as the author wants it, before mentions are resolved.
+        #[derive(Debug, Clone, Default)]
+        pub struct CastDraft {
+            pub text: String,
+            pub embeds: Vec<String>,
+            pub parent_url: Option<String>,
+        }
+
+        /// A `CastAdd` body with mentions already pulled out of the text.
+        #[derive(Debug, Clone, Default, PartialEq)]
+        pub struct CastAddContent {
+            pub text: String,
+            pub mentions: Vec<u64>,
+            pub mentions_positions: Vec<u32>,
+            pub embeds: Vec<String>,
+            pub parent_url: Option<String>,
+        }
+
+        impl CastAddContent {
+            pub fn validate(&self) -> Result<(), Error> {
+                if self.text.is_empty() && self.embeds.is_empty() {
+                    return Err(anyhow!("a cast needs text or an embed"));
+                }
+                if self.text.len() > MAX_CAST_BYTES {
+                    return Err(anyhow!("cast text is {} bytes, the limit is {}", self.text.len(), MAX_CAST_BYTES));
+                }
+                if self.embeds.len() > MAX_EMBEDS {
+                    return Err(anyhow!("a cast can have at most {} embeds", MAX_EMBEDS));
+                }
+                if self.mentions.len() > MAX_MENTIONS {
+                    return Err(anyhow!("a cast can have at most {} mentions", MAX_MENTIONS));
+                }
+                Ok(())
+            }
+
+            /// Encodes `MessageData { type: CAST_ADD, fid, timestamp, network, cast_add_body }`.
+            pub fn encode_message_data(&self, fid: u64, timestamp: FarcasterTimestamp) -> Vec<u8> {
+                let mut body = ProtoWriter::default();
+                body.packed(2, self.mentions.iter().copied());
+                body.bytes(4, self.text.as_bytes());
+                body.packed(5, self.mentions_positions.iter().map(|&p| p as u64));
+                for url in &self.embeds {
+                    let mut embed = ProtoWriter::default();
+                    embed.len_delimited(1, url.as_bytes());
+                    body.len_delimited(6, &embed.buf);
+                }
+                if let Some(parent_url) = &self.parent_url {
+                    body.len_delimited(7, parent_url.as_bytes());
+                }
+
+                let mut data = ProtoWriter::default();
+                data.uint(1, MESSAGE_TYPE_CAST_ADD);
+                data.uint(2, fid);
+                data.uint(3, timestamp.0);
+                data.uint(4, FARCASTER_NETWORK_MAINNET);
+                data.len_delimited(5, &body.buf);
+                data.buf
+            }
+        }
+
+        // fnames are lowercase, up to 16 chars; the leading space keeps emails out
+        fn mention_regex() -> Regex {
+            Regex::new(r"(^|\s)@([a-z0-9][a-z0-9-]{0,15}(?:\.eth)?)").unwrap()
+        }
+
+        /// Pulls `@fname` mentions out of `text`. Mentions come out of the text
+        /// entirely and are recorded as byte offsets into what's left, which is
+        /// how hubs expect them. Names missing from `fids` stay in the text.
+        pub fn extract_mentions(text: &str, fids: &HashMap<String, u64>) -> (String, Vec<u64>, Vec<u32>) {
+            let mention_re = mention_regex();
+
+            let mut stripped = String::with_capacity(text.len());
+            let mut mentions = Vec::new();
+            let mut positions = Vec::new();
+            let mut last = 0;
+
+            for cap in mention_re.captures_iter(text) {
+                let name = cap.get(2).unwrap();
+                let Some(&fid) = fids.get(name.as_str()) else { continue };
+                let at = name.start() - 1;
+                stripped.push_str(&text[last..at]);
+                mentions.push(fid);
+                positions.push(stripped.len() as u32);
+                last = name.end();
+            }
+            stripped.push_str(&text[last..]);
+
+            (stripped, mentions, positions)
+        }
+
+        pub fn mentioned_names(text: &str) -> Vec<String> {
+            let mut names: Vec<String> = mention_regex().captures_iter(text).map(|cap| cap[2].to_string()).collect();
+            names.sort();
+            names.dedup();
+            names
+        }
+
+        /// The app's Ed25519 signer, registered on-chain for `fid`.
+        pub struct AppSigner {
+            pub fid: u64,
+            key: SigningKey,
+        }
+
+        impl AppSigner {
+            pub fn new(fid: u64, key: SigningKey) -> Self {
+                AppSigner { fid, key }
+            }
+
+            /// `key_hex` as `FARCASTER_SIGNER_KEY` holds it. 64 byte keys (seed
+            /// followed by public key) are accepted too.
+            pub fn from_hex(fid: u64, key_hex: &str) -> Result<Self, Error> {
+                let key_bytes = hex::decode(key_hex.trim().trim_start_matches("0x"))
+                    .map_err(|e| anyhow!("FARCASTER_SIGNER_KEY is not valid hex: {}", e))?;
+                let seed: [u8; 32] = key_bytes
+                    .get(..32)
+                    .and_then(|seed| seed.try_into().ok())
+                    .ok_or_else(|| anyhow!("FARCASTER_SIGNER_KEY must be at least 32 bytes"))?;
+                Ok(AppSigner::new(fid, SigningKey::from_bytes(&seed)))
+            }
+
+            pub fn public_key(&self) -> [u8; 32] {
+                self.key.verifying_key().to_bytes()
+            }
+
+            /// Hashes and signs encoded `MessageData`, wrapping it up as a `Message`.
+            pub fn sign(&self, data_bytes: Vec<u8>) -> SignedMessage {
+                let hash = blake3::hash(&data_bytes).as_bytes()[..HASH_LENGTH].to_vec();
+                let signature = self.key.sign(&hash).to_bytes().to_vec();
+                SignedMessage {
+                    data_bytes,
+                    hash,
+                    signature,
+                    signer: self.public_key().to_vec(),
+                }
+            }
+        }
+
+        #[derive(Debug, Clone)]
+        pub struct SignedMessage {
+            pub data_bytes: Vec<u8>,
+            pub hash: Vec<u8>,
+            pub signature: Vec<u8>,
+            pub signer: Vec<u8>,
+        }
+
+        impl SignedMessage {
+            pub fn hash_hex(&self) -> String {
+                format!("0x{}", hex::encode(&self.hash))
+            }
+
+            /// Encodes `Message`. `data_bytes` rides along so the hub checks the
+            /// hash against exactly the bytes that were signed.
+            pub fn encode(&self) -> Vec<u8> {
+                let mut message = ProtoWriter::default();
+                message.len_delimited(1, &self.data_bytes);
+                message.bytes(2, &self.hash);
+                message.uint(3, HASH_SCHEME_BLAKE3);
+                message.bytes(4, &self.signature);
+                message.uint(5, SIGNATURE_SCHEME_ED25519);
+                message.bytes(6, &self.signer);
+                message.bytes(7, &self.data_bytes);
+                message.buf
+            }
+        }
+
+        pub struct CastPublisher {
+            client: Client,
+            /// hub http api root, e.g. `http://hub:2281`
+            hub_url: String,
+            signer: AppSigner,
+        }
+
+        impl CastPublisher {
+            pub fn new(hub_url: String, signer: AppSigner) -> Result<Self, Error> {
+                let client = Client::builder().timeout(Duration::from_secs(10)).build()?;
+                Ok(CastPublisher { client, hub_url, signer })
+            }
+
+            pub fn from_config(config: &Config) -> Result<Self, Error> {
+                let hubble_url = config.hubble_url.as_ref().ok_or_else(|| anyhow!("HUBBLE_URL isn't set, publishing is off"))?;
+                let signer = config
+                    .signer
+                    .as_ref()
+                    .ok_or_else(|| anyhow!("FARCASTER_FID and FARCASTER_SIGNER_KEY aren't set, publishing is off"))?;
+                CastPublisher::new(format!("{}:2281", hubble_url), AppSigner::from_hex(signer.fid, signer.key.expose())?)
+            }
+
+            /// Resolves mentions, builds and signs the `CastAdd`, and submits it
+            /// unless this is a dry run.
+            pub async fn publish(&self, draft: CastDraft, dry_run: bool) -> Result<PublishedCast, Error> {
+                let mut fids = HashMap::new();
+                let mut unresolved_mentions = Vec::new();
+                for name in mentioned_names(&draft.text) {
+                    match self.resolve_fname(&name).await {
+                        Ok(fid) => {
+                            fids.insert(name, fid);
+                        }
+                        Err(e) => {
+                            crate::log_debug!("leaving @{} as text: {}", name, e);
+                            unresolved_mentions.push(name);
+                        }
+                    }
+                }
+
+                let (text, mentions, mentions_positions) = extract_mentions(&draft.text, &fids);
+                let content = CastAddContent {
+                    text,
+                    mentions,
+                    mentions_positions,
+                    embeds: draft.embeds,
+                    parent_url: draft.parent_url,
+                };
+                content.validate()?;
+
+                let timestamp = FarcasterTimestamp::from_datetime(Utc::now());
+                let signed = self.signer.sign(content.encode_message_data(self.signer.fid, timestamp));
+
+                if dry_run {
+                    crate::log_info!("dry run, not submitting cast {}", signed.hash_hex());
+                } else {
+                    self.submit(&signed).await?;
+                    crate::log_info!("published cast {}", signed.hash_hex());
+                }
+
+                Ok(PublishedCast {
+                    hash: signed.hash_hex(),
+                    fid: self.signer.fid,
+                    text: content.text,
+                    mentions: content.mentions,
+                    mentions_positions: content.mentions_positions,
+                    embeds: content.embeds,
+                    parent_url: content.parent_url,
+                    unresolved_mentions,
+                    dry_run,
+                })
+            }
+
+            async fn resolve_fname(&self, name: &str) -> Result<u64, Error> {
+                let response = self
+                    .client
+                    .get(format!("{}/v1/userNameProofByName", self.hub_url))
+                    .query(&[("name", name)])
+                    .send()
+                    .await?;
+                if !response.status().is_success() {
+                    return Err(anyhow!("hub returned {} for @{}", response.status(), name));
+                }
+                Ok(response.json::<UserNameProof>().await?.fid)
+            }
+
+            async fn submit(&self, signed: &SignedMessage) -> Result<(), Error> {
+                let response = self
+                    .client
+                    .post(format!("{}/v1/submitMessage", self.hub_url))
+                    .header(CONTENT_TYPE, "application/octet-stream")
+                    .body(signed.encode())
+                    .send()
+                    .await?;
+                if !response.status().is_success() {
+                    let status = response.status();
+                    let body = response.text().await.unwrap_or_default();
+                    return Err(anyhow!("hub rejected the cast ({}): {}", status, body));
+                }
+                Ok(())
+            }
+        }
+
+        #[cfg(test)]
+        mod tests {
+            use super::*;
+            use axum::body::Bytes;
+            use axum::extract::{Query, State};
+            use axum::http::StatusCode;
+            use axum::routing::{get, post};
+            use axum::{Json, Router};
+            use ed25519_dalek::{Signature, Verifier, VerifyingKey};
+            use serde_json::{json, Value};
+            use std::sync::{Arc, Mutex};
+
+            fn test_signer() -> AppSigner {
+                AppSigner::new(1234, SigningKey::from_bytes(&[7u8; 32]))
+            }
+
+            fn fids() -> HashMap<String, u64> {
+                HashMap::from([("dwr".to_string(), 3), ("v".to_string(), 2)])
+            }
+
+            #[test]
+            fn encodes_a_minimal_cast_add() {
+                let content = CastAddContent { text: "hi".to_string(), ..Default::default() };
+                assert_eq!(
+                    content.encode_message_data(1, FarcasterTimestamp(2)),
+                    [0x08, 0x01, 0x10, 0x01, 0x18, 0x02, 0x20, 0x01, 0x2a, 0x04, 0x22, 0x02, b'h', b'i'],
+                );
+            }
+
+            #[test]
+            fn encodes_mentions_embeds_and_parent() {
+                let content = CastAddContent {
+                    text: "gm ".to_string(),
+                    mentions: vec![3],
+                    mentions_positions: vec![3],
+                    embeds: vec!["a.co".to_string()],
+                    parent_url: Some("p".to_string()),
+                };
+                let data = content.encode_message_data(300, FarcasterTimestamp(1));
+                let body: &[u8] = &[
+                    0x12, 0x01, 0x03, // mentions, packed
+                    0x22, 0x03, b'g', b'm', b' ', // text
+                    0x2a, 0x01, 0x03, // mentions_positions, packed
+                    0x32, 0x06, 0x0a, 0x04, b'a', b'.', b'c', b'o', // embeds[0].url
+                    0x3a, 0x01, b'p', // parent_url
+                ];
+                let mut expected = vec![0x08, 0x01, 0x10, 0xac, 0x02, 0x18, 0x01, 0x20, 0x01, 0x2a, body.len() as u8];
+                expected.extend_from_slice(body);
+                assert_eq!(data, expected);
+            }
+
+            #[test]
+            fn signs_the_blake3_hash() {
+                let signer = test_signer();
+                let data = CastAddContent { text: "hi".to_string(), ..Default::default() }
+                    .encode_message_data(signer.fid, FarcasterTimestamp(100));
+                let signed = signer.sign(data.clone());
+
+                assert_eq!(signed.hash, blake3::hash(&data).as_bytes()[..20].to_vec());
+                assert_eq!(signed.hash_hex().len(), 42);
+
+                let key = VerifyingKey::from_bytes(&signer.public_key()).unwrap();
+                let signature = Signature::from_slice(&signed.signature).unwrap();
+                assert!(key.verify(&signed.hash, &signature).is_ok());
+            }
+
+            #[test]
+            fn pulls_mentions_out_as_byte_offsets() {
+                let (text, mentions, positions) = extract_mentions("gm @dwr and @v!", &fids());
+                assert_eq!(text, "gm  and !");
+                assert_eq!(mentions, vec![3, 2]);
+                assert_eq!(positions, vec![3, 8]);
+            }
+
+            #[test]
+            fn leaves_unknown_names_and_emails_alone() {
+                let (text, mentions, _) = extract_mentions("ask @nobody or mail me@dwr.com, @dwr", &fids());
+                assert_eq!(text, "ask @nobody or mail me@dwr.com, ");
+                assert_eq!(mentions, vec![3]);
+            }
+
+            #[test]
+            fn offsets_count_bytes_not_chars() {
+                let (text, _, positions) = extract_mentions("café @dwr", &fids());
+                assert_eq!(text, "café ");
+                assert_eq!(positions, vec![6]);
+            }
+
+            #[test]
+            fn rejects_oversized_casts() {
+                let content = CastAddContent { text: "a".repeat(MAX_CAST_BYTES + 1), ..Default::default() };
+                assert!(content.validate().is_err());
+                let content = CastAddContent {
+                    text: "a".to_string(),
+                    embeds: vec!["1".into(), "2".into(), "3".into()],
+                    ..Default::default()
+                };
+                assert!(content.validate().is_err());
+                assert!(CastAddContent::default().validate().is_err());
+            }
+
+            type Submitted = Arc<Mutex<Vec<Vec<u8>>>>;
+
+            // stands in for a hub: knows @dwr and records whatever gets submitted
+            async fn mock_hub(submitted: Submitted) -> String {
+                let app = Router::new()
+                    .route(
+                        "/v1/userNameProofByName",
+                        get(|Query(params): Query<HashMap<String, String>>| async move {
+                            match params.get("name").map(String::as_str) {
+                                Some("dwr") => Ok(Json(json!({
+                                    "timestamp": 1, "name": "dwr", "owner": "0x00", "fid": 3,
+                                    "type": "USERNAME_TYPE_FNAME"
+                                }))),
+                                _ => Err(StatusCode::NOT_FOUND),
+                            }
+                        }),
+                    )
+                    .route(
+                        "/v1/submitMessage",
+                        post(|State(submitted): State<Submitted>, body: Bytes| async move {
+                            submitted.lock().unwrap().push(body.to_vec());
+                            Json(Value::Null)
+                        }),
+                    )
+                    .with_state(submitted);
+                let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+                let addr = listener.local_addr().unwrap();
+                tokio::spawn(async move { axum::serve(listener, app).await.unwrap() });
+                format!("http://{}", addr)
+            }
+
+            fn draft() -> CastDraft {
+                CastDraft {
+                    text: "gm @dwr and @ghost".to_string(),
+                    embeds: vec!["https://thenetworktimes.xyz".to_string()],
+                    parent_url: Some("https://warpcast.com/~/channel/networktimes".to_string()),
+                }
+            }
+
+            #[tokio::test]
+            async fn submits_a_signed_cast_to_the_hub() {
+                let submitted = Submitted::default();
+                let publisher = CastPublisher::new(mock_hub(submitted.clone()).await, test_signer()).unwrap();
+
+                let published = publisher.publish(draft(), false).await.unwrap();
+
+                assert!(!published.dry_run);
+                assert_eq!(published.text, "gm  and @ghost");
+                assert_eq!(published.mentions, vec![3]);
+                assert_eq!(published.mentions_positions, vec![3]);
+                assert_eq!(published.unresolved_mentions, vec!["ghost".to_string()]);
+
+                let submitted = submitted.lock().unwrap();
+                assert_eq!(submitted.len(), 1);
+                // the signature and signer key go out as the tail of the message
+                let body = &submitted[0];
+                let signer = test_signer().public_key();
+                assert!(body.windows(signer.len()).any(|window| window == signer));
+                let hash = hex::decode(published.hash.trim_start_matches("0x")).unwrap();
+                assert!(body.windows(hash.len()).any(|window| window == hash.as_slice()));
+            }
+
+            #[tokio::test]
+            async fn dry_run_signs_without_submitting() {
+                let submitted = Submitted::default();
+                let publisher = CastPublisher::new(mock_hub(submitted.clone()).await, test_signer()).unwrap();
+
+                let published = publisher.publish(draft(), true).await.unwrap();
+
+                assert!(published.dry_run);
+                assert_eq!(published.hash.len(), 42);
+                assert!(submitted.lock().unwrap().is_empty());
+            }
+
+            #[tokio::test]
+            async fn invalid_drafts_never_reach_the_hub() {
+                let submitted = Submitted::default();
+                let publisher = CastPublisher::new(mock_hub(submitted.clone()).await, test_signer()).unwrap();
+
+                let result = publisher
+                    .publish(CastDraft { text: "a".repeat(400), ..Default::default() }, false)
+                    .await;
+
+                assert!(result.is_err());
+                assert!(submitted.lock().unwrap().is_empty());
+            }
+        }
+    }
+}
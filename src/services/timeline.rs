@@ -0,0 +1,162 @@
+use serde::{Deserialize, Serialize};
+use std::collections::{HashMap, HashSet};
+
+use crate::models::farcaster::Cast;
+
+/// A cast on the home timeline, tagged with the channel it was pulled from.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct TimelineCast {
+    pub channel_id: String,
+    pub cast: Cast,
+}
+
+/// One page of the merged timeline. `cursors` holds how far into each channel's
+/// newest-first feed the timeline has read, and goes back up with the next request.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct TimelinePage {
+    pub casts: Vec<TimelineCast>,
+    pub cursors: HashMap<String, u64>,
+    pub has_more: bool,
+}
+
+/// A window of one channel's feed, newest first, read from `offset`.
+#[derive(Debug, Clone)]
+pub struct ChannelWindow {
+    pub channel_id: String,
+    pub offset: u64,
+    pub casts: Vec<Cast>,
+    /// false once the channel handed back less than was asked for
+    pub has_more: bool,
+}
+
+/// Merges per-channel windows into up to `limit` casts, newest first and
+/// deduplicated by hash. Each channel's cursor only moves past the casts that
+/// were actually consumed, so the leftovers head the next page.
+pub fn merge_timeline(windows: Vec<ChannelWindow>, limit: usize) -> TimelinePage {
+    let mut cursors: HashMap<String, u64> = windows
+        .iter()
+        .map(|window| (window.channel_id.clone(), window.offset))
+        .collect();
+    let mut heads: Vec<(String, std::iter::Peekable<std::vec::IntoIter<Cast>>, bool)> = windows
+        .into_iter()
+        .map(|window| (window.channel_id, window.casts.into_iter().peekable(), window.has_more))
+        .collect();
+
+    let mut seen = HashSet::new();
+    let mut casts = Vec::new();
+
+    while casts.len() < limit {
+        // newest head wins; ties go to the lower hash so pages are stable
+        let next = heads
+            .iter_mut()
+            .filter_map(|(channel_id, feed, _)| feed.peek().map(|cast| (cast.data.timestamp, cast.hash.clone(), channel_id.clone())))
+            .max_by(|a, b| a.0.cmp(&b.0).then_with(|| b.1.cmp(&a.1)));
+        let Some((_, _, channel_id)) = next else { break };
+
+        let (_, feed, _) = heads.iter_mut().find(|(id, _, _)| *id == channel_id).expect("head came from this list");
+        let cast = feed.next().expect("peeked above");
+        *cursors.entry(channel_id.clone()).or_default() += 1;
+
+        if seen.insert(cast.hash.clone()) {
+            casts.push(TimelineCast { channel_id, cast });
+        }
+    }
+
+    let has_more = heads
+        .iter_mut()
+        .any(|(_, feed, has_more)| *has_more || feed.peek().is_some());
+
+    TimelinePage { casts, cursors, has_more }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::models::farcaster::CastData;
+
+    fn cast(hash: &str, timestamp: u64) -> Cast {
+        Cast {
+            data: CastData {
+                castAddBody: None,
+                fid: 1,
+                network: "FARCASTER_NETWORK_MAINNET".to_string(),
+                timestamp,
+                cast_type: "MESSAGE_TYPE_CAST_ADD".to_string(),
+            },
+            hash: hash.to_string(),
+        }
+    }
+
+    fn window(channel_id: &str, offset: u64, casts: Vec<Cast>, has_more: bool) -> ChannelWindow {
+        ChannelWindow { channel_id: channel_id.to_string(), offset, casts, has_more }
+    }
+
+    fn hashes(page: &TimelinePage) -> Vec<&str> {
+        page.casts.iter().map(|c| c.cast.hash.as_str()).collect()
+    }
+
+    #[test]
+    fn interleaves_channels_newest_first() {
+        let page = merge_timeline(
+            vec![
+                window("a", 0, vec![cast("a1", 50), cast("a2", 30), cast("a3", 10)], false),
+                window("b", 0, vec![cast("b1", 40), cast("b2", 20)], false),
+            ],
+            10,
+        );
+        assert_eq!(hashes(&page), ["a1", "b1", "a2", "b2", "a3"]);
+        assert_eq!(page.casts[1].channel_id, "b");
+        assert!(!page.has_more);
+    }
+
+    #[test]
+    fn cursors_only_advance_past_consumed_casts() {
+        let page = merge_timeline(
+            vec![
+                window("a", 4, vec![cast("a1", 50), cast("a2", 30)], true),
+                window("b", 2, vec![cast("b1", 40), cast("b2", 20)], false),
+            ],
+            2,
+        );
+        assert_eq!(hashes(&page), ["a1", "b1"]);
+        assert_eq!(page.cursors["a"], 5);
+        assert_eq!(page.cursors["b"], 3);
+        assert!(page.has_more);
+    }
+
+    #[test]
+    fn drops_duplicates_but_still_moves_the_cursor() {
+        let page = merge_timeline(
+            vec![
+                window("a", 0, vec![cast("x", 50), cast("z", 45)], false),
+                window("b", 0, vec![cast("x", 50), cast("y", 40)], false),
+            ],
+            10,
+        );
+        assert_eq!(hashes(&page), ["x", "z", "y"]);
+        assert_eq!(page.cursors["a"], 2);
+        assert_eq!(page.cursors["b"], 2);
+    }
+
+    #[test]
+    fn equal_timestamps_come_out_in_a_stable_order() {
+        let page = merge_timeline(
+            vec![
+                window("a", 0, vec![cast("m", 10)], false),
+                window("b", 0, vec![cast("c", 10)], false),
+            ],
+            10,
+        );
+        assert_eq!(hashes(&page), ["c", "m"]);
+    }
+
+    #[test]
+    fn has_more_while_a_channel_is_unfinished() {
+        let page = merge_timeline(vec![window("a", 0, vec![], true)], 10);
+        assert!(page.casts.is_empty());
+        assert!(page.has_more);
+
+        let page = merge_timeline(vec![window("a", 0, vec![], false)], 10);
+        assert!(!page.has_more);
+    }
+}
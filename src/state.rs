@@ -3,15 +3,33 @@ use cfg_if::cfg_if;
 cfg_if! {
     if #[cfg(feature = "ssr")] {
         use axum::extract::FromRef;
+        use std::sync::Arc;
         use leptos::LeptosOptions;
-        use redis::aio::MultiplexedConnection;
+        use crate::config::Config;
         use crate::database::db::DbPool;
+        use crate::database::repository::{MessageRepository, ThreadRepository};
+        use crate::services::cache::{CacheStore, Caches};
+        use crate::services::hub_events::CastFeed;
 
         #[derive(FromRef, Clone)]
         pub struct AppState {
             pub leptos_options: LeptosOptions,
             pub pool: DbPool,
-            pub redis_pool: MultiplexedConnection,
+            /// redis, or an in-process stand in when it's missing or down
+            pub cache: CacheStore,
+            pub caches: Caches,
+            pub cast_feed: CastFeed,
+            pub config: Arc<Config>,
+        }
+
+        impl AppState {
+            pub fn threads(&self) -> ThreadRepository {
+                ThreadRepository::new(self.pool.clone())
+            }
+
+            pub fn messages(&self) -> MessageRepository {
+                MessageRepository::new(self.pool.clone())
+            }
         }
     }
 }
@@ -0,0 +1,150 @@
+use chrono::{DateTime, Datelike, Utc};
+use serde::{Deserialize, Serialize};
+
+/// 2021-01-01 00:00:00 utc, where hub timestamps start counting.
+pub const FARCASTER_EPOCH_SECS: i64 = 1_609_459_200;
+pub const FARCASTER_EPOCH_MILLIS: i64 = FARCASTER_EPOCH_SECS * 1000;
+
+// anything past this as "seconds" would be after the year 5000, so it's milliseconds
+const MILLIS_THRESHOLD: u64 = 100_000_000_000;
+
+/// Seconds since the Farcaster epoch, as found in `CastData.timestamp` and
+/// every other hub message.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize)]
+pub struct FarcasterTimestamp(pub u64);
+
+impl FarcasterTimestamp {
+    pub fn from_unix_secs(secs: i64) -> Self {
+        FarcasterTimestamp(secs.saturating_sub(FARCASTER_EPOCH_SECS).max(0) as u64)
+    }
+
+    pub fn from_datetime(datetime: DateTime<Utc>) -> Self {
+        Self::from_unix_secs(datetime.timestamp())
+    }
+
+    pub fn to_unix_secs(self) -> i64 {
+        self.0 as i64 + FARCASTER_EPOCH_SECS
+    }
+
+    pub fn to_unix_millis(self) -> i64 {
+        self.to_unix_secs() * 1000
+    }
+
+    pub fn to_datetime(self) -> DateTime<Utc> {
+        DateTime::from_timestamp(self.to_unix_secs(), 0).unwrap_or_default()
+    }
+}
+
+impl From<u64> for FarcasterTimestamp {
+    fn from(secs: u64) -> Self {
+        FarcasterTimestamp(secs)
+    }
+}
+
+/// Unix time in milliseconds.
+pub fn from_unix_millis(millis: i64) -> DateTime<Utc> {
+    DateTime::from_timestamp_millis(millis).unwrap_or_default()
+}
+
+/// Warpcast's `createdAt`. Documented as unix milliseconds, but older channel
+/// records carry plain seconds, so the magnitude decides.
+pub fn from_warpcast_created_at(created_at: u64) -> DateTime<Utc> {
+    if created_at >= MILLIS_THRESHOLD {
+        from_unix_millis(created_at as i64)
+    } else {
+        DateTime::from_timestamp(created_at as i64, 0).unwrap_or_default()
+    }
+}
+
+/// "march '24", as used for channel creation dates.
+pub fn month_year(datetime: DateTime<Utc>) -> String {
+    datetime.format("%B '%y").to_string().to_lowercase()
+}
+
+/// A deterministic absolute date for when there's no "now" to compare against,
+/// e.g. while rendering on the server.
+pub fn short_date(datetime: DateTime<Utc>) -> String {
+    datetime.format("%b %-d, %Y").to_string().to_lowercase()
+}
+
+/// "just now", "5m ago", "3h ago", "2d ago", then a plain date past a week.
+/// Timestamps slightly in the future (clock skew) read as "just now".
+pub fn relative_time(then: DateTime<Utc>, now: DateTime<Utc>) -> String {
+    let elapsed = (now - then).num_seconds();
+    match elapsed {
+        s if s < 60 => "just now".to_string(),
+        s if s < 60 * 60 => format!("{}m ago", s / 60),
+        s if s < 60 * 60 * 24 => format!("{}h ago", s / (60 * 60)),
+        s if s < 60 * 60 * 24 * 7 => format!("{}d ago", s / (60 * 60 * 24)),
+        _ if then.year() == now.year() => then.format("%b %-d").to_string().to_lowercase(),
+        _ => short_date(then),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::{Duration, TimeZone};
+
+    fn utc(y: i32, m: u32, d: u32, h: u32, min: u32, s: u32) -> DateTime<Utc> {
+        Utc.with_ymd_and_hms(y, m, d, h, min, s).unwrap()
+    }
+
+    #[test]
+    fn farcaster_epoch_is_2021() {
+        assert_eq!(FarcasterTimestamp(0).to_datetime(), utc(2021, 1, 1, 0, 0, 0));
+        assert_eq!(FarcasterTimestamp(0).to_unix_secs(), 1_609_459_200);
+        assert_eq!(FarcasterTimestamp(0).to_unix_millis(), FARCASTER_EPOCH_MILLIS);
+    }
+
+    #[test]
+    fn converts_hub_timestamps() {
+        // 2024-06-01 12:00:00 utc
+        let ts = FarcasterTimestamp(107_784_000);
+        assert_eq!(ts.to_datetime(), utc(2024, 6, 1, 12, 0, 0));
+        assert_eq!(FarcasterTimestamp::from_datetime(utc(2024, 6, 1, 12, 0, 0)), ts);
+        assert_eq!(FarcasterTimestamp::from_unix_secs(ts.to_unix_secs()), ts);
+    }
+
+    #[test]
+    fn unix_times_before_the_farcaster_epoch_clamp_to_zero() {
+        assert_eq!(FarcasterTimestamp::from_unix_secs(0), FarcasterTimestamp(0));
+    }
+
+    #[test]
+    fn unix_millis() {
+        assert_eq!(from_unix_millis(1_717_243_200_000), utc(2024, 6, 1, 12, 0, 0));
+    }
+
+    #[test]
+    fn warpcast_created_at_in_either_unit() {
+        assert_eq!(from_warpcast_created_at(1_691_015_606_000), utc(2023, 8, 2, 22, 33, 26));
+        assert_eq!(from_warpcast_created_at(1_691_015_606), utc(2023, 8, 2, 22, 33, 26));
+        assert_eq!(month_year(from_warpcast_created_at(1_691_015_606_000)), "august '23");
+    }
+
+    #[test]
+    fn relative_times() {
+        let now = utc(2024, 6, 10, 12, 0, 0);
+        assert_eq!(relative_time(now, now), "just now");
+        assert_eq!(relative_time(now - Duration::seconds(59), now), "just now");
+        assert_eq!(relative_time(now - Duration::minutes(5), now), "5m ago");
+        assert_eq!(relative_time(now - Duration::hours(3), now), "3h ago");
+        assert_eq!(relative_time(now - Duration::hours(25), now), "1d ago");
+        assert_eq!(relative_time(now - Duration::days(6), now), "6d ago");
+        assert_eq!(relative_time(utc(2024, 3, 4, 9, 0, 0), now), "mar 4");
+        assert_eq!(relative_time(utc(2023, 3, 4, 9, 0, 0), now), "mar 4, 2023");
+    }
+
+    #[test]
+    fn future_timestamps_read_as_just_now() {
+        let now = utc(2024, 6, 10, 12, 0, 0);
+        assert_eq!(relative_time(now + Duration::minutes(2), now), "just now");
+    }
+
+    #[test]
+    fn relative_time_from_a_hub_timestamp() {
+        let cast_time = FarcasterTimestamp(107_784_000).to_datetime();
+        assert_eq!(relative_time(cast_time, utc(2024, 6, 1, 15, 30, 0)), "3h ago");
+    }
+}
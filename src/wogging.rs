@@ -1,21 +1,60 @@
 use cfg_if::cfg_if;
+use leptos::{server, ServerFnError};
 
 cfg_if! {
     if #[cfg(feature = "ssr")] {
+        use http::Request;
         use log::LevelFilter;
-        use std::env;
+        use tracing::Span;
+        use tracing_subscriber::filter::LevelFilter as TracingLevel;
+        use tracing_subscriber::EnvFilter;
+        use crate::config::LogFormat;
 
-        pub fn init_logging() {
-            let log_level = env::var("LOG_LEVEL").unwrap_or_else(|_| "info".to_string());
-            let level = match log_level.to_lowercase().as_str() {
-                "trace" => LevelFilter::Trace,
-                "debug" => LevelFilter::Debug,
-                "info" => LevelFilter::Info,
-                "warn" => LevelFilter::Warn,
-                "error" => LevelFilter::Error,
-                _ => LevelFilter::Info,
+        /// set on every request that comes in without one, and echoed back on the response
+        pub const REQUEST_ID_HEADER: &str = "x-request-id";
+        /// the most of a client error that makes it into the server log
+        const MAX_CLIENT_MESSAGE: usize = 2000;
+
+        /// `level` and `format` are `LOG_LEVEL` and `LOG_FORMAT`, already parsed by
+        /// the config. `RUST_LOG`, when set, takes over from `level` with per-target
+        /// directives like `info,thenetworktimes=debug`. Lines from the `log` macros
+        /// are carried into tracing, inside whichever span they were written in.
+        pub fn init_logging(level: LevelFilter, format: LogFormat) {
+            let level = match level {
+                LevelFilter::Off => TracingLevel::OFF,
+                LevelFilter::Error => TracingLevel::ERROR,
+                LevelFilter::Warn => TracingLevel::WARN,
+                LevelFilter::Info => TracingLevel::INFO,
+                LevelFilter::Debug => TracingLevel::DEBUG,
+                LevelFilter::Trace => TracingLevel::TRACE,
             };
-            env_logger::Builder::new().filter_level(level).init();
+            // blank counts as unset, like everything the config reads
+            let filter = std::env::var(EnvFilter::DEFAULT_ENV)
+                .ok()
+                .filter(|directives| !directives.trim().is_empty())
+                .and_then(|directives| EnvFilter::try_new(directives).ok())
+                .unwrap_or_else(|| EnvFilter::default().add_directive(level.into()));
+            let subscriber = tracing_subscriber::fmt().with_env_filter(filter);
+            match format {
+                LogFormat::Json => subscriber.json().with_current_span(true).with_span_list(true).init(),
+                LogFormat::Text => subscriber.init(),
+            }
+        }
+
+        /// The span each request runs in, so everything logged while serving it
+        /// carries its id.
+        pub fn request_span<B>(request: &Request<B>) -> Span {
+            let request_id = request
+                .headers()
+                .get(REQUEST_ID_HEADER)
+                .and_then(|value| value.to_str().ok())
+                .unwrap_or_default();
+            tracing::info_span!(
+                "request",
+                request_id = %request_id,
+                method = %request.method(),
+                path = %request.uri().path(),
+            )
         }
     } else {
         use wasm_bindgen::prelude::*;
@@ -30,9 +69,34 @@ cfg_if! {
             // Client-side logging initialization (if needed)
             // For now, we'll just use console.log
         }
+
+        /// Sends a `[CLIENT]` error on to the server's log, so it isn't only in someone's console.
+        pub fn forward(message: String) {
+            cfg_if! {
+                if #[cfg(target_arch = "wasm32")] {
+                    let location = web_sys::window()
+                        .and_then(|window| window.location().href().ok())
+                        .unwrap_or_default();
+                    // a failure here is not worth another error, or it'd forward itself
+                    leptos::spawn_local(async move {
+                        let _ = report_client_error(message, location).await;
+                    });
+                } else {
+                    let _ = message;
+                }
+            }
+        }
     }
 }
 
+/// Writes an error the browser hit into the server's log.
+#[server(ReportClientError, "/api")]
+pub async fn report_client_error(message: String, location: String) -> Result<(), ServerFnError> {
+    let message: String = message.chars().take(MAX_CLIENT_MESSAGE).collect();
+    tracing::error!(target: "client", location = %location, "{}", message);
+    Ok(())
+}
+
 #[macro_export]
 macro_rules! custom_log {
     ($level:expr, $($arg:tt)+) => {{
@@ -49,6 +113,9 @@ macro_rules! custom_log {
             } else {
                 let msg = format!("[CLIENT] [{}] {}", $level.to_uppercase(), format_args!($($arg)+));
                 $crate::wogging::log(&msg);
+                if $level == "error" {
+                    $crate::wogging::forward(msg);
+                }
             }
         }
     }};
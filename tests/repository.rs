@@ -0,0 +1,215 @@
+//! Repository tests against a real Postgres. Each test gets its own throwaway
+//! database on the server at `TEST_DATABASE_URL` (any database there works, it
+//! only needs permission to create others), migrated from `migrations/`, and
+//! dropped afterwards. Without `TEST_DATABASE_URL` the tests skip.
+#![cfg(feature = "ssr")]
+
+use chrono::{Duration, Utc};
+use diesel::connection::SimpleConnection;
+use diesel::{Connection, PgConnection};
+use std::path::Path;
+
+use thenetworktimes::database::db::{establish_connection, DbPool, ThreadAccess};
+use thenetworktimes::database::repository::{MessageRepository, RepositoryError, ThreadRepository};
+use thenetworktimes::models::conversations::{NewMessage, Thread, ThreadShare};
+
+const OWNER: i64 = 3;
+const STRANGER: i64 = 5;
+
+struct TestDatabase {
+    admin_url: String,
+    name: String,
+    pool: DbPool,
+}
+
+impl TestDatabase {
+    fn create() -> Option<TestDatabase> {
+        let Ok(admin_url) = std::env::var("TEST_DATABASE_URL") else {
+            eprintln!("TEST_DATABASE_URL not set, skipping");
+            return None;
+        };
+        let name = format!("nwt_test_{}", uuid::Uuid::new_v4().simple());
+        let mut admin = PgConnection::establish(&admin_url).expect("failed to connect to TEST_DATABASE_URL");
+        admin
+            .batch_execute(&format!("CREATE DATABASE {}", name))
+            .expect("failed to create test database");
+
+        // built before migrating so a bad migration still drops the database
+        let url = database_url(&admin_url, &name);
+        let db = TestDatabase { admin_url, name, pool: establish_connection(&url, 4) };
+        let mut conn = PgConnection::establish(&url).expect("failed to connect to test database");
+        migrate(&mut conn);
+        Some(db)
+    }
+
+    fn threads(&self) -> ThreadRepository {
+        ThreadRepository::new(self.pool.clone())
+    }
+
+    fn messages(&self) -> MessageRepository {
+        MessageRepository::new(self.pool.clone())
+    }
+}
+
+impl Drop for TestDatabase {
+    fn drop(&mut self) {
+        self.pool.close();
+        if let Ok(mut admin) = PgConnection::establish(&self.admin_url) {
+            let _ = admin.batch_execute(&format!("DROP DATABASE IF EXISTS {} WITH (FORCE)", self.name));
+        }
+    }
+}
+
+/// same server and credentials, different database
+fn database_url(admin_url: &str, name: &str) -> String {
+    let (base, query) = match admin_url.split_once('?') {
+        Some((base, query)) => (base, format!("?{}", query)),
+        None => (admin_url, String::new()),
+    };
+    let server = base.rsplit_once('/').map(|(server, _)| server).unwrap_or(base);
+    format!("{}/{}{}", server, name, query)
+}
+
+/// every `up.sql`, oldest first, the way `diesel migration run` would
+fn migrate(conn: &mut PgConnection) {
+    let dir = Path::new(env!("CARGO_MANIFEST_DIR")).join("migrations");
+    let mut migrations = std::fs::read_dir(dir)
+        .expect("failed to read migrations")
+        .map(|entry| entry.expect("failed to read migration").path())
+        .filter(|path| path.is_dir())
+        .collect::<Vec<_>>();
+    migrations.sort();
+    for migration in migrations {
+        let sql = std::fs::read_to_string(migration.join("up.sql")).expect("failed to read up.sql");
+        conn.batch_execute(&sql)
+            .unwrap_or_else(|e| panic!("{} failed: {}", migration.display(), e));
+    }
+}
+
+fn thread(id: &str, owner: i64) -> Thread {
+    let now = Utc::now().naive_utc();
+    Thread { id: id.to_string(), created_at: Some(now), updated_at: Some(now), title: None, owner }
+}
+
+fn message(thread_id: &str, content: &str) -> NewMessage {
+    NewMessage {
+        thread_id: thread_id.to_string(),
+        content: Some(content.to_string()),
+        role: "user".to_string(),
+        active_model: "gpt-4o-mini".to_string(),
+        active_lab: "openai".to_string(),
+    }
+}
+
+#[tokio::test]
+async fn threads_are_only_visible_to_their_owner() {
+    let Some(db) = TestDatabase::create() else { return };
+    let threads = db.threads();
+
+    threads.create(thread("mine", OWNER)).await.unwrap();
+    threads.create(thread("theirs", STRANGER)).await.unwrap();
+
+    let listed = threads.list(OWNER).await.unwrap();
+    assert_eq!(listed.iter().map(|t| t.id.as_str()).collect::<Vec<_>>(), vec!["mine"]);
+    assert_eq!(threads.access("mine", OWNER).await.unwrap(), ThreadAccess::Owner);
+    assert_eq!(threads.access("theirs", OWNER).await.unwrap(), ThreadAccess::Forbidden);
+    assert_eq!(threads.access("nowhere", OWNER).await.unwrap(), ThreadAccess::Missing);
+
+    assert!(matches!(threads.rename("theirs", OWNER, "hijacked").await, Err(RepositoryError::NotFound(_))));
+    assert!(matches!(threads.delete("theirs", OWNER).await, Err(RepositoryError::NotFound(_))));
+    assert_eq!(threads.list(STRANGER).await.unwrap()[0].title, None);
+
+    threads.rename("mine", OWNER, "notes").await.unwrap();
+    assert_eq!(threads.list(OWNER).await.unwrap()[0].title.as_deref(), Some("notes"));
+}
+
+#[tokio::test]
+async fn messages_open_threads_only_when_asked() {
+    let Some(db) = TestDatabase::create() else { return };
+    let messages = db.messages();
+
+    // a reply can't start a thread
+    assert!(matches!(
+        messages.add(OWNER, message("fresh", "hi"), false).await,
+        Err(RepositoryError::NotFound(_))
+    ));
+    messages.add(OWNER, message("fresh", "hi"), true).await.unwrap();
+    messages.add(OWNER, message("fresh", "again"), false).await.unwrap();
+    assert_eq!(db.threads().access("fresh", OWNER).await.unwrap(), ThreadAccess::Owner);
+
+    // nor can anyone write into someone else's
+    assert!(matches!(
+        messages.add(STRANGER, message("fresh", "sneaky"), true).await,
+        Err(RepositoryError::NotFound(_))
+    ));
+
+    assert_eq!(messages.for_thread("fresh").await.unwrap().len(), 2);
+    assert_eq!(messages.for_owner(OWNER).await.unwrap().len(), 2);
+    assert!(messages.for_owner(STRANGER).await.unwrap().is_empty());
+}
+
+#[tokio::test]
+async fn search_and_delete_cover_messages() {
+    let Some(db) = TestDatabase::create() else { return };
+    let threads = db.threads();
+    let messages = db.messages();
+
+    messages.add(OWNER, message("recipes", "how long to boil an egg"), true).await.unwrap();
+    messages.add(OWNER, message("travel", "trains to lisbon"), true).await.unwrap();
+    messages.add(STRANGER, message("their-eggs", "egg salad"), true).await.unwrap();
+
+    let found = threads.search(OWNER, "egg").await.unwrap();
+    assert_eq!(found.iter().map(|t| t.id.as_str()).collect::<Vec<_>>(), vec!["recipes"]);
+
+    threads.delete("recipes", OWNER).await.unwrap();
+    assert!(threads.search(OWNER, "egg").await.unwrap().is_empty());
+    assert!(messages.for_thread("recipes").await.unwrap().is_empty());
+}
+
+#[tokio::test]
+async fn search_takes_wildcards_literally() {
+    let Some(db) = TestDatabase::create() else { return };
+    let threads = db.threads();
+    let messages = db.messages();
+
+    messages.add(OWNER, message("sale", "50% off"), true).await.unwrap();
+    messages.add(OWNER, message("plain", "500 off"), true).await.unwrap();
+
+    let found = threads.search(OWNER, "0%").await.unwrap();
+    assert_eq!(found.iter().map(|t| t.id.as_str()).collect::<Vec<_>>(), vec!["sale"]);
+    assert!(threads.search(OWNER, "5_0").await.unwrap().is_empty());
+}
+
+#[tokio::test]
+async fn share_links_expire_and_revoke() {
+    let Some(db) = TestDatabase::create() else { return };
+    let threads = db.threads();
+    db.messages().add(OWNER, message("shared", "look at this"), true).await.unwrap();
+
+    let now = Utc::now().naive_utc();
+    let share = |token: &str, thread_id: &str, expires_at| ThreadShare {
+        token: token.to_string(),
+        thread_id: thread_id.to_string(),
+        created_at: now,
+        expires_at,
+        revoked_at: None,
+    };
+
+    assert!(matches!(
+        threads.share(share("stolen", "shared", None), STRANGER).await,
+        Err(RepositoryError::NotFound(_))
+    ));
+    threads.share(share("open", "shared", None), OWNER).await.unwrap();
+    threads.share(share("brief", "shared", Some(now + Duration::hours(1))), OWNER).await.unwrap();
+
+    let (thread, shared_messages) = threads.shared("open", now).await.unwrap();
+    assert_eq!(thread.id, "shared");
+    assert_eq!(shared_messages[0].content.as_deref(), Some("look at this"));
+
+    assert!(threads.shared("brief", now).await.is_ok());
+    assert!(matches!(threads.shared("brief", now + Duration::hours(2)).await, Err(RepositoryError::NotFound(_))));
+
+    assert!(matches!(threads.revoke_share("open", STRANGER).await, Err(RepositoryError::NotFound(_))));
+    threads.revoke_share("open", OWNER).await.unwrap();
+    assert!(matches!(threads.shared("open", now).await, Err(RepositoryError::NotFound(_))));
+}